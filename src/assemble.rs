@@ -0,0 +1,359 @@
+//! Pick the best take of every chunk and join them into a rough cut of the whole script.
+
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+
+use crate::{
+    data::{self, Slicer, Take},
+    export::{self, ClipTrack, Timeline},
+    subtitles,
+    timecode::FrameRate,
+    timestamp::Timestamp,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum TieBreak {
+    /// Prefer the take recorded last.
+    #[default]
+    Latest,
+    Longest,
+    Shortest,
+}
+
+#[derive(Debug, Clone)]
+pub struct SelectionRules {
+    /// Marks from best to worst. Takes with marks that aren't listed rank below all of them.
+    pub mark_priority: Vec<String>,
+    pub tie_break: TieBreak,
+}
+
+impl Default for SelectionRules {
+    fn default() -> Self {
+        Self {
+            mark_priority: vec!["good".to_owned(), "ok".to_owned()],
+            tie_break: TieBreak::default(),
+        }
+    }
+}
+
+impl SelectionRules {
+    fn mark_rank(&self, take: &Take) -> usize {
+        self.mark_priority
+            .iter()
+            .position(|mark| mark.eq_ignore_ascii_case(&take.mark))
+            .unwrap_or(self.mark_priority.len())
+    }
+
+    /// Whether `a` should be picked over `b`.
+    fn is_better(&self, a: &Take, b: &Take) -> bool {
+        let (rank_a, rank_b) = (self.mark_rank(a), self.mark_rank(b));
+        if rank_a != rank_b {
+            return rank_a < rank_b;
+        }
+        let length = |take: &Take| take.end - take.start;
+        let order = match self.tie_break {
+            TieBreak::Latest => Ordering::Equal,
+            TieBreak::Longest => length(a).cmp(&length(b)),
+            TieBreak::Shortest => length(b).cmp(&length(a)),
+        };
+        // Whatever else ties goes to the later take, so the pick doesn't depend on the order the
+        // takes come in.
+        order.then_with(|| recording_order(a).cmp(&recording_order(b))) == Ordering::Greater
+    }
+}
+
+/// Orders takes by when they were recorded: by session, numerically where the ids are numbers,
+/// then by their position in the session.
+fn recording_order(take: &Take) -> (Option<u64>, &str, Timestamp, usize) {
+    (
+        take.session_id.parse().ok(),
+        &take.session_id,
+        take.start,
+        take.take_index,
+    )
+}
+
+/// Pick one take per chunk, returned in script order.
+pub fn select_best_takes<'a>(
+    takes: impl IntoIterator<Item = &'a Take>,
+    rules: &SelectionRules,
+) -> Vec<&'a Take> {
+    let mut best: BTreeMap<(Option<u64>, String), &Take> = BTreeMap::new();
+    for take in takes {
+        let key = (take.chunk_id.parse().ok(), take.chunk_id.clone());
+        match best.get(&key) {
+            Some(current) if !rules.is_better(take, current) => {}
+            _ => {
+                best.insert(key, take);
+            }
+        }
+    }
+    best.into_values().collect()
+}
+
+/// Build the timeline of the best takes, in script order.
+pub fn best_take_timeline(
+    slicer: &Slicer,
+    rules: &SelectionRules,
+    name: &str,
+    rate: FrameRate,
+) -> Timeline {
+    let selected = select_best_takes(slicer.takes.values().flatten(), rules);
+    let sessions = slicer.sessions.read().unwrap();
+
+    let mut timeline = Timeline::new(name, rate);
+    for take in selected {
+        let Some(session) = sessions.get(&take.session_id) else {
+            continue;
+        };
+        timeline.push(take.clone(), ClipTrack::for_take(take, session));
+    }
+    timeline
+}
+
+/// Build the ffmpeg arguments that cut every clip out of its sources and concatenates them.
+///
+/// Each clip uses its first video track for picture, and its first audio track for sound,
+/// falling back to the video's own audio. If any clip has no video, the rough cut is audio only.
+///
+/// If `burn_in` is given, those subtitles are burned into the joined video.
+pub fn concat_args(
+    timeline: &Timeline,
+    output: &Path,
+    burn_in: Option<&Path>,
+) -> anyhow::Result<Vec<String>> {
+    if timeline.clips.is_empty() {
+        anyhow::bail!("no takes to assemble");
+    }
+    let with_video = timeline
+        .clips
+        .iter()
+        .all(|clip| clip.tracks.iter().any(|t| !t.is_audio));
+    if !with_video {
+        warn!("some takes have no video, the rough cut will be audio only");
+    }
+
+    let mut args = vec![];
+    let mut filter = String::new();
+    let mut input = 0;
+    let mut add_input = |args: &mut Vec<String>, track: &ClipTrack| {
+        args.extend([
+            "-ss".to_owned(),
+            track.source_in.max(Timestamp::ZERO).to_string(),
+            "-to".to_owned(),
+            track.source_out.to_string(),
+            "-i".to_owned(),
+            track.file.to_string_lossy().into_owned(),
+        ]);
+        input += 1;
+        input - 1
+    };
+
+    for clip in &timeline.clips {
+        let video = clip.tracks.iter().find(|t| !t.is_audio);
+        let audio = clip.tracks.iter().find(|t| t.is_audio);
+
+        let video_input = video.map(|track| add_input(&mut args, track));
+        let audio_input = match audio {
+            Some(track) => add_input(&mut args, track),
+            None => video_input.ok_or(anyhow::anyhow!("{} has no tracks", clip.name))?,
+        };
+        if with_video {
+            filter.push_str(&format!("[{}:v:0]", video_input.unwrap()));
+        }
+        filter.push_str(&format!("[{}:a:0]", audio_input));
+    }
+
+    filter.push_str(&format!(
+        "concat=n={}:v={}:a=1",
+        timeline.clips.len(),
+        with_video as u8
+    ));
+    if with_video {
+        match burn_in {
+            Some(srt) => filter.push_str(&format!(
+                "[joined][a];[joined]{}[v]",
+                subtitles::burn_in_filter(srt)
+            )),
+            None => filter.push_str("[v][a]"),
+        }
+    } else {
+        filter.push_str("[a]");
+    }
+
+    args.extend(["-filter_complex".to_owned(), filter]);
+    if with_video {
+        args.extend(["-map".to_owned(), "[v]".to_owned()]);
+    }
+    args.extend(["-map".to_owned(), "[a]".to_owned()]);
+    args.push(output.to_string_lossy().into_owned());
+    Ok(args)
+}
+
+/// Write the rough cut of the best takes to `output`, and a matching CMX3600 EDL to `edl` and
+/// captions next to it.
+pub fn assemble(
+    slicer: &Slicer,
+    rules: &SelectionRules,
+    rate: FrameRate,
+    output: &Path,
+    edl: Option<PathBuf>,
+) -> anyhow::Result<Timeline> {
+    let name = output
+        .file_stem()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "rough cut".to_owned());
+    let timeline = best_take_timeline(slicer, rules, &name, rate);
+    info!(
+        "assembling {} takes into {}",
+        timeline.clips.len(),
+        output.display()
+    );
+    for clip in &timeline.clips {
+        debug!("chunk {}: {}", clip.take.chunk_id, clip.name);
+    }
+
+    let edl = edl.unwrap_or_else(|| output.with_extension("edl"));
+    export::ExportFormat::Cmx3600.write(&timeline, &edl)?;
+    info!("wrote rough cut EDL to {}", edl.display());
+
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    subtitles::write_captions(output, &subtitles::timeline_cues(&timeline))?;
+    let srt = output.with_extension("srt");
+    let burn_in = Some(srt.as_path()).filter(|_| slicer.options.profile.burn_in_subtitles);
+
+    let mut cmd = slicer.ffmpeg_command();
+    cmd.arg("-y").args(concat_args(&timeline, output, burn_in)?);
+    data::run_ffmpeg(cmd)?;
+
+    Ok(timeline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::tests::take;
+
+    fn marked(chunk: &str, take_index: usize, mark: &str, start_ms: i64, end_ms: i64) -> Take {
+        Take {
+            mark: mark.to_owned(),
+            ..take(chunk, take_index, start_ms, end_ms)
+        }
+    }
+
+    #[test]
+    fn best_mark_wins() {
+        let takes = [
+            marked("1", 0, "good", 0, 1000),
+            marked("1", 1, "bad", 2000, 3000),
+            marked("2", 0, "ok", 4000, 5000),
+            marked("2", 1, "good", 6000, 7000),
+        ];
+
+        let best = select_best_takes(&takes, &SelectionRules::default());
+
+        let picked: Vec<_> = best
+            .iter()
+            .map(|t| (t.chunk_id.as_str(), t.take_index))
+            .collect();
+        assert_eq!(picked, [("1", 0), ("2", 1)]);
+    }
+
+    #[test]
+    fn tie_breakers() {
+        let takes = [
+            marked("1", 0, "good", 0, 3000),
+            marked("1", 1, "good", 4000, 5000),
+            marked("1", 2, "good", 6000, 8000),
+        ];
+        let pick = |tie_break| {
+            let rules = SelectionRules {
+                tie_break,
+                ..Default::default()
+            };
+            select_best_takes(&takes, &rules)[0].take_index
+        };
+
+        assert_eq!(pick(TieBreak::Latest), 2);
+        assert_eq!(pick(TieBreak::Longest), 0);
+        assert_eq!(pick(TieBreak::Shortest), 1);
+    }
+
+    #[test]
+    fn ties_are_broken_by_session_order() {
+        let in_session = |session: &str, take_index| Take {
+            session_id: session.to_owned(),
+            ..marked("1", take_index, "good", 0, 1000)
+        };
+        let takes = [in_session("9", 0), in_session("10", 1), in_session("10", 2)];
+        let rules = |tie_break| SelectionRules {
+            tie_break,
+            ..Default::default()
+        };
+
+        for tie_break in [TieBreak::Latest, TieBreak::Longest] {
+            let forwards = select_best_takes(&takes, &rules(tie_break))[0];
+            let backwards = select_best_takes(takes.iter().rev(), &rules(tie_break))[0];
+            assert_eq!(
+                (forwards.session_id.as_str(), forwards.take_index),
+                ("10", 2)
+            );
+            assert_eq!(forwards.take_index, backwards.take_index);
+        }
+    }
+
+    #[test]
+    fn sources_are_not_cut_before_their_start() {
+        let mut timeline = crate::export::tests::timeline();
+        timeline.clips[0].tracks[1].source_in = Timestamp::ZERO - std::time::Duration::from_secs(1);
+
+        let args = concat_args(&timeline, Path::new("out.mp4"), None).unwrap();
+
+        assert_eq!(args[..2], ["-ss", "00:00:00.000"]);
+    }
+
+    #[test]
+    fn chunks_in_script_order() {
+        let takes = [
+            marked("10", 0, "good", 0, 1000),
+            marked("2", 0, "good", 0, 1000),
+        ];
+
+        let best = select_best_takes(&takes, &SelectionRules::default());
+
+        assert_eq!(best[0].chunk_id, "2");
+        assert_eq!(best[1].chunk_id, "10");
+    }
+
+    #[test]
+    fn concat_uses_video_and_separate_audio() {
+        let timeline = crate::export::tests::timeline();
+
+        let args = concat_args(&timeline, Path::new("out.mp4"), None).unwrap();
+
+        let filter = &args[args.iter().position(|a| a == "-filter_complex").unwrap() + 1];
+        assert_eq!(
+            filter,
+            "[0:v:0][1:a:0][2:v:0][3:a:0]concat=n=2:v=1:a=1[v][a]"
+        );
+        assert_eq!(
+            args[..6],
+            [
+                "-ss",
+                "00:00:11.000",
+                "-to",
+                "00:00:13.000",
+                "-i",
+                "/media/video.mp4"
+            ]
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Sample-accurate cut points for audio tracks.
+
+use std::{fs::File, path::Path, time::Duration};
+
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error,
+    formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+    units::Time,
+};
+
+use crate::timestamp::Timestamp;
+
+/// File extensions that are treated as audio-only tracks.
+pub const AUDIO_EXTENSIONS: &[&str] = &["wav", "mp3", "aac", "m4a", "flac"];
+
+pub fn is_audio_file(path: impl AsRef<Path>) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Convert a duration to a sample index, rounding to the nearest sample.
+pub fn duration_to_samples(duration: Duration, sample_rate: u32) -> u64 {
+    let nanos = duration.as_nanos() * sample_rate as u128;
+    ((nanos + 500_000_000) / 1_000_000_000) as u64
+}
+
+pub fn samples_to_duration(samples: u64, sample_rate: u32) -> Duration {
+    let nanos = samples as u128 * 1_000_000_000 / sample_rate as u128;
+    Duration::from_nanos(nanos as u64)
+}
+
+/// A half open range of samples, `[start, end)`, in a track with the given sample rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRange {
+    pub start: u64,
+    pub end: u64,
+    pub sample_rate: u32,
+}
+
+impl SampleRange {
+    pub fn from_timestamps(start: Timestamp, end: Timestamp, sample_rate: u32) -> Self {
+        let start = duration_to_samples(start.saturating_duration(), sample_rate);
+        let end = duration_to_samples(end.saturating_duration(), sample_rate);
+        Self {
+            start,
+            end: end.max(start),
+            sample_rate,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of samples a fade of `fade` spans, limited to half of the range so that the
+    /// fade in and fade out never overlap.
+    pub fn fade_samples(&self, fade: Duration) -> u64 {
+        duration_to_samples(fade, self.sample_rate).min(self.len() / 2)
+    }
+
+    /// Build an ffmpeg audio filter that trims exactly to this range, with optional fades.
+    pub fn ffmpeg_filter(&self, fade: Duration) -> String {
+        let mut filter = format!(
+            "atrim=start_sample={}:end_sample={},asetpts=PTS-STARTPTS",
+            self.start, self.end
+        );
+        let fade = self.fade_samples(fade);
+        if fade > 0 {
+            filter.push_str(&format!(
+                ",afade=t=in:ss=0:ns={fade},afade=t=out:ss={}:ns={fade}",
+                self.len() - fade
+            ));
+        }
+        filter
+    }
+}
+
+pub(crate) fn open_format(path: &Path) -> anyhow::Result<Box<dyn FormatReader>> {
+    let src = File::open(path)?;
+    let media_source = MediaSourceStream::new(Box::new(src), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension() {
+        hint.with_extension(ext.to_str().unwrap());
+    }
+
+    let meta_opts: MetadataOptions = Default::default();
+    let fmt_opts: FormatOptions = Default::default();
+
+    let probed =
+        symphonia::default::get_probe().format(&hint, media_source, &fmt_opts, &meta_opts)?;
+
+    Ok(probed.format)
+}
+
+/// Find the sample rate of the first audio track in a media file.
+pub fn probe_sample_rate(path: impl AsRef<Path>) -> anyhow::Result<u32> {
+    open_format(path.as_ref())?
+        .tracks()
+        .iter()
+        .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .find_map(|t| t.codec_params.sample_rate)
+        .ok_or(anyhow::anyhow!("no audio track with a sample rate found"))
+}
+
+/// Decode the first audio track of a media file between `start` and `end`, mixed down to mono.
+///
+/// Returns the samples and the sample rate.
+pub fn decode_mono(
+    path: impl AsRef<Path>,
+    start: Duration,
+    end: Duration,
+) -> anyhow::Result<(Vec<f32>, u32)> {
+    let mut format = open_format(path.as_ref())?;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or(anyhow::anyhow!("no supported audio format found"))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or(anyhow::anyhow!("audio track has no sample rate"))?;
+    let time_base = track.codec_params.time_base;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let start_sample = duration_to_samples(start, sample_rate);
+    let end_sample = duration_to_samples(end, sample_rate);
+    format.seek(
+        SeekMode::Accurate,
+        SeekTo::Time {
+            time: Time::new(start.as_secs(), start.subsec_nanos() as f64 / 1e9),
+            track_id: Some(track_id),
+        },
+    )?;
+
+    let mut samples = vec![];
+    let mut buf: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::ResetRequired) => continue,
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let packet_start = match time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(packet.ts());
+                duration_to_samples(
+                    Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac),
+                    sample_rate,
+                )
+            }
+            None => packet.ts(),
+        };
+        if packet_start >= end_sample {
+            break;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(Error::IoError(_)) | Err(Error::DecodeError(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let channels = decoded.spec().channels.count();
+        let buf = buf
+            .get_or_insert_with(|| SampleBuffer::new(decoded.capacity() as u64, *decoded.spec()));
+        if buf.capacity() < decoded.capacity() * channels {
+            *buf = SampleBuffer::new(decoded.capacity() as u64, *decoded.spec());
+        }
+        buf.copy_interleaved_ref(decoded);
+
+        for (i, frame) in buf.samples().chunks_exact(channels).enumerate() {
+            let pos = packet_start + i as u64;
+            if pos < start_sample {
+                continue;
+            }
+            if pos >= end_sample {
+                break;
+            }
+            samples.push(frame.iter().sum::<f32>() / channels as f32);
+        }
+    }
+
+    Ok((samples, sample_rate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_round_to_nearest() {
+        assert_eq!(duration_to_samples(Duration::from_millis(1), 48000), 48);
+        assert_eq!(duration_to_samples(Duration::from_millis(1), 44100), 44);
+        assert_eq!(
+            duration_to_samples(Duration::from_micros(11350), 44100),
+            501
+        );
+    }
+
+    #[test]
+    fn sample_range_from_timestamps() {
+        let range = SampleRange::from_timestamps(
+            Timestamp::from_millis(1500),
+            Timestamp::from_millis(2500),
+            48000,
+        );
+
+        assert_eq!(range.start, 72000);
+        assert_eq!(range.end, 120000);
+        assert_eq!(range.len(), 48000);
+    }
+
+    #[test]
+    fn fade_is_limited_to_half_the_range() {
+        let range = SampleRange {
+            start: 0,
+            end: 100,
+            sample_rate: 1000,
+        };
+
+        assert_eq!(range.fade_samples(Duration::from_millis(10)), 10);
+        assert_eq!(range.fade_samples(Duration::from_secs(1)), 50);
+    }
+
+    #[test]
+    fn ffmpeg_filter_with_fade() {
+        let range = SampleRange {
+            start: 48000,
+            end: 96000,
+            sample_rate: 48000,
+        };
+
+        assert_eq!(
+            range.ffmpeg_filter(Duration::ZERO),
+            "atrim=start_sample=48000:end_sample=96000,asetpts=PTS-STARTPTS"
+        );
+        assert_eq!(
+            range.ffmpeg_filter(Duration::from_millis(10)),
+            "atrim=start_sample=48000:end_sample=96000,asetpts=PTS-STARTPTS,afade=t=in:ss=0:ns=480,afade=t=out:ss=47520:ns=480"
+        );
+    }
+}
@@ -0,0 +1,764 @@
+//! Backends that do the actual cutting of slices, so slicing can be run without ffmpeg or
+//! somewhere else.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+    time::Duration,
+};
+
+use log::*;
+
+use crate::{
+    audio::{self, SampleRange},
+    channels::ChannelMix,
+    data::run_ffmpeg,
+    loudness::{self, LoudnessReport, LoudnessTarget},
+    sidecar::SliceMetadata,
+    subtitles,
+    timestamp::Timestamp,
+    wav::WavFile,
+};
+
+/// What mapped audio in video slices is encoded with, unless the profile says otherwise.
+const DEFAULT_AUDIO_ENCODER: &str = "aac";
+
+/// One slice to cut out of a track.
+#[derive(Debug, Clone)]
+pub struct CutJob {
+    pub source: PathBuf,
+    pub output: PathBuf,
+    /// Where to cut, in the source's own time.
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub kind: CutKind,
+    /// The audio channels to keep or mix, all of them as they are if not set.
+    pub channels: Option<ChannelMix>,
+    /// Written into the slice, as far as its format allows.
+    pub metadata: SliceMetadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CutKind {
+    /// Cut on exact sample boundaries, fading in and out over `fade`.
+    Audio {
+        /// Sample rate of the source, if it could be probed.
+        sample_rate: Option<u32>,
+        fade: Duration,
+    },
+    /// Cut video, copying the streams unless `transcode` is set.
+    Video {
+        transcode: bool,
+        /// Subtitles to burn into the picture, which means transcoding.
+        burn_in: Option<PathBuf>,
+        /// Encoder to transcode the video with, ffmpeg's default if not set.
+        video_encoder: Option<String>,
+        /// Encoder to transcode the audio with. The audio is copied if not set.
+        audio_encoder: Option<String>,
+        /// A proxy to make from the same decode.
+        proxy: Option<ProxyCut>,
+    },
+}
+
+/// A scaled down copy of a video slice, made alongside it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyCut {
+    pub output: PathBuf,
+    pub height: u32,
+    pub video_bitrate: String,
+    /// ffmpeg's default if not set.
+    pub video_encoder: Option<String>,
+    pub audio_encoder: Option<String>,
+}
+
+pub trait CutBackend: std::fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Whether this backend can cut `job` at all.
+    fn supports(&self, job: &CutJob) -> bool;
+
+    fn cut(&self, job: &CutJob) -> anyhow::Result<()>;
+
+    /// Normalize a slice in place.
+    fn normalize_loudness(
+        &self,
+        file: &Path,
+        target: LoudnessTarget,
+    ) -> anyhow::Result<LoudnessReport> {
+        let _ = target;
+        anyhow::bail!(
+            "the {} backend can't normalize loudness of {}",
+            self.name(),
+            file.display()
+        )
+    }
+}
+
+/// Runs ffmpeg for every cut.
+#[derive(Debug, Clone, Default)]
+pub struct FfmpegBackend {
+    /// Path to the ffmpeg binary. Uses `ffmpeg` from `PATH` if not set.
+    pub ffmpeg: Option<PathBuf>,
+}
+
+impl FfmpegBackend {
+    fn ffmpeg_command(&self) -> Command {
+        match &self.ffmpeg {
+            Some(path) => Command::new(path),
+            None => Command::new("ffmpeg"),
+        }
+    }
+
+    /// The ffmpeg invocation that cuts `job`.
+    pub fn command(&self, job: &CutJob) -> anyhow::Result<Command> {
+        let mut cmd = self.ffmpeg_command();
+        match &job.kind {
+            CutKind::Audio { sample_rate, fade } => {
+                let sample_rate = sample_rate
+                    .ok_or(anyhow::anyhow!("no audio track with a sample rate found"))?;
+                let range = SampleRange::from_timestamps(job.start, job.end, sample_rate);
+                // The input is seeked to a whole second at least a second before the slice, so
+                // what comes before isn't decoded, and trimmed from there to the exact sample.
+                let seek_secs = (range.start / sample_rate as u64).saturating_sub(1);
+                let seek = seek_secs * sample_rate as u64;
+                let range = SampleRange {
+                    start: range.start - seek,
+                    end: range.end - seek,
+                    ..range
+                };
+                let mut filter = range.ffmpeg_filter(*fade);
+                if let Some(mix) = &job.channels {
+                    filter.push(',');
+                    filter.push_str(&mix.pan_filter());
+                }
+                if seek_secs > 0 {
+                    cmd.arg("-ss")
+                        .arg(Timestamp::from_secs(seek_secs as i64).to_string());
+                }
+                cmd.arg("-i")
+                    .arg(job.source.as_os_str())
+                    .arg("-af")
+                    .arg(filter)
+                    .arg("-threads")
+                    .arg("1");
+                // Keeps the sample format of WAV sources, which would otherwise become 16-bit.
+                if is_wav(&job.source) && is_wav(&job.output) {
+                    if let Some(codec) = WavFile::open(&job.source)?.format.ffmpeg_codec() {
+                        cmd.args(["-c:a", codec]);
+                    }
+                }
+            }
+            CutKind::Video {
+                transcode,
+                burn_in,
+                video_encoder,
+                audio_encoder,
+                proxy,
+            } => {
+                // Filters see the timestamps of their input, and burnt in cues start at 0, so the
+                // input is seeked when burning in. Otherwise every output is cut on its own.
+                let mut cut = vec![];
+                if burn_in.is_some() {
+                    cmd.arg("-ss")
+                        .arg(job.start.to_string())
+                        .arg("-t")
+                        .arg((job.end - job.start).to_string());
+                } else {
+                    cut = vec![
+                        "-ss".to_owned(),
+                        job.start.to_string(),
+                        "-to".to_owned(),
+                        job.end.to_string(),
+                    ];
+                }
+                cmd.arg("-i")
+                    .arg(job.source.as_os_str())
+                    .args(&cut)
+                    .arg("-threads")
+                    .arg("1");
+                if let Some(srt) = burn_in {
+                    cmd.arg("-vf").arg(subtitles::burn_in_filter(srt));
+                }
+                let transcode = *transcode || burn_in.is_some();
+                if transcode {
+                    if let Some(encoder) = video_encoder {
+                        cmd.args(["-c:v", encoder]);
+                    }
+                } else if job.channels.is_some() {
+                    cmd.args(["-c:v", "copy"]);
+                } else {
+                    cmd.args(["-c", "copy"]);
+                }
+                // Mapped channels can't be stream copied.
+                if let Some(mix) = &job.channels {
+                    cmd.arg("-af").arg(mix.pan_filter()).args([
+                        "-c:a",
+                        audio_encoder.as_deref().unwrap_or(DEFAULT_AUDIO_ENCODER),
+                    ]);
+                } else if transcode {
+                    cmd.args(["-c:a", audio_encoder.as_deref().unwrap_or("copy")]);
+                }
+                if let Some(proxy) = proxy {
+                    // A second output of the same run, so the source is only decoded once. Output
+                    // options don't carry over, so the cut is given again.
+                    cmd.args(job.metadata.ffmpeg_args())
+                        .arg(&job.output)
+                        .args(&cut)
+                        .arg("-threads")
+                        .arg("1")
+                        .arg("-vf")
+                        .arg(proxy_filter(burn_in.as_deref(), proxy.height));
+                    if let Some(encoder) = &proxy.video_encoder {
+                        cmd.args(["-c:v", encoder]);
+                    }
+                    cmd.args(["-b:v", &proxy.video_bitrate]);
+                    if let Some(mix) = &job.channels {
+                        cmd.arg("-af").arg(mix.pan_filter());
+                    }
+                    if let Some(encoder) = &proxy.audio_encoder {
+                        cmd.args(["-c:a", encoder]);
+                    }
+                    cmd.args(job.metadata.ffmpeg_args()).arg(&proxy.output);
+                    return Ok(cmd);
+                }
+            }
+        }
+        cmd.args(job.metadata.ffmpeg_args()).arg(&job.output);
+        Ok(cmd)
+    }
+}
+
+impl CutBackend for FfmpegBackend {
+    fn name(&self) -> &'static str {
+        "ffmpeg"
+    }
+
+    fn supports(&self, _job: &CutJob) -> bool {
+        true
+    }
+
+    fn cut(&self, job: &CutJob) -> anyhow::Result<()> {
+        run_ffmpeg(self.command(job)?)?;
+        if matches!(job.kind, CutKind::Audio { .. }) && is_wav(&job.source) && is_wav(&job.output) {
+            let source = WavFile::open(&job.source)?;
+            let range = SampleRange::from_timestamps(job.start, job.end, source.format.sample_rate);
+            restore_wav_metadata(
+                &job.output,
+                &source,
+                range.start,
+                Some(&job.metadata.riff_info()),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Normalize with a two-pass `loudnorm`.
+    fn normalize_loudness(
+        &self,
+        file: &Path,
+        target: LoudnessTarget,
+    ) -> anyhow::Result<LoudnessReport> {
+        let mut cmd = self.ffmpeg_command();
+        cmd.arg("-hide_banner")
+            .arg("-i")
+            .arg(file)
+            .arg("-af")
+            .arg(target.measure_filter())
+            .args(["-f", "null", "-"]);
+        let out = run_ffmpeg(cmd)?;
+        let (measured, _) = loudness::parse_loudnorm_output(&String::from_utf8_lossy(&out.stderr))?;
+
+        // loudnorm upsamples to 192 kHz internally, so the original rate has to be restored.
+        let sample_rate = audio::probe_sample_rate(file)?;
+        let ext = file.extension().unwrap().to_str().unwrap();
+        let tmp_file = file.with_extension(format!("loudnorm.{ext}"));
+        let mut cmd = self.ffmpeg_command();
+        cmd.arg("-hide_banner")
+            .arg("-i")
+            .arg(file)
+            .arg("-af")
+            .arg(target.normalize_filter(&measured))
+            .arg("-ar")
+            .arg(sample_rate.to_string());
+        let wav = if is_wav(file) {
+            Some(WavFile::open(file)?)
+        } else {
+            None
+        };
+        if let Some(wav) = &wav {
+            if let Some(codec) = wav.format.ffmpeg_codec() {
+                cmd.args(["-c:a", codec]);
+            }
+        } else if !audio::is_audio_file(file) {
+            cmd.args(["-c:v", "copy"]);
+        }
+        cmd.arg("-y").arg(&tmp_file);
+        let out = run_ffmpeg(cmd)?;
+        let (_, normalized) =
+            loudness::parse_loudnorm_output(&String::from_utf8_lossy(&out.stderr))?;
+
+        if let Some(wav) = &wav {
+            restore_wav_metadata(&tmp_file, wav, 0, None)?;
+        }
+        std::fs::rename(&tmp_file, file)?;
+
+        Ok(LoudnessReport {
+            target,
+            measured,
+            normalized,
+        })
+    }
+}
+
+/// Cuts WAV audio by copying samples, without any external tools.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeBackend;
+
+impl CutBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    /// WAV audio, with channels picked but not mixed.
+    fn supports(&self, job: &CutJob) -> bool {
+        matches!(job.kind, CutKind::Audio { .. })
+            && is_wav(&job.source)
+            && job
+                .channels
+                .as_ref()
+                .is_none_or(|mix| mix.copied_channels().is_some())
+    }
+
+    fn cut(&self, job: &CutJob) -> anyhow::Result<()> {
+        let CutKind::Audio { fade, .. } = job.kind else {
+            anyhow::bail!("the native backend can only cut audio");
+        };
+        if !is_wav(&job.source) {
+            anyhow::bail!(
+                "the native backend can only cut WAV files, not {}",
+                job.source.display()
+            );
+        }
+
+        let mut wav = WavFile::open(&job.source)?;
+        wav.set_info(&job.metadata.riff_info());
+        let range = SampleRange::from_timestamps(job.start, job.end, wav.format.sample_rate);
+        if range.end > wav.frames() {
+            warn!(
+                "{} ends {} samples after the end of {}, the slice will be short",
+                job.output.display(),
+                range.end - wav.frames(),
+                job.source.display()
+            );
+        }
+        match &job.channels {
+            Some(mix) => {
+                let channels = mix.copied_channels().ok_or(anyhow::anyhow!(
+                    "the native backend can't mix channels of {}",
+                    job.source.display()
+                ))?;
+                wav.write_channels(&job.output, range, fade, &channels)?;
+            }
+            None => {
+                wav.write_slice(&job.output, range, fade)?;
+            }
+        }
+        trace!("{:?} is samples {}..{}", job.output, range.start, range.end);
+        Ok(())
+    }
+}
+
+/// Cuts WAV audio natively and everything else with ffmpeg. This is what a
+/// [`Slicer`](crate::Slicer) uses unless told otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct AutoBackend {
+    pub native: NativeBackend,
+    pub ffmpeg: FfmpegBackend,
+}
+
+impl AutoBackend {
+    fn pick(&self, job: &CutJob) -> &dyn CutBackend {
+        if self.native.supports(job) {
+            &self.native
+        } else {
+            &self.ffmpeg
+        }
+    }
+}
+
+impl CutBackend for AutoBackend {
+    fn name(&self) -> &'static str {
+        "auto"
+    }
+
+    fn supports(&self, _job: &CutJob) -> bool {
+        true
+    }
+
+    fn cut(&self, job: &CutJob) -> anyhow::Result<()> {
+        let backend = self.pick(job);
+        trace!("cutting {} with {}", job.output.display(), backend.name());
+        backend.cut(job)
+    }
+
+    fn normalize_loudness(
+        &self,
+        file: &Path,
+        target: LoudnessTarget,
+    ) -> anyhow::Result<LoudnessReport> {
+        self.ffmpeg.normalize_loudness(file, target)
+    }
+}
+
+/// Remembers every job and writes an empty output instead of cutting anything, for testing
+/// slicing logic.
+#[derive(Debug, Default)]
+pub struct RecordingBackend {
+    jobs: Mutex<Vec<CutJob>>,
+}
+
+impl RecordingBackend {
+    /// The jobs cut so far, in the order they came in.
+    pub fn jobs(&self) -> Vec<CutJob> {
+        self.jobs.lock().unwrap().clone()
+    }
+}
+
+impl CutBackend for RecordingBackend {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn supports(&self, _job: &CutJob) -> bool {
+        true
+    }
+
+    fn cut(&self, job: &CutJob) -> anyhow::Result<()> {
+        self.jobs.lock().unwrap().push(job.clone());
+        std::fs::write(&job.output, "")?;
+        Ok(())
+    }
+}
+
+/// Puts the BWF chunks and `INFO` tags of `source` back into `file`, a WAV that ffmpeg wrote from
+/// `frames` into `source` without them. `info` replaces the tags of `source`.
+fn restore_wav_metadata(
+    file: &Path,
+    source: &WavFile,
+    frames: u64,
+    info: Option<&[([u8; 4], String)]>,
+) -> anyhow::Result<()> {
+    let mut wav = WavFile::open(file)?;
+    wav.copy_metadata(source, frames);
+    if let Some(info) = info {
+        wav.set_info(info);
+    }
+    let range = SampleRange {
+        start: 0,
+        end: wav.frames(),
+        sample_rate: wav.format.sample_rate,
+    };
+    let tmp_file = file.with_extension("bwf.wav");
+    wav.write_slice(&tmp_file, range, Duration::ZERO)?;
+    std::fs::rename(&tmp_file, file)?;
+    Ok(())
+}
+
+/// Scales to `height`, keeping the aspect ratio with an even width, after burning in `burn_in`.
+fn proxy_filter(burn_in: Option<&Path>, height: u32) -> String {
+    let scale = format!("scale=-2:{height}");
+    match burn_in {
+        Some(srt) => format!("{},{scale}", subtitles::burn_in_filter(srt)),
+        None => scale,
+    }
+}
+
+pub(crate) fn is_wav(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("wav"))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        bwf::BwfInfo,
+        data::Track,
+        export::tests::take,
+        wav::tests::{write_test_bwf, write_test_wav},
+    };
+
+    /// A stand-in for ffmpeg in `dir` that writes `output` instead of cutting anything, dropping
+    /// all metadata like ffmpeg does, and reports the same loudness every time.
+    #[cfg(unix)]
+    pub(crate) fn fake_ffmpeg(dir: &Path, output: &Path) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let report = concat!(
+            r#"{"input_i": "-27.61", "input_tp": "-4.47", "input_lra": "18.06", "#,
+            r#""input_thresh": "-39.20", "output_i": "-16.58", "output_tp": "-1.50", "#,
+            r#""output_lra": "14.78", "output_thresh": "-27.71", "target_offset": "0.58"}"#,
+        );
+        let script = format!(
+            "#!/bin/sh\nfor last; do :; done\necho '{report}' >&2\n\
+             if [ \"$last\" != - ]; then cp '{}' \"$last\"; fi\n",
+            output.display()
+        );
+        let path = dir.join("ffmpeg");
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn job(source: &str, kind: CutKind) -> CutJob {
+        let take = take("1", 0, 1000, 2500);
+        let track = Track {
+            file: source.into(),
+            sync_offset: Timestamp::ZERO,
+        };
+        CutJob {
+            source: source.into(),
+            output: "out.mp4".into(),
+            start: take.start,
+            end: take.end,
+            kind,
+            channels: None,
+            metadata: SliceMetadata::new(&take, 0, &track),
+        }
+    }
+
+    fn args_of(cmd: &Command) -> Vec<String> {
+        cmd.get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn ffmpeg_commands() {
+        let backend = FfmpegBackend {
+            ffmpeg: Some("/opt/ffmpeg".into()),
+        };
+        let remux = CutKind::Video {
+            transcode: false,
+            burn_in: None,
+            video_encoder: Some("libx264".to_owned()),
+            audio_encoder: None,
+            proxy: None,
+        };
+        let cmd = backend.command(&job("video.mov", remux)).unwrap();
+        assert_eq!(cmd.get_program(), "/opt/ffmpeg");
+        let args = args_of(&cmd);
+        assert_eq!(
+            args[..6],
+            [
+                "-i",
+                "video.mov",
+                "-ss",
+                "00:00:01.000",
+                "-to",
+                "00:00:02.500"
+            ]
+        );
+        assert_eq!(args[8..10], ["-c", "copy"]);
+        assert_eq!(args.last().unwrap(), "out.mp4");
+
+        let burn_in = CutKind::Video {
+            transcode: false,
+            burn_in: Some("out.srt".into()),
+            video_encoder: Some("libx264".to_owned()),
+            audio_encoder: Some("aac".to_owned()),
+            proxy: None,
+        };
+        let args = args_of(&backend.command(&job("video.mov", burn_in)).unwrap());
+        // Seeking the input keeps the cues, which start at 0, in time with the slice.
+        assert_eq!(
+            args[..14],
+            [
+                "-ss",
+                "00:00:01.000",
+                "-t",
+                "00:00:01.500",
+                "-i",
+                "video.mov",
+                "-threads",
+                "1",
+                "-vf",
+                "subtitles=out.srt",
+                "-c:v",
+                "libx264",
+                "-c:a",
+                "aac"
+            ]
+        );
+
+        let audio = CutKind::Audio {
+            sample_rate: None,
+            fade: Duration::ZERO,
+        };
+        assert!(backend.command(&job("audio.mp3", audio)).is_err());
+    }
+
+    #[test]
+    fn mapped_channels_are_panned() {
+        let backend = FfmpegBackend::default();
+        let mix = ChannelMix {
+            channels: vec![vec![1, 2]],
+            description: vec!["FR+FC".to_owned()],
+            suffix: None,
+        };
+        let remux = CutKind::Video {
+            transcode: false,
+            burn_in: None,
+            video_encoder: None,
+            audio_encoder: None,
+            proxy: None,
+        };
+        let video = CutJob {
+            channels: Some(mix.clone()),
+            ..job("video.mov", remux)
+        };
+        let args = args_of(&backend.command(&video).unwrap());
+        assert_eq!(
+            args[8..14],
+            ["-c:v", "copy", "-af", "pan=mono|c0<c1+c2", "-c:a", "aac"]
+        );
+
+        let audio = CutKind::Audio {
+            sample_rate: Some(1000),
+            fade: Duration::ZERO,
+        };
+        let audio = CutJob {
+            channels: Some(mix),
+            ..job("audio.wav", audio)
+        };
+        let args = args_of(&backend.command(&audio).unwrap());
+        assert_eq!(
+            args[3],
+            "atrim=start_sample=1000:end_sample=2500,asetpts=PTS-STARTPTS,pan=mono|c0<c1+c2"
+        );
+        assert_eq!(AutoBackend::default().pick(&audio).name(), "ffmpeg");
+    }
+
+    #[test]
+    fn audio_is_seeked_and_keeps_its_sample_format() {
+        let tmp = crate::tests::temp_dir();
+        let source = tmp.path().join("audio.wav");
+        write_test_wav(&source, 1000, &[0; 8000]);
+        let audio = CutKind::Audio {
+            sample_rate: Some(1000),
+            fade: Duration::ZERO,
+        };
+        let job = CutJob {
+            output: "out.wav".into(),
+            start: Timestamp::from_millis(5500),
+            end: Timestamp::from_millis(6000),
+            ..job(source.to_str().unwrap(), audio)
+        };
+        let args = args_of(&FfmpegBackend::default().command(&job).unwrap());
+        assert_eq!(args[..2], ["-ss", "00:00:04.000"]);
+        assert_eq!(
+            args[5],
+            "atrim=start_sample=1500:end_sample=2000,asetpts=PTS-STARTPTS"
+        );
+        assert_eq!(args[8..10], ["-c:a", "pcm_s16le"]);
+    }
+
+    #[test]
+    fn proxy_is_a_second_output() {
+        let backend = FfmpegBackend::default();
+        let kind = CutKind::Video {
+            transcode: false,
+            burn_in: None,
+            video_encoder: None,
+            audio_encoder: None,
+            proxy: Some(ProxyCut {
+                output: "proxy/out.mp4".into(),
+                height: 540,
+                video_bitrate: "2M".to_owned(),
+                video_encoder: Some("libx264".to_owned()),
+                audio_encoder: Some("aac".to_owned()),
+            }),
+        };
+        let args = args_of(&backend.command(&job("video.mov", kind)).unwrap());
+        assert_eq!(args.iter().filter(|arg| *arg == "-i").count(), 1);
+        let full = args.iter().position(|arg| arg == "out.mp4").unwrap();
+        assert_eq!(args[8..10], ["-c", "copy"]);
+        assert_eq!(
+            args[full + 1..full + 15],
+            [
+                "-ss",
+                "00:00:01.000",
+                "-to",
+                "00:00:02.500",
+                "-threads",
+                "1",
+                "-vf",
+                "scale=-2:540",
+                "-c:v",
+                "libx264",
+                "-b:v",
+                "2M",
+                "-c:a",
+                "aac"
+            ]
+        );
+        assert_eq!(args.last().unwrap(), "proxy/out.mp4");
+    }
+
+    #[test]
+    fn auto_cuts_wav_natively() {
+        let audio = CutKind::Audio {
+            sample_rate: Some(48000),
+            fade: Duration::ZERO,
+        };
+        let video = CutKind::Video {
+            transcode: true,
+            burn_in: None,
+            video_encoder: None,
+            audio_encoder: None,
+            proxy: None,
+        };
+        let backend = AutoBackend::default();
+        assert_eq!(backend.pick(&job("a.WAV", audio.clone())).name(), "native");
+        assert_eq!(backend.pick(&job("a.mp3", audio)).name(), "ffmpeg");
+        assert_eq!(backend.pick(&job("a.wav", video)).name(), "ffmpeg");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn panned_wav_slices_keep_bwf_metadata() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let source = dir.join("audio.wav");
+        write_test_bwf(&source, 1000, &[0; 3000], 5000);
+        let stripped = dir.join("stripped.wav");
+        write_test_wav(&stripped, 1000, &[0; 1500]);
+        let backend = FfmpegBackend {
+            ffmpeg: Some(fake_ffmpeg(dir, &stripped)),
+        };
+        let audio = CutKind::Audio {
+            sample_rate: Some(1000),
+            fade: Duration::ZERO,
+        };
+        let job = CutJob {
+            source: source.clone(),
+            output: dir.join("out.wav"),
+            channels: Some(ChannelMix {
+                channels: vec![vec![0, 1]],
+                description: vec!["FL+FR".to_owned()],
+                suffix: None,
+            }),
+            ..job(source.to_str().unwrap(), audio)
+        };
+
+        backend.cut(&job).unwrap();
+        let wav = WavFile::open(&job.output).unwrap();
+        let info = BwfInfo::read(&wav);
+        assert_eq!(info.bext.unwrap().time_reference, 6000);
+        assert_eq!(info.ixml.unwrap().scene.as_deref(), Some("1A"));
+        assert!(wav.chunk(b"LIST").unwrap().starts_with(b"INFOINAM"));
+        assert_eq!(wav.frames(), 1500);
+    }
+}
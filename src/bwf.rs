@@ -0,0 +1,301 @@
+//! Broadcast WAV (`bext`) and iXML metadata, as written by field recorders.
+
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    timecode::{FrameRate, Timecode},
+    timestamp::Timestamp,
+    wav::WavFile,
+};
+
+const BEXT_TIME_REFERENCE: usize = 338;
+const BEXT_MIN_LEN: usize = 602;
+
+/// The fixed fields of a `bext` chunk that are worth reading. The rest is kept as is when
+/// slicing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bext {
+    pub description: String,
+    pub originator: String,
+    pub originator_reference: String,
+    pub origination_date: String,
+    pub origination_time: String,
+    /// Samples since midnight at the first sample of the file.
+    pub time_reference: u64,
+}
+
+impl Bext {
+    pub fn parse(body: &[u8]) -> anyhow::Result<Self> {
+        if body.len() < BEXT_MIN_LEN {
+            anyhow::bail!("bext chunk too short");
+        }
+        let text = |range: std::ops::Range<usize>| {
+            let field = &body[range];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).trim().to_owned()
+        };
+        Ok(Self {
+            description: text(0..256),
+            originator: text(256..288),
+            originator_reference: text(288..320),
+            origination_date: text(320..330),
+            origination_time: text(330..338),
+            time_reference: u64::from_le_bytes(
+                body[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8]
+                    .try_into()
+                    .unwrap(),
+            ),
+        })
+    }
+}
+
+/// A track of a polyphonic recording, from iXML.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IxmlTrack {
+    /// 1-based channel of the file the track is in.
+    pub channel_index: u16,
+    pub name: String,
+}
+
+/// The parts of an iXML chunk that name the recording.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ixml {
+    pub project: Option<String>,
+    pub scene: Option<String>,
+    pub take: Option<String>,
+    pub tape: Option<String>,
+    pub note: Option<String>,
+    pub circled: bool,
+    pub timecode_rate: Option<FrameRate>,
+    pub tracks: Vec<IxmlTrack>,
+}
+
+impl Ixml {
+    pub fn parse(body: &[u8]) -> Self {
+        let xml = String::from_utf8_lossy(body);
+        let xml = xml.trim_end_matches('\0');
+        let tag = |name| xml_tag(xml, name).filter(|value| !value.is_empty());
+
+        let timecode_rate = tag("TIMECODE_RATE").and_then(|rate| {
+            let rate: FrameRate = rate.parse().ok()?;
+            match tag("TIMECODE_FLAG").as_deref() {
+                Some("DF") => rate.drop_frame().ok(),
+                _ => Some(rate),
+            }
+        });
+        let tracks = xml_blocks(xml, "TRACK")
+            .filter_map(|track| {
+                Some(IxmlTrack {
+                    channel_index: xml_tag(track, "CHANNEL_INDEX")?.parse().ok()?,
+                    name: xml_tag(track, "NAME").unwrap_or_default(),
+                })
+            })
+            .collect();
+
+        Self {
+            project: tag("PROJECT"),
+            scene: tag("SCENE"),
+            take: tag("TAKE"),
+            tape: tag("TAPE"),
+            note: tag("NOTE"),
+            circled: tag("CIRCLED").is_some_and(|c| c.eq_ignore_ascii_case("true")),
+            timecode_rate,
+            tracks,
+        }
+    }
+}
+
+/// The contents of the first `<name>` element in `xml`, unescaped.
+fn xml_tag(xml: &str, name: &str) -> Option<String> {
+    let open = format!("<{name}>");
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&format!("</{name}>"))?;
+    Some(xml_unescape(xml[start..end].trim()))
+}
+
+/// The contents of every `<name>` element in `xml`.
+fn xml_blocks<'a>(xml: &'a str, name: &str) -> impl Iterator<Item = &'a str> {
+    let (open, close) = (format!("<{name}>"), format!("</{name}>"));
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = start + rest[start..].find(&close)?;
+        let block = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(block)
+    })
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Replace the contents of the first `<name>` element in `xml`, if there is one.
+fn replace_xml_tag(xml: &mut String, name: &str, value: &str) {
+    let open = format!("<{name}>");
+    let Some(start) = xml.find(&open).map(|i| i + open.len()) else {
+        return;
+    };
+    if let Some(len) = xml[start..].find(&format!("</{name}>")) {
+        xml.replace_range(start..start + len, value);
+    }
+}
+
+/// The BWF metadata of a WAV file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BwfInfo {
+    pub sample_rate: u32,
+    pub bext: Option<Bext>,
+    pub ixml: Option<Ixml>,
+}
+
+impl BwfInfo {
+    pub fn read(wav: &WavFile) -> Self {
+        Self {
+            sample_rate: wav.format.sample_rate,
+            bext: wav.chunk(b"bext").and_then(|body| Bext::parse(body).ok()),
+            ixml: wav.chunk(b"iXML").map(Ixml::parse),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bext.is_none() && self.ixml.is_none()
+    }
+
+    /// Time of day at the first sample of the file.
+    pub fn time_reference(&self) -> Option<Timestamp> {
+        let samples = self.bext.as_ref()?.time_reference;
+        Some(Timestamp::from_samples(
+            i64::try_from(samples).ok()?,
+            self.sample_rate,
+        ))
+    }
+
+    /// The time reference as timecode, at the rate the recorder ran at.
+    pub fn start_timecode(&self) -> Option<Timecode> {
+        let rate = self.ixml.as_ref()?.timecode_rate?;
+        Some(Timecode::from_timestamp(self.time_reference()?, rate))
+    }
+
+    /// Name of the channel with the given 1-based index.
+    pub fn track_name(&self, channel_index: u16) -> Option<&str> {
+        self.ixml
+            .as_ref()?
+            .tracks
+            .iter()
+            .find(|track| track.channel_index == channel_index)
+            .map(|track| track.name.as_str())
+    }
+}
+
+/// The body of a `bext` or `iXML` chunk for a slice starting `frames` into the file, with the
+/// time reference moved to the start of the slice. Other chunks are returned as is.
+pub fn shift_time_reference<'a>(id: &[u8; 4], body: &'a [u8], frames: u64) -> Cow<'a, [u8]> {
+    match id {
+        b"bext" if body.len() >= BEXT_MIN_LEN => {
+            let range = BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8;
+            let time_reference = u64::from_le_bytes(body[range.clone()].try_into().unwrap());
+            let mut body = body.to_vec();
+            body[range].copy_from_slice(&(time_reference + frames).to_le_bytes());
+            Cow::Owned(body)
+        }
+        b"iXML" => {
+            let mut xml = String::from_utf8_lossy(body)
+                .trim_end_matches('\0')
+                .to_owned();
+            let (Some(low), Some(high)) = (
+                xml_tag(&xml, "BWF_TIME_REFERENCE_LOW").and_then(|v| v.parse::<u64>().ok()),
+                xml_tag(&xml, "BWF_TIME_REFERENCE_HIGH").and_then(|v| v.parse::<u64>().ok()),
+            ) else {
+                return Cow::Borrowed(body);
+            };
+            let time_reference = (high << 32 | low) + frames;
+            replace_xml_tag(
+                &mut xml,
+                "BWF_TIME_REFERENCE_LOW",
+                &(time_reference & 0xffff_ffff).to_string(),
+            );
+            replace_xml_tag(
+                &mut xml,
+                "BWF_TIME_REFERENCE_HIGH",
+                &(time_reference >> 32).to_string(),
+            );
+            Cow::Owned(xml.into_bytes())
+        }
+        _ => Cow::Borrowed(body),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn bext_body(time_reference: u64) -> Vec<u8> {
+        let mut body = vec![0u8; BEXT_MIN_LEN];
+        body[..11].copy_from_slice(b"sc=1A;tk=3;");
+        body[256..262].copy_from_slice(b"MixPre");
+        body[BEXT_TIME_REFERENCE..BEXT_TIME_REFERENCE + 8]
+            .copy_from_slice(&time_reference.to_le_bytes());
+        body
+    }
+
+    pub(crate) const IXML: &str = "<?xml version=\"1.0\"?><BWFXML>\
+        <PROJECT>Doc</PROJECT><SCENE>1A</SCENE><TAKE>3</TAKE><CIRCLED>TRUE</CIRCLED>\
+        <NOTE>plane &amp; wind</NOTE>\
+        <SPEED><TIMECODE_RATE>30000/1001</TIMECODE_RATE><TIMECODE_FLAG>NDF</TIMECODE_FLAG></SPEED>\
+        <BEXT><BWF_TIME_REFERENCE_LOW>48000</BWF_TIME_REFERENCE_LOW>\
+        <BWF_TIME_REFERENCE_HIGH>0</BWF_TIME_REFERENCE_HIGH></BEXT>\
+        <TRACK_LIST><TRACK_COUNT>2</TRACK_COUNT>\
+        <TRACK><CHANNEL_INDEX>1</CHANNEL_INDEX><NAME>Boom</NAME></TRACK>\
+        <TRACK><CHANNEL_INDEX>2</CHANNEL_INDEX><NAME>Lav</NAME></TRACK>\
+        </TRACK_LIST></BWFXML>";
+
+    #[test]
+    fn parses_bext_and_ixml() {
+        let bext = Bext::parse(&bext_body(48000 * 3600)).unwrap();
+        assert_eq!(bext.description, "sc=1A;tk=3;");
+        assert_eq!(bext.originator, "MixPre");
+
+        let ixml = Ixml::parse(IXML.as_bytes());
+        assert_eq!(ixml.scene.as_deref(), Some("1A"));
+        assert_eq!(ixml.take.as_deref(), Some("3"));
+        assert_eq!(ixml.note.as_deref(), Some("plane & wind"));
+        assert!(ixml.circled);
+        assert_eq!(ixml.timecode_rate, Some(FrameRate::FPS_29_97));
+
+        let info = BwfInfo {
+            sample_rate: 48000,
+            bext: Some(bext),
+            ixml: Some(ixml),
+        };
+        assert_eq!(
+            info.time_reference(),
+            Some(Timestamp::from_samples(48000 * 3600, 48000))
+        );
+        assert_eq!(info.track_name(2), Some("Lav"));
+    }
+
+    #[test]
+    fn time_reference_moves_to_slice_start() {
+        let body = bext_body(1000);
+        let bext = shift_time_reference(b"bext", &body, 500);
+        assert_eq!(Bext::parse(&bext).unwrap().time_reference, 1500);
+
+        let ixml = shift_time_reference(b"iXML", IXML.as_bytes(), (1 << 32) + 2000);
+        let ixml = String::from_utf8(ixml.into_owned()).unwrap();
+        assert!(ixml.contains("<BWF_TIME_REFERENCE_LOW>50000</BWF_TIME_REFERENCE_LOW>"));
+        assert!(ixml.contains("<BWF_TIME_REFERENCE_HIGH>1</BWF_TIME_REFERENCE_HIGH>"));
+
+        let data = [1, 2, 3];
+        assert!(matches!(
+            shift_time_reference(b"LIST", &data, 10),
+            Cow::Borrowed(_)
+        ));
+    }
+}
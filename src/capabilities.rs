@@ -0,0 +1,261 @@
+//! What the local ffmpeg can do, so output profiles can be checked against it before anything is
+//! sliced, rather than failing on every take.
+
+use std::{collections::BTreeSet, process::Command};
+
+use crate::{data::run_ffmpeg, profile::OutputProfile};
+
+/// Encoders to try when an encoder isn't available, in order. A fallback's own fallbacks are
+/// tried after it, so `hevc_nvenc` falls back to `libx265` and then to `libx264`.
+pub const ENCODER_FALLBACKS: &[(&str, &[&str])] = &[
+    ("hevc_nvenc", &["libx265"]),
+    ("hevc_qsv", &["libx265"]),
+    ("hevc_vaapi", &["libx265"]),
+    ("hevc_videotoolbox", &["libx265"]),
+    ("libx265", &["libx264"]),
+    ("h264_nvenc", &["libx264"]),
+    ("h264_qsv", &["libx264"]),
+    ("h264_vaapi", &["libx264"]),
+    ("h264_videotoolbox", &["libx264"]),
+    ("libx264", &["libopenh264", "mpeg4"]),
+    ("libvpx-vp9", &["libvpx"]),
+    ("libfdk_aac", &["aac"]),
+    ("libopus", &["opus"]),
+    ("libmp3lame", &["mp3"]),
+];
+
+/// Filters the slicer uses for some profile features.
+const LOUDNESS_FILTER: &str = "loudnorm";
+const SUBTITLES_FILTER: &str = "subtitles";
+const SCALE_FILTER: &str = "scale";
+const PAN_FILTER: &str = "pan";
+
+/// The encoders and filters an ffmpeg build has, from `ffmpeg -encoders` and `ffmpeg -filters`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FfmpegCapabilities {
+    pub encoders: BTreeSet<String>,
+    pub filters: BTreeSet<String>,
+}
+
+impl FfmpegCapabilities {
+    /// Ask ffmpeg. `ffmpeg` makes a new command for the binary to ask.
+    pub fn query(ffmpeg: impl Fn() -> Command) -> anyhow::Result<Self> {
+        let list = |what: &str| -> anyhow::Result<String> {
+            let mut cmd = ffmpeg();
+            cmd.args(["-hide_banner", what]);
+            let out = run_ffmpeg(cmd)?;
+            Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+        };
+        Ok(Self {
+            encoders: parse_encoders(&list("-encoders")?),
+            filters: parse_filters(&list("-filters")?),
+        })
+    }
+
+    /// Check that `profile` can be used, picking the first of its encoders or their fallbacks
+    /// that ffmpeg has. The returned profile lists every usable encoder, the picked one first, so
+    /// a worker with another ffmpeg build can negotiate again. The error lists everything that is
+    /// missing.
+    pub fn negotiate(&self, profile: &OutputProfile) -> anyhow::Result<OutputProfile> {
+        let mut missing = vec![];
+        let mut profile = profile.clone();
+        let mut encoder_lists = vec![
+            ("video", &mut profile.video_encoders),
+            ("audio", &mut profile.audio_encoders),
+        ];
+        if let Some(proxy) = &mut profile.proxy {
+            encoder_lists.push(("proxy video", &mut proxy.video_encoders));
+            encoder_lists.push(("proxy audio", &mut proxy.audio_encoders));
+        }
+        for (kind, encoders) in encoder_lists {
+            if encoders.is_empty() {
+                continue;
+            }
+            let candidates = with_fallbacks(encoders);
+            let usable: Vec<_> = candidates
+                .iter()
+                .filter(|encoder| self.encoders.contains(*encoder))
+                .cloned()
+                .collect();
+            if usable.is_empty() {
+                missing.push(format!(
+                    "no {kind} encoder out of {}",
+                    candidates.join(", ")
+                ));
+            }
+            *encoders = usable;
+        }
+        if profile.loudness.is_some() && !self.filters.contains(LOUDNESS_FILTER) {
+            missing.push(format!(
+                "no {LOUDNESS_FILTER} filter for loudness normalization"
+            ));
+        }
+        if profile.burn_in_subtitles && !self.filters.contains(SUBTITLES_FILTER) {
+            missing.push(format!(
+                "no {SUBTITLES_FILTER} filter for burning in subtitles, ffmpeg needs libass"
+            ));
+        }
+        if profile.proxy.is_some() && !self.filters.contains(SCALE_FILTER) {
+            missing.push(format!("no {SCALE_FILTER} filter for making proxies"));
+        }
+        if !profile.channel_maps.is_empty() && !self.filters.contains(PAN_FILTER) {
+            missing.push(format!("no {PAN_FILTER} filter for mapping channels"));
+        }
+
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "output profile {} can't be used with this ffmpeg: {}",
+                profile.name,
+                missing.join("; ")
+            );
+        }
+        Ok(profile)
+    }
+}
+
+/// `encoders` in order, each followed by its fallbacks, without repeats.
+pub fn with_fallbacks(encoders: &[String]) -> Vec<String> {
+    fn push(encoder: &str, out: &mut Vec<String>) {
+        if out.iter().any(|e| e == encoder) {
+            return;
+        }
+        out.push(encoder.to_owned());
+        let fallbacks = ENCODER_FALLBACKS
+            .iter()
+            .find(|(name, _)| *name == encoder)
+            .map(|(_, fallbacks)| *fallbacks)
+            .unwrap_or_default();
+        for fallback in fallbacks {
+            push(fallback, out);
+        }
+    }
+
+    let mut out = vec![];
+    for encoder in encoders {
+        push(encoder, &mut out);
+    }
+    out
+}
+
+/// Names of the encoders in `ffmpeg -encoders` output. They come after a `------` line, each
+/// after a column of flags.
+fn parse_encoders(text: &str) -> BTreeSet<String> {
+    text.lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Names of the filters in `ffmpeg -filters` output. The legend at the top has a `=` where a
+/// filter has its name.
+fn parse_filters(text: &str) -> BTreeSet<String> {
+    text.lines()
+        .skip_while(|line| !line.starts_with("Filters:"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_flags, name, _io) = (fields.next()?, fields.next()?, fields.next()?);
+            (name != "=").then(|| name.to_owned())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loudness::LoudnessTarget;
+
+    const ENCODERS: &str = "Encoders:
+ V..... = Video
+ A..... = Audio
+ S..... = Subtitle
+ .F.... = Frame-level multithreading
+ ------
+ V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC (codec h264)
+ V....D mpeg4                MPEG-4 part 2
+ A....D aac                  AAC (Advanced Audio Coding)
+ A....D pcm_s16le            PCM signed 16-bit little-endian
+";
+
+    const FILTERS: &str = "Filters:
+  T.. = Timeline support
+  .S. = Slice threading
+  A = Audio input/output
+  | = Source or sink filter
+ ... afade             A->A       Fade in/out input audio.
+ ... loudnorm          A->A       EBU R128 loudness normalization
+ T.. atrim             A->A       Pick one continuous section from the input, drop the rest.
+";
+
+    fn capabilities() -> FfmpegCapabilities {
+        FfmpegCapabilities {
+            encoders: parse_encoders(ENCODERS),
+            filters: parse_filters(FILTERS),
+        }
+    }
+
+    #[test]
+    fn parses_ffmpeg_lists() {
+        let capabilities = capabilities();
+        assert_eq!(
+            capabilities.encoders.iter().collect::<Vec<_>>(),
+            ["aac", "libx264", "mpeg4", "pcm_s16le"]
+        );
+        assert_eq!(
+            capabilities.filters.iter().collect::<Vec<_>>(),
+            ["afade", "atrim", "loudnorm"]
+        );
+    }
+
+    #[test]
+    fn fallbacks_are_picked_in_order() {
+        assert_eq!(
+            with_fallbacks(&["hevc_nvenc".to_owned(), "libx264".to_owned()]),
+            ["hevc_nvenc", "libx265", "libx264", "libopenh264", "mpeg4"]
+        );
+
+        let profile = OutputProfile {
+            name: "hevc".to_owned(),
+            video_encoders: vec!["libx265".to_owned()],
+            audio_encoders: vec!["libfdk_aac".to_owned()],
+            loudness: Some(LoudnessTarget::PODCAST),
+            ..Default::default()
+        };
+        let negotiated = capabilities().negotiate(&profile).unwrap();
+        assert_eq!(negotiated.video_encoders, ["libx264", "mpeg4"]);
+        assert_eq!(negotiated.audio_encoders, ["aac"]);
+    }
+
+    #[test]
+    fn everything_missing_is_reported() {
+        let profile = OutputProfile {
+            name: "vp9".to_owned(),
+            video_encoders: vec!["libvpx-vp9".to_owned()],
+            burn_in_subtitles: true,
+            ..Default::default()
+        };
+        let error = capabilities().negotiate(&profile).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "output profile vp9 can't be used with this ffmpeg: \
+             no video encoder out of libvpx-vp9, libvpx; \
+             no subtitles filter for burning in subtitles, ffmpeg needs libass"
+        );
+    }
+
+    #[test]
+    fn channel_maps_need_pan() {
+        let mut slicer = crate::Slicer::new();
+        slicer.options.profile.channel_maps =
+            vec![serde_json::from_str(r#"{ "mode": "downmix" }"#).unwrap()];
+        assert!(slicer.options.profile.needs_ffmpeg_features());
+        slicer.capabilities.set(capabilities()).unwrap();
+        let error = slicer.negotiate_profile().unwrap_err().to_string();
+        assert!(
+            error.ends_with("no pan filter for mapping channels"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! Picking, reordering, downmixing and splitting the audio channels of a track while slicing.
+//!
+//! Camera files often carry several microphones as channels of one stream, eg. scratch, lav and
+//! boom. A [`ChannelMap`] in the output profile says which of them end up in the slices. It is
+//! resolved against the [`ChannelLayout`] symphonia reads from the track, so a map naming channels
+//! a track doesn't have fails when planning rather than giving silent slices.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use symphonia::core::{audio::Channels, codecs::CODEC_TYPE_NULL};
+
+use crate::audio;
+
+/// Names ffmpeg uses for the channel positions, in the order of symphonia's channel bits.
+const POSITION_NAMES: &[Option<&str>] = &[
+    Some("FL"),
+    Some("FR"),
+    Some("FC"),
+    Some("LFE"),
+    Some("BL"),
+    Some("BR"),
+    Some("FLC"),
+    Some("FRC"),
+    Some("BC"),
+    Some("SL"),
+    Some("SR"),
+    Some("TC"),
+    Some("TFL"),
+    Some("TFC"),
+    Some("TFR"),
+    Some("TBL"),
+    Some("TBC"),
+    Some("TBR"),
+    None,
+    None,
+    Some("WL"),
+    Some("WR"),
+    None,
+    None,
+    None,
+    Some("LFE2"),
+];
+
+/// A channel of a track, by its index from 0, or by its name in the track's layout, eg. `FR`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Channel {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "lowercase")]
+pub enum ChannelMapping {
+    /// Keep these channels, in this order.
+    Pick { channels: Vec<Channel> },
+    /// Mix these channels down to mono, all of them if none are given.
+    Downmix {
+        #[serde(default)]
+        channels: Vec<Channel>,
+    },
+    /// Write each of these channels to a mono file of its own, all of them if none are given.
+    /// Split channels of video tracks are extracted to WAV files.
+    Split {
+        #[serde(default)]
+        channels: Vec<Channel>,
+        /// Used in the file names instead of the channel numbers, eg. `lav`.
+        #[serde(default)]
+        names: Vec<String>,
+    },
+}
+
+/// How to map the channels of a track, as given in the output profile.
+///
+/// ```json
+/// { "track": 1, "mode": "split", "channels": [1, 2], "names": ["lav", "boom"] }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelMap {
+    /// Index of the session track to map. Applies to every track with audio if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub track: Option<usize>,
+    #[serde(flatten)]
+    pub mapping: ChannelMapping,
+}
+
+impl ChannelMap {
+    /// Work out the files to make from a track with `layout`, one for each mix.
+    pub fn resolve(&self, layout: &ChannelLayout) -> anyhow::Result<Vec<ChannelMix>> {
+        let indices = |channels: &[Channel]| -> anyhow::Result<Vec<usize>> {
+            if channels.is_empty() {
+                return Ok((0..layout.len()).collect());
+            }
+            channels.iter().map(|c| layout.index(c)).collect()
+        };
+        Ok(match &self.mapping {
+            ChannelMapping::Pick { channels } => {
+                if channels.is_empty() {
+                    anyhow::bail!("no channels to pick");
+                }
+                let channels = indices(channels)?;
+                vec![ChannelMix {
+                    description: channels.iter().map(|&c| layout.name(c)).collect(),
+                    channels: channels.into_iter().map(|c| vec![c]).collect(),
+                    suffix: None,
+                }]
+            }
+            ChannelMapping::Downmix { channels } => {
+                let channels = indices(channels)?;
+                let names: Vec<_> = channels.iter().map(|&c| layout.name(c)).collect();
+                vec![ChannelMix {
+                    channels: vec![channels],
+                    description: vec![names.join("+")],
+                    suffix: None,
+                }]
+            }
+            ChannelMapping::Split { channels, names } => {
+                let channels = indices(channels)?;
+                if names.len() > channels.len() {
+                    anyhow::bail!(
+                        "{} names given for {} split channels",
+                        names.len(),
+                        channels.len()
+                    );
+                }
+                channels
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &c)| ChannelMix {
+                        channels: vec![vec![c]],
+                        description: vec![layout.name(c)],
+                        suffix: Some(
+                            names
+                                .get(i)
+                                .cloned()
+                                .unwrap_or_else(|| format!("ch{}", c + 1)),
+                        ),
+                    })
+                    .collect()
+            }
+        })
+    }
+}
+
+/// The channels of one output, worked out from a [`ChannelMap`] and a track's layout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelMix {
+    /// For every output channel, the source channels mixed into it at equal gain.
+    pub channels: Vec<Vec<usize>>,
+    /// For every output channel, the names of its source channels, eg. `FL+FR`.
+    pub description: Vec<String>,
+    /// Added to the slice's file name, for split channels.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+}
+
+impl ChannelMix {
+    /// The source channel of every output channel, if none are mixed, so the samples can be
+    /// copied as they are.
+    pub fn copied_channels(&self) -> Option<Vec<usize>> {
+        self.channels
+            .iter()
+            .map(|sources| match sources[..] {
+                [source] => Some(source),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// An ffmpeg `pan` filter making this mix.
+    pub fn pan_filter(&self) -> String {
+        let layout = match self.channels.len() {
+            1 => "mono".to_owned(),
+            2 => "stereo".to_owned(),
+            n => format!("{n}c"),
+        };
+        let mut filter = format!("pan={layout}");
+        for (out, sources) in self.channels.iter().enumerate() {
+            let sources: Vec<_> = sources.iter().map(|c| format!("c{c}")).collect();
+            // `<` scales the gains so the mix can't clip.
+            let op = if sources.len() == 1 { '=' } else { '<' };
+            filter.push_str(&format!("|c{out}{op}{}", sources.join("+")));
+        }
+        filter
+    }
+}
+
+/// The channels of the first audio stream of a file, named as ffmpeg names them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelLayout {
+    pub names: Vec<String>,
+}
+
+impl ChannelLayout {
+    /// Read the layout from symphonia's `codec_params` for the first audio track of `path`.
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let format = audio::open_format(path)?;
+        let channels = format
+            .tracks()
+            .iter()
+            .filter(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .find_map(|t| t.codec_params.channels)
+            .ok_or(anyhow::anyhow!("no audio track with known channels found"))?;
+        Ok(Self::from_channels(channels))
+    }
+
+    pub fn from_channels(channels: Channels) -> Self {
+        let names = channels
+            .iter()
+            .map(|channel| {
+                let bit = channel.bits().trailing_zeros() as usize;
+                match POSITION_NAMES.get(bit).copied().flatten() {
+                    Some(name) => name.to_owned(),
+                    None => format!("c{bit}"),
+                }
+            })
+            .collect();
+        Self { names }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    fn name(&self, index: usize) -> String {
+        self.names[index].clone()
+    }
+
+    fn index(&self, channel: &Channel) -> anyhow::Result<usize> {
+        match channel {
+            Channel::Index(index) if *index < self.len() => Ok(*index),
+            Channel::Name(name) => self
+                .names
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow::anyhow!("no {name} channel in {}", self.names.join(", "))),
+            Channel::Index(index) => anyhow::bail!(
+                "no channel {index}, there are only {} ({})",
+                self.len(),
+                self.names.join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad() -> ChannelLayout {
+        ChannelLayout::from_channels(
+            Channels::FRONT_LEFT | Channels::FRONT_RIGHT | Channels::FRONT_CENTRE | Channels::LFE1,
+        )
+    }
+
+    fn map(json: &str) -> ChannelMap {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn layout_names() {
+        assert_eq!(quad().names, ["FL", "FR", "FC", "LFE"]);
+        assert_eq!(
+            ChannelLayout::from_channels(Channels::REAR_LEFT_CENTRE).names,
+            ["c18"]
+        );
+    }
+
+    #[test]
+    fn resolve_mappings() {
+        let pick = map(r#"{ "mode": "pick", "channels": [2, "fl"] }"#);
+        let mixes = pick.resolve(&quad()).unwrap();
+        assert_eq!(mixes[0].channels, [vec![2], vec![0]]);
+        assert_eq!(mixes[0].description, ["FC", "FL"]);
+        assert_eq!(mixes[0].copied_channels(), Some(vec![2, 0]));
+        assert_eq!(mixes[0].pan_filter(), "pan=stereo|c0=c2|c1=c0");
+
+        let downmix = map(r#"{ "track": 1, "mode": "downmix", "channels": [0, 1] }"#);
+        assert_eq!(downmix.track, Some(1));
+        let mixes = downmix.resolve(&quad()).unwrap();
+        assert_eq!(mixes[0].description, ["FL+FR"]);
+        assert_eq!(mixes[0].copied_channels(), None);
+        assert_eq!(mixes[0].pan_filter(), "pan=mono|c0<c0+c1");
+
+        let split = map(r#"{ "mode": "split", "channels": [1, 2, 3], "names": ["lav", "boom"] }"#);
+        let mixes = split.resolve(&quad()).unwrap();
+        let suffixes: Vec<_> = mixes.iter().filter_map(|m| m.suffix.as_deref()).collect();
+        assert_eq!(suffixes, ["lav", "boom", "ch4"]);
+        assert_eq!(mixes[2].pan_filter(), "pan=mono|c0=c3");
+    }
+
+    #[test]
+    fn missing_channels_are_errors() {
+        let error = map(r#"{ "mode": "pick", "channels": [4] }"#)
+            .resolve(&quad())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no channel 4, there are only 4 (FL, FR, FC, LFE)"
+        );
+        assert!(map(r#"{ "mode": "downmix", "channels": ["BL"] }"#)
+            .resolve(&quad())
+            .is_err());
+    }
+}
@@ -0,0 +1,588 @@
+//! Lint a sessions directory without slicing anything, reporting every problem with where it is.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use log::*;
+use serde::Serialize;
+
+use crate::{
+    data::Take,
+    import::ImporterRegistry,
+    session::{SessionMeta, SessionTake, AUDIO_WAV, META_JSON, TAKES_CSV},
+    timestamp::Timestamp,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProblemKind {
+    MissingFile,
+    Unreadable,
+    UnrecognizedEntry,
+    InvalidMetadata,
+    InvalidTake,
+    EmptyTake,
+    OverlappingTakes,
+    UnsortedTakes,
+    DuplicateTakeIndex,
+    UnknownMark,
+}
+
+impl ProblemKind {
+    pub fn severity(self) -> Severity {
+        match self {
+            ProblemKind::MissingFile
+            | ProblemKind::Unreadable
+            | ProblemKind::InvalidMetadata
+            | ProblemKind::InvalidTake
+            | ProblemKind::EmptyTake => Severity::Error,
+            ProblemKind::UnrecognizedEntry
+            | ProblemKind::OverlappingTakes
+            | ProblemKind::UnsortedTakes
+            | ProblemKind::DuplicateTakeIndex
+            | ProblemKind::UnknownMark => Severity::Warning,
+        }
+    }
+}
+
+/// One problem found in a sessions directory. Lines and columns start at 1.
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    pub severity: Severity,
+    pub kind: ProblemKind,
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<u64>,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(kind: ProblemKind, file: &Path, message: impl Into<String>) -> Self {
+        Self {
+            severity: kind.severity(),
+            kind,
+            file: file.to_owned(),
+            line: None,
+            column: None,
+            message: message.into(),
+        }
+    }
+
+    fn at(mut self, line: Option<u64>, column: Option<u64>) -> Self {
+        self.line = line;
+        self.column = column;
+        self
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+            if let Some(column) = self.column {
+                write!(f, ":{column}")?;
+            }
+        }
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, ": {severity}: {}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ReportFormat {
+    /// One `file:line:column: severity: message` line per problem.
+    #[default]
+    Text,
+    /// A JSON array of problems.
+    Json,
+}
+
+pub fn write_report(
+    problems: &[Problem],
+    format: ReportFormat,
+    out: &mut impl std::io::Write,
+) -> anyhow::Result<()> {
+    match format {
+        ReportFormat::Text => {
+            for problem in problems {
+                writeln!(out, "{problem}")?;
+            }
+        }
+        ReportFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, problems)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+/// A take, and the line of the file it came from, if known.
+struct LocatedTake {
+    take: Take,
+    line: Option<u64>,
+}
+
+/// Check a sessions directory and write the problems found to `out`. Returns whether the check
+/// passed, which is when nothing is wrong, or only warnings are when `deny_warnings` isn't set.
+pub fn check_and_report(
+    dir: &Path,
+    importers: &ImporterRegistry,
+    known_marks: &[String],
+    format: ReportFormat,
+    deny_warnings: bool,
+    out: &mut impl std::io::Write,
+) -> anyhow::Result<bool> {
+    let problems = check_sessions(dir, importers, known_marks)?;
+    write_report(&problems, format, out)?;
+    let errors = problems
+        .iter()
+        .filter(|problem| problem.severity == Severity::Error)
+        .count();
+    info!("{} errors, {} warnings", errors, problems.len() - errors);
+    Ok(errors == 0 && (!deny_warnings || problems.is_empty()))
+}
+
+/// Check every entry of a sessions directory. `known_marks` empty means any mark is fine.
+pub fn check_sessions(
+    dir: &Path,
+    importers: &ImporterRegistry,
+    known_marks: &[String],
+) -> anyhow::Result<Vec<Problem>> {
+    let mut entries = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    let mut problems = vec![];
+    for path in entries {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        let importer = importers.detect(&path);
+        let (file, takes) = match importer.map(|i| i.name()) {
+            Some("teleprompt-studio") | None if path.is_dir() => {
+                (path.join(TAKES_CSV), check_teleprompt(&path, &mut problems))
+            }
+            Some(_) => match importers.import(&path, None) {
+                Ok(session) => (
+                    path.clone(),
+                    session
+                        .takes
+                        .into_iter()
+                        .map(|take| LocatedTake { take, line: None })
+                        .collect(),
+                ),
+                Err(e) => {
+                    problems.push(Problem::new(
+                        ProblemKind::Unreadable,
+                        &path,
+                        format!("{e:#}"),
+                    ));
+                    continue;
+                }
+            },
+            None => {
+                problems.push(Problem::new(
+                    ProblemKind::UnrecognizedEntry,
+                    &path,
+                    "not a session of any known format",
+                ));
+                continue;
+            }
+        };
+        check_takes(&file, &takes, known_marks, &mut problems);
+    }
+    Ok(problems)
+}
+
+/// Check the files of a teleprompt-studio session, and read the takes that parse.
+fn check_teleprompt(dir: &Path, problems: &mut Vec<Problem>) -> Vec<LocatedTake> {
+    for name in [META_JSON, TAKES_CSV, AUDIO_WAV] {
+        let file = dir.join(name);
+        if !file.is_file() {
+            problems.push(Problem::new(
+                ProblemKind::MissingFile,
+                &file,
+                format!("missing {name}"),
+            ));
+        }
+    }
+
+    let meta = dir.join(META_JSON);
+    if let Ok(text) = std::fs::read_to_string(&meta) {
+        if let Err(e) = serde_json::from_str::<SessionMeta>(&text) {
+            // Errors in a value are reported at the end of the object, so point at the value.
+            let ((line, column), message) = invalid_meta_field(&text)
+                .unwrap_or(((e.line() as u64, e.column() as u64), e.to_string()));
+            problems.push(
+                Problem::new(ProblemKind::InvalidMetadata, &meta, message)
+                    .at(Some(line), Some(column)),
+            );
+        }
+    }
+
+    let file = dir.join(TAKES_CSV);
+    let Ok(mut reader) = csv::Reader::from_path(&file) else {
+        return vec![];
+    };
+    let session_id = dir.file_name().unwrap().to_string_lossy().into_owned();
+    let headers = reader.headers().cloned().unwrap_or_default();
+    let mut takes = vec![];
+    let mut record = csv::StringRecord::new();
+    loop {
+        let line = reader.position().line() + 1;
+        match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => {}
+            Err(e) => {
+                let line = e.position().map(|pos| pos.line()).unwrap_or(line);
+                problems.push(
+                    Problem::new(ProblemKind::InvalidTake, &file, e.to_string())
+                        .at(Some(line), None),
+                );
+                continue;
+            }
+        }
+        let line = record.position().map(|pos| pos.line()).unwrap_or(line);
+        match record.deserialize::<SessionTake>(Some(&headers)) {
+            Ok(take) => takes.push(LocatedTake {
+                take: take.to_take(&session_id),
+                line: Some(line),
+            }),
+            Err(e) => {
+                let (column, message) = match invalid_field(&headers, &record) {
+                    Some((column, message)) => (Some(column), message),
+                    None => (None, e.to_string()),
+                };
+                problems.push(
+                    Problem::new(ProblemKind::InvalidTake, &file, message).at(Some(line), column),
+                );
+            }
+        }
+    }
+    takes
+}
+
+/// The first field of a `takes.csv` record that doesn't parse, as its 1-based column and what's
+/// wrong with it.
+fn invalid_field(headers: &csv::StringRecord, record: &csv::StringRecord) -> Option<(u64, String)> {
+    headers
+        .iter()
+        .zip(record.iter())
+        .enumerate()
+        .find_map(|(i, (name, value))| {
+            let error = match name {
+                "chunk_index" | "take_index" => value.parse::<usize>().err()?.to_string(),
+                "take_start" | "take_end" => Timestamp::parse(value).err()?.to_string(),
+                _ => return None,
+            };
+            Some((i as u64 + 1, format!("invalid {name}: {error}")))
+        })
+}
+
+/// The first field of a `metadata.json` that doesn't parse, as the 1-based line and column of
+/// its value and what's wrong with it.
+fn invalid_meta_field(text: &str) -> Option<((u64, u64), String)> {
+    let meta: serde_json::Value = serde_json::from_str(text).ok()?;
+    let name = "SyncOffset";
+    let value = meta.get(name)?;
+    let error = match value.as_str() {
+        Some(value) => Timestamp::parse(value).err()?.to_string(),
+        None => format!("expected a timestamp, found {value}"),
+    };
+    Some((
+        value_position(text, name)?,
+        format!("invalid {name}: {error}"),
+    ))
+}
+
+/// Where the value of the top level `key` of a JSON object starts, as a 1-based line and column.
+fn value_position(text: &str, key: &str) -> Option<(u64, u64)> {
+    let quoted = format!("\"{key}\"");
+    let mut search = 0;
+    let start = loop {
+        let found = search + text[search..].find(&quoted)?;
+        let rest = text[found + quoted.len()..].trim_start();
+        if let Some(value) = rest.strip_prefix(':') {
+            break text.len() - value.trim_start().len();
+        }
+        search = found + quoted.len();
+    };
+    let before = &text[..start];
+    let line = before.matches('\n').count() as u64 + 1;
+    let column = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+        .chars()
+        .count() as u64
+        + 1;
+    Some((line, column))
+}
+
+/// Problems with takes that parsed: empty, overlapping, out of order, duplicated or oddly marked.
+fn check_takes(
+    file: &Path,
+    takes: &[LocatedTake],
+    known_marks: &[String],
+    problems: &mut Vec<Problem>,
+) {
+    let problem = |kind, located: &LocatedTake, message: String| {
+        Problem::new(kind, file, message).at(located.line, None)
+    };
+    let describe = |take: &Take| format!("chunk {} take {}", take.chunk_id, take.take_index);
+
+    let mut seen = HashMap::new();
+    for (i, located) in takes.iter().enumerate() {
+        let take = &located.take;
+        if take.end <= take.start {
+            problems.push(problem(
+                ProblemKind::EmptyTake,
+                located,
+                format!(
+                    "{} ends at {}, before it starts at {}",
+                    describe(take),
+                    take.end,
+                    take.start
+                ),
+            ));
+        }
+        if let Some(previous) = i.checked_sub(1).map(|i| &takes[i].take) {
+            if take.start < previous.start {
+                problems.push(problem(
+                    ProblemKind::UnsortedTakes,
+                    located,
+                    format!("{} starts before {}", describe(take), describe(previous)),
+                ));
+            }
+        }
+        let key = (take.chunk_id.clone(), take.take_index);
+        if let Some(first) = seen.insert(key, located) {
+            let at = first
+                .line
+                .map(|line| format!(" on line {line}"))
+                .unwrap_or_default();
+            problems.push(problem(
+                ProblemKind::DuplicateTakeIndex,
+                located,
+                format!("{} is also{at}", describe(take)),
+            ));
+        }
+        if !known_marks.is_empty()
+            && !known_marks
+                .iter()
+                .any(|m| m.eq_ignore_ascii_case(&take.mark))
+        {
+            problems.push(problem(
+                ProblemKind::UnknownMark,
+                located,
+                format!("{} has unknown mark {:?}", describe(take), take.mark),
+            ));
+        }
+    }
+
+    // Compare every take with the one that reaches furthest of those starting before it, so a
+    // long take overlapping several later ones is caught for each of them.
+    let mut by_start: Vec<&LocatedTake> = takes.iter().collect();
+    by_start.sort_by_key(|located| located.take.start);
+    let mut furthest: Option<&Take> = None;
+    for located in by_start {
+        let take = &located.take;
+        if let Some(a) = furthest {
+            if take.start < a.end {
+                problems.push(problem(
+                    ProblemKind::OverlappingTakes,
+                    located,
+                    format!(
+                        "{} overlaps {} until {}",
+                        describe(take),
+                        describe(a),
+                        a.end
+                    ),
+                ));
+            }
+        }
+        // Empty takes are reported on their own, and don't overlap anything.
+        if take.start < take.end && furthest.is_none_or(|a| take.end > a.end) {
+            furthest = Some(take);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_session(dir: &Path, meta: &str, takes: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(dir.join(META_JSON), meta).unwrap();
+        std::fs::write(dir.join(TAKES_CSV), takes).unwrap();
+    }
+
+    fn summary(
+        dir: &Path,
+        problems: &[Problem],
+    ) -> Vec<(ProblemKind, String, Option<u64>, Option<u64>)> {
+        problems
+            .iter()
+            .map(|p| {
+                let file = p.file.strip_prefix(dir).unwrap();
+                (p.kind, file.display().to_string(), p.line, p.column)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_problem_with_its_position() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        write_session(
+            &dir.join("1"),
+            "{\n  \"SyncOffset\": \"soon\"\n}",
+            "header,chunk_index,chunk_text,take_index,take_mark,take_start,take_end\n\
+             Intro,0,Hi,0,good,00:00:01.000,00:00:03.000\n\
+             Intro,0,Hi,1,good,00:00:02.000,00:00:04.000\n\
+             Intro,1,Yo,0,meh,00:00:00.500,00:00:00.900\n\
+             Intro,1,Yo,0,good,00:00:05.000,00:00:xx\n\
+             Intro,2,Hey,0,good,00:00:06.000,00:00:07.000\n\
+             Intro,2,Hey,0,good,00:00:08.000,00:00:09.000\n",
+        );
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let problems = check_sessions(
+            dir,
+            &ImporterRegistry::default(),
+            &["good".to_owned(), "ok".to_owned()],
+        )
+        .unwrap();
+
+        assert_eq!(
+            summary(dir, &problems),
+            [
+                (
+                    ProblemKind::MissingFile,
+                    "1/audio.wav".to_owned(),
+                    None,
+                    None
+                ),
+                (
+                    ProblemKind::InvalidMetadata,
+                    "1/metadata.json".to_owned(),
+                    Some(2),
+                    Some(17)
+                ),
+                (
+                    ProblemKind::InvalidTake,
+                    "1/takes.csv".to_owned(),
+                    Some(5),
+                    Some(7)
+                ),
+                (
+                    ProblemKind::UnsortedTakes,
+                    "1/takes.csv".to_owned(),
+                    Some(4),
+                    None
+                ),
+                (
+                    ProblemKind::UnknownMark,
+                    "1/takes.csv".to_owned(),
+                    Some(4),
+                    None
+                ),
+                (
+                    ProblemKind::DuplicateTakeIndex,
+                    "1/takes.csv".to_owned(),
+                    Some(7),
+                    None
+                ),
+                (
+                    ProblemKind::OverlappingTakes,
+                    "1/takes.csv".to_owned(),
+                    Some(3),
+                    None
+                ),
+                (
+                    ProblemKind::UnrecognizedEntry,
+                    "notes.txt".to_owned(),
+                    None,
+                    None
+                ),
+            ]
+        );
+        assert_eq!(
+            problems[1].message,
+            "invalid SyncOffset: invalid timestamp \"soon\""
+        );
+        assert_eq!(
+            problems[2].message,
+            "invalid take_end: invalid timestamp \"00:00:xx\""
+        );
+    }
+
+    #[test]
+    fn long_takes_overlap_every_later_take() {
+        let located = |idx, start_ms, end_ms| LocatedTake {
+            take: crate::export::tests::take("1", idx, start_ms, end_ms),
+            line: Some(idx as u64 + 2),
+        };
+        let takes = [
+            located(0, 0, 10_000),
+            located(1, 1000, 2000),
+            located(2, 5000, 6000),
+            located(3, 10_000, 11_000),
+        ];
+        let mut problems = vec![];
+        check_takes(Path::new("takes.csv"), &takes, &[], &mut problems);
+
+        let overlapping: Vec<_> = problems.iter().map(|p| (p.kind, p.line)).collect();
+        assert_eq!(
+            overlapping,
+            [
+                (ProblemKind::OverlappingTakes, Some(3)),
+                (ProblemKind::OverlappingTakes, Some(4)),
+            ]
+        );
+        assert_eq!(
+            problems[1].message,
+            "chunk 1 take 2 overlaps chunk 1 take 0 until 00:00:10.000"
+        );
+    }
+
+    #[test]
+    fn json_report() {
+        let problem =
+            Problem::new(ProblemKind::EmptyTake, Path::new("takes.csv"), "empty").at(Some(3), None);
+        let mut out = vec![];
+        write_report(&[problem], ReportFormat::Json, &mut out).unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "severity": "error",
+                "kind": "empty-take",
+                "file": "takes.csv",
+                "line": 3,
+                "message": "empty",
+            }])
+        );
+    }
+}
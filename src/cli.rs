@@ -1,28 +1,407 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
+use log::*;
+
+use session_slicer::{
+    assemble::TieBreak,
+    check::ReportFormat,
+    data::{Progress, SliceOptions},
+    export::{ExportFormat, TakeFilter},
+    import::{
+        markers::MarkerOptions,
+        take_list::{TakeListImporter, TakeListMapping},
+        ImporterRegistry,
+    },
+    profile::{OutputProfile, ProxyProfile},
+    review::PosterPoint,
+    timecode::FrameRate,
+    timestamp::Timestamp,
+    trim::TrimOptions,
+    validate::RangePolicy,
+    Project, Slicer, SlicerBuilder,
+};
 
 #[derive(Debug, Parser)]
 pub struct Args {
     #[arg(short, long)]
     pub ffmpeg_path: Option<PathBuf>,
 
+    /// Inspect media with `ffprobe` instead of symphonia. Uses the `ffprobe` next to
+    /// `--ffmpeg-path` if that is given, otherwise the one on `PATH`.
+    #[arg(long)]
+    pub ffprobe: bool,
+
     /// The `sessions` directory.
     #[arg(long)]
     pub sessions: Option<PathBuf>,
 
+    /// Read every entry of the sessions directory with this importer, instead of detecting the
+    /// format of each. One of `teleprompt-studio`, `reaper`, `audition`, `premiere`, `resolve`,
+    /// `audacity`, `bwf` or `take-list`.
+    #[arg(long)]
+    pub session_format: Option<String>,
+
+    /// Column mapping JSON for reading CSV and JSON take lists in the sessions directory.
+    #[arg(long)]
+    pub take_list_mapping: Option<PathBuf>,
+
+    /// The media the takes of take lists were recorded in, instead of the one in the mapping.
+    #[arg(long, requires = "take_list_mapping")]
+    pub take_list_media: Option<PathBuf>,
+
+    /// The media marker files in the sessions directory were marked in, instead of the one they
+    /// reference or the one with the same name next to them.
+    #[arg(long)]
+    pub marker_media: Option<PathBuf>,
+
+    /// Frame rate of the timecodes in Premiere and Resolve marker exports.
+    #[arg(long)]
+    pub marker_fps: Option<FrameRate>,
+
+    /// Subtracted from every marker, eg. `01:00:00:00@25` for a timeline that starts at one hour.
+    #[arg(long, default_value_t = Timestamp::ZERO)]
+    pub marker_time_base: Timestamp,
+
     /// The `video` directory.
     #[arg(long)]
     pub video: Option<PathBuf>,
 
+    /// Directory of WAVs from external recorders. Each is added as a track to the session it was
+    /// recorded during, synced by its BWF time reference.
+    #[arg(long)]
+    pub recorder_dir: Option<PathBuf>,
+
+    /// Sync offset of a session's video, as `SESSION=TIMESTAMP`, instead of asking for it. Any
+    /// timestamp works, eg. `3=-00:00:01.500` or `3=00:00:12;04@29.97`. Can be given more than
+    /// once.
+    #[arg(long = "sync-offset", value_parser = parse_sync_offset, allow_hyphen_values = true)]
+    pub sync_offsets: Vec<(String, Timestamp)>,
+
     /// Where the sliced videos will be saved.
     #[arg(long)]
     pub output: Option<PathBuf>,
 
+    /// Length of the fade in and out applied to audio slices, in milliseconds.
+    #[arg(long, default_value_t = 0)]
+    pub audio_fade_ms: u64,
+
+    /// Output profile to apply to every slice. Either `default`, `podcast`, `broadcast`, `hevc`,
+    /// or the path to a profile JSON file.
+    #[arg(long, default_value = "default")]
+    pub profile: String,
+
+    /// Also make lightweight proxies of every slice, for offline editing. Slices then go in
+    /// `full/` and proxies with the same names in `proxy/`. Profile files can set this up with
+    /// a `proxy` section instead.
+    #[arg(long)]
+    pub proxy: bool,
+
+    /// What to do with takes that don't fit inside their media.
+    #[arg(long, value_enum, default_value_t = RangePolicy::Clamp)]
+    pub out_of_range: RangePolicy,
+
+    /// Trim silence from the start and end of every take.
+    #[arg(long)]
+    pub trim_silence: bool,
+
+    /// Level above which audio counts as speech when trimming, in dBFS.
+    #[arg(long, default_value_t = -45.0, allow_negative_numbers = true)]
+    pub trim_threshold_db: f32,
+
+    /// Silence to keep around the speech when trimming, in milliseconds.
+    #[arg(long, default_value_t = 200)]
+    pub trim_padding_ms: u64,
+
+    /// Write SRT and WebVTT captions of the script text next to every slice.
+    #[arg(long)]
+    pub subtitles: bool,
+
+    /// Also write an XMP sidecar next to every slice.
+    #[arg(long)]
+    pub xmp: bool,
+
+    /// Extract a poster frame of every take, a contact sheet per session, and a `review.html` page
+    /// into the output directory.
+    #[arg(long)]
+    pub review: bool,
+
+    /// Where in each take the poster frame is taken from, either a percentage (`50%`) or seconds
+    /// after the start of the take (`1.5`).
+    #[arg(long, default_value = "50%")]
+    pub poster_at: PosterPoint,
+
+    /// Export the takes as a CMX3600 EDL that references the original media.
+    #[arg(long)]
+    pub edl: Option<PathBuf>,
+
+    /// Export the takes as Final Cut Pro XML that references the original media.
+    #[arg(long)]
+    pub fcpxml: Option<PathBuf>,
+
+    /// Export the takes as an OpenTimelineIO timeline that references the original media.
+    #[arg(long)]
+    pub otio: Option<PathBuf>,
+
+    /// Frame rate of exported timelines, eg. `25`, `23.976`, `29.97df` or `30000/1001`. Defaults
+    /// to the frame rate of the first video track.
+    #[arg(long)]
+    pub fps: Option<FrameRate>,
+
+    /// Only export takes that start at or after this point of their session. Accepts any
+    /// timestamp, including timecode such as `00:10:00;00@29.97`.
+    #[arg(long, allow_hyphen_values = true)]
+    pub from: Option<Timestamp>,
+
+    /// Only export takes that end at or before this point of their session.
+    #[arg(long, allow_hyphen_values = true)]
+    pub to: Option<Timestamp>,
+
+    /// Only export takes with this mark. Can be given more than once.
+    #[arg(long = "mark")]
+    pub marks: Vec<String>,
+
+    /// Only export this chunk. Can be given more than once.
+    #[arg(long = "chunk")]
+    pub chunks: Vec<String>,
+
+    /// Don't slice anything, only sync and export.
+    #[arg(long)]
+    pub skip_slicing: bool,
+
     pub project: Option<PathBuf>,
 
-    #[arg(short, long, short, long, value_enum, default_value_t=Verbosity::Info)]
+    #[arg(short, long, short, long, value_enum, default_value_t=Verbosity::Info, global = true)]
     pub(crate) verbosity: Verbosity,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// The project's directories, from the project directory and the ones given on their own.
+    pub fn project(&self) -> anyhow::Result<Project> {
+        let missing = |flag: &str| anyhow::anyhow!("needs a project directory or {flag}");
+        let mut project = match &self.project {
+            Some(root) => Project::new(root),
+            None => Project {
+                name: "session-slicer".to_owned(),
+                sessions_dir: self.sessions.clone().ok_or_else(|| missing("--sessions"))?,
+                video_dir: self.video.clone().ok_or_else(|| missing("--video"))?,
+                output_dir: self.output.clone().ok_or_else(|| missing("--output"))?,
+            },
+        };
+        if let Some(dir) = &self.sessions {
+            project.sessions_dir = dir.clone();
+        }
+        if let Some(dir) = &self.video {
+            project.video_dir = dir.clone();
+        }
+        if let Some(dir) = &self.output {
+            project.output_dir = dir.clone();
+        }
+        Ok(project)
+    }
+
+    /// Only the sessions directory, for commands that don't need the others.
+    pub fn sessions_dir(&self) -> anyhow::Result<PathBuf> {
+        match (&self.sessions, &self.project) {
+            (Some(dir), _) => Ok(dir.clone()),
+            (None, Some(root)) => Ok(Project::new(root).sessions_dir),
+            (None, None) => anyhow::bail!("needs a project directory or --sessions"),
+        }
+    }
+
+    /// The importers for the sessions directory.
+    pub fn importers(&self) -> anyhow::Result<ImporterRegistry> {
+        let mut importers = ImporterRegistry::new(MarkerOptions {
+            media: self
+                .marker_media
+                .as_deref()
+                .map(std::path::absolute)
+                .transpose()?,
+            frame_rate: self.marker_fps,
+            time_base: self.marker_time_base,
+        });
+        if let Some(path) = &self.take_list_mapping {
+            let mut mapping = TakeListMapping::from_path(path)?;
+            if let Some(media) = &self.take_list_media {
+                mapping.media = Some(std::path::absolute(media)?);
+            }
+            importers.register(TakeListImporter { mapping });
+        }
+        Ok(importers)
+    }
+
+    /// A slicer set up from the command line, without an output directory.
+    pub fn slicer_builder(&self) -> anyhow::Result<SlicerBuilder> {
+        let mut options = SliceOptions {
+            audio_fade: Duration::from_millis(self.audio_fade_ms),
+            profile: OutputProfile::resolve(&self.profile)?,
+            subtitles: self.subtitles,
+            sidecar_xmp: self.xmp,
+            per_session_dirs: matches!(self.command, Some(Command::Watch(_))),
+            ..Default::default()
+        };
+        if self.proxy && options.profile.proxy.is_none() {
+            options.profile.proxy = Some(ProxyProfile::default());
+        }
+        debug!("output profile: {:?}", options.profile);
+        if self.trim_silence {
+            options.trim = Some(TrimOptions {
+                threshold_db: self.trim_threshold_db,
+                padding: Duration::from_millis(self.trim_padding_ms),
+            });
+        }
+
+        let mut builder =
+            Slicer::builder()
+                .options(options)
+                .on_progress(|progress| match progress {
+                    Progress::Sliced { .. } | Progress::Failed { .. } => info!("{}", progress),
+                    _ => debug!("{}", progress),
+                });
+        if let Some(ffmpeg) = &self.ffmpeg_path {
+            builder = builder.ffmpeg(ffmpeg);
+        }
+        if self.ffprobe {
+            let ffprobe = match &self.ffmpeg_path {
+                Some(ffmpeg) => {
+                    let ext = ffmpeg.extension().map(|e| e.to_string_lossy().into_owned());
+                    let mut name = "ffprobe".to_owned();
+                    if let Some(ext) = ext {
+                        name = format!("{name}.{ext}");
+                    }
+                    ffmpeg.with_file_name(name)
+                }
+                None => "ffprobe".into(),
+            };
+            builder = builder.ffprobe(ffprobe);
+        }
+        Ok(builder)
+    }
+
+    /// The takes to export.
+    pub fn take_filter(&self) -> TakeFilter {
+        TakeFilter {
+            marks: self.marks.clone(),
+            chunks: self.chunks.clone(),
+            from: self.from,
+            to: self.to,
+            ..Default::default()
+        }
+    }
+
+    /// The timelines to export, and where to.
+    pub fn exports(&self) -> Vec<(ExportFormat, PathBuf)> {
+        [
+            (ExportFormat::Cmx3600, &self.edl),
+            (ExportFormat::FcpXml, &self.fcpxml),
+            (ExportFormat::Otio, &self.otio),
+        ]
+        .into_iter()
+        .filter_map(|(format, path)| Some((format, path.clone()?)))
+        .collect()
+    }
+}
+
+fn parse_sync_offset(s: &str) -> anyhow::Result<(String, Timestamp)> {
+    let (session_id, offset) = s
+        .split_once('=')
+        .ok_or(anyhow::anyhow!("expected SESSION=TIMESTAMP"))?;
+    Ok((session_id.to_owned(), offset.parse()?))
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Pick the best take of every chunk and join them into a rough cut of the whole script.
+    Assemble(AssembleArgs),
+    /// Check the sessions directory for problems without slicing anything.
+    Check(CheckArgs),
+    /// Keep watching the sessions and video directories, and slice new takes as their files
+    /// finish copying.
+    Watch(WatchArgs),
+    /// Plan the slices and hand them out to workers over HTTP instead of cutting them here.
+    Serve(ServeArgs),
+    /// Cut slices handed out by a server started with `serve`.
+    Worker(WorkerArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CheckArgs {
+    /// How to write the problems found to stdout.
+    #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+    pub format: ReportFormat,
+
+    /// Marks takes are expected to have, comma separated. Empty allows any mark.
+    #[arg(long, value_delimiter = ',', default_value = "good,ok,bad")]
+    pub known_marks: Vec<String>,
+
+    /// Fail on warnings too, not just errors.
+    #[arg(long)]
+    pub deny_warnings: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct WatchArgs {
+    /// Seconds between looking for changes.
+    #[arg(long, default_value_t = 5)]
+    pub interval_secs: u64,
+
+    /// Seconds a file has to stay the same size before it counts as finished.
+    #[arg(long, default_value_t = 10)]
+    pub settle_secs: u64,
+
+    /// Slice sessions without a video straight away, instead of waiting for the video.
+    #[arg(long)]
+    pub no_wait_for_video: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ServeArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "0.0.0.0:7878")]
+    pub listen: String,
+
+    /// Seconds a worker has to finish or renew a job before it's handed to another worker.
+    #[arg(long, default_value_t = 300)]
+    pub lease_secs: u64,
+
+    /// How often a job is tried before giving up on it.
+    #[arg(long, default_value_t = 3)]
+    pub max_attempts: u32,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct WorkerArgs {
+    /// URL of the server, e.g. `http://10.0.0.2:7878`.
+    pub server: String,
+
+    /// Name to report to the server. Defaults to the host name and process id.
+    #[arg(long)]
+    pub name: Option<String>,
+
+    /// Seconds to wait before asking again when no job is free.
+    #[arg(long, default_value_t = 2)]
+    pub poll_secs: u64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AssembleArgs {
+    /// Where the rough cut will be saved.
+    pub output: PathBuf,
+
+    /// Marks from best to worst, comma separated. Takes with other marks are picked last.
+    #[arg(long, value_delimiter = ',', default_value = "good,ok")]
+    pub mark_priority: Vec<String>,
+
+    /// How to pick between takes with equally good marks.
+    #[arg(long, value_enum, default_value_t = TieBreak::Latest)]
+    pub tie_break: TieBreak,
+
+    /// Where the rough cut's EDL will be saved. Defaults to next to the rough cut.
+    #[arg(long)]
+    pub edl: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -1,31 +1,270 @@
 use std::{
     collections::HashMap,
+    fmt,
     path::{Path, PathBuf},
-    process::Command,
-    sync::{Arc, RwLock},
+    process::{Command, Output},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock, RwLock,
+    },
+    time::Duration,
 };
 
+use anyhow::Context;
 use log::*;
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::timestamp::Timestamp;
+use crate::{
+    audio,
+    backend::{is_wav, AutoBackend, CutBackend, CutJob, CutKind, FfmpegBackend, ProxyCut},
+    bwf::BwfInfo,
+    capabilities::FfmpegCapabilities,
+    channels::{ChannelLayout, ChannelMix},
+    export,
+    probe::{MediaProbe, ProbeBackend, StreamKind},
+    profile::{OutputProfile, ProxyProfile},
+    sidecar::{Rendition, RenditionKind, SliceMetadata},
+    subtitles,
+    synchronizer::{SyncSource, SyncerCache, TimeReferenceSyncer, TrackSync},
+    timestamp::Timestamp,
+    trim::{self, TrimOptions, TrimReport},
+    wav::WavFile,
+};
 
 #[derive(Debug, Default)]
 pub struct Slicer {
     pub sessions: Arc<RwLock<HashMap<String, Session>>>,
     /// Map of session id to takes
     pub takes: HashMap<String, Vec<Take>>,
+    /// Path to the ffmpeg binary. Uses `ffmpeg` from `PATH` if not set.
+    pub ffmpeg: Option<PathBuf>,
+    pub probe: MediaProbe,
+    pub options: SliceOptions,
+    /// Where [`Slicer::plan`] puts slices.
+    pub output_dir: Option<PathBuf>,
+    /// Cuts the slices. See [`Slicer::cut_backend`].
+    pub backend: Option<Arc<dyn CutBackend>>,
+    pub progress: Option<ProgressCallback>,
+    /// What ffmpeg supports, once [`Slicer::ffmpeg_capabilities`] has asked.
+    pub capabilities: OnceLock<FfmpegCapabilities>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SliceOptions {
+    /// Length of the fade in and fade out applied to audio slices, to avoid clicks at the edges.
+    pub audio_fade: Duration,
+    pub profile: OutputProfile,
+    /// Trim silence from the heads and tails of takes before slicing.
+    pub trim: Option<TrimOptions>,
+    /// Write SRT and WebVTT captions of the script text next to every slice.
+    pub subtitles: bool,
+    /// Write an XMP sidecar next to every slice, in addition to the JSON one.
+    pub sidecar_xmp: bool,
+    /// Put the slices of every session in a directory of its own and number them by take index,
+    /// so slicing more takes later doesn't clash with earlier slices.
+    pub per_session_dirs: bool,
+}
+
+/// A file produced by slicing one track of a take.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceOutput {
+    /// Index of the take in the order it was sliced, as used in the file name.
+    pub take_index: usize,
+    pub track_index: usize,
+    pub file: PathBuf,
+    /// The proxy of `file`, if the profile makes them.
+    #[serde(default)]
+    pub proxy: Option<PathBuf>,
+    pub metadata: SliceMetadata,
+}
+
+/// Something that happened while syncing or slicing, as passed to [`SlicerBuilder::on_progress`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Progress<'a> {
+    /// A session's video was found and lined up with its audio.
+    Synced {
+        session_id: &'a str,
+        file: &'a Path,
+        sync_offset: Timestamp,
+        source: SyncSource,
+    },
+    /// A session has no video.
+    NoVideo { session_id: &'a str },
+    /// Slicing is about to start.
+    Planned { slices: usize },
+    /// A slice was written. `done` counts the slices finished so far, failed ones included.
+    Sliced {
+        output: &'a SliceOutput,
+        done: usize,
+        total: usize,
+    },
+    /// A slice couldn't be written.
+    Failed {
+        file: &'a Path,
+        error: &'a anyhow::Error,
+        done: usize,
+        total: usize,
+    },
+}
+
+impl fmt::Display for Progress<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Progress::Synced {
+                session_id,
+                sync_offset,
+                source,
+                ..
+            } => write!(
+                f,
+                "synced session {session_id} at {sync_offset} ({})",
+                source.name()
+            ),
+            Progress::NoVideo { session_id } => write!(f, "no video for session {session_id}"),
+            Progress::Planned { slices } => write!(f, "slicing {slices} files"),
+            Progress::Sliced {
+                output,
+                done,
+                total,
+            } => write!(f, "[{done}/{total}] {}", output.file.display()),
+            Progress::Failed {
+                file,
+                error,
+                done,
+                total,
+            } => write!(f, "[{done}/{total}] {} failed: {error}", file.display()),
+        }
+    }
+}
+
+/// A progress callback. Slicing runs in parallel, so it can be called from several threads at
+/// once.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&Progress) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// One track of one take to cut, as worked out by [`Slicer::plan`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedSlice {
+    /// Index of the take in the order it was planned, or its own take index with
+    /// [`SliceOptions::per_session_dirs`]. Used in the file name.
+    pub take_index: usize,
+    /// The take, with its in and out points moved by trimming.
+    pub take: Take,
+    pub trim: Option<TrimReport>,
+    pub track_index: usize,
+    pub track: Track,
+    /// Where to cut, in the track's own time.
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub file: PathBuf,
+    /// Where the proxy goes, if the profile makes them.
+    #[serde(default)]
+    pub proxy: Option<PathBuf>,
+    /// The audio channels to keep, from the profile's channel map for the track.
+    #[serde(default)]
+    pub channels: Option<ChannelMix>,
+}
+
+/// Everything [`Slicer::slice`] will write. Takes whose files already exist are left out.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlicePlan {
+    pub output_dir: PathBuf,
+    pub slices: Vec<PlannedSlice>,
+}
+
+/// Sets up a [`Slicer`]. Everything is optional.
+///
+/// ```
+/// # use session_slicer::{SliceOptions, Slicer};
+/// let slicer = Slicer::builder()
+///     .ffmpeg("/opt/ffmpeg/bin/ffmpeg")
+///     .output("slices")
+///     .options(SliceOptions {
+///         subtitles: true,
+///         ..Default::default()
+///     })
+///     .on_progress(|progress| eprintln!("{progress}"))
+///     .build();
+/// assert!(slicer.options.subtitles);
+/// ```
+#[derive(Debug, Default)]
+pub struct SlicerBuilder {
+    slicer: Slicer,
+}
+
+impl SlicerBuilder {
+    /// Path to the ffmpeg binary. Uses `ffmpeg` from `PATH` if not set.
+    pub fn ffmpeg(mut self, path: impl Into<PathBuf>) -> Self {
+        self.slicer.ffmpeg = Some(path.into());
+        self
+    }
+
+    /// Probe media with the ffprobe binary at `path` instead of reading it natively, which gives
+    /// keyframe indexes for cutting video without transcoding.
+    pub fn ffprobe(mut self, path: impl Into<PathBuf>) -> Self {
+        self.slicer.probe.backend = ProbeBackend::Ffprobe(path.into());
+        self
+    }
+
+    /// Where [`Slicer::plan`] puts slices.
+    pub fn output(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.slicer.output_dir = Some(dir.into());
+        self
+    }
+
+    /// What cuts the slices, instead of ffmpeg and native WAV cutting.
+    pub fn backend(mut self, backend: Arc<dyn CutBackend>) -> Self {
+        self.slicer.backend = Some(backend);
+        self
+    }
+
+    pub fn options(mut self, options: SliceOptions) -> Self {
+        self.slicer.options = options;
+        self
+    }
+
+    /// Call `callback` as sessions are synced and slices are written.
+    pub fn on_progress(mut self, callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.slicer.progress = Some(ProgressCallback(Arc::new(callback)));
+        self
+    }
+
+    pub fn build(self) -> Slicer {
+        self.slicer
+    }
 }
 
 impl Slicer {
+    pub fn builder() -> SlicerBuilder {
+        SlicerBuilder::default()
+    }
+
     pub fn new() -> Self {
         Self {
             ..Default::default()
         }
     }
 
-    pub fn with_ffmpeg(oath: impl AsRef<Path>) -> Self {
-        todo!("impl")
+    pub fn with_ffmpeg(path: impl AsRef<Path>) -> Self {
+        Self {
+            ffmpeg: Some(path.as_ref().to_owned()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn ffmpeg_command(&self) -> Command {
+        match &self.ffmpeg {
+            Some(path) => Command::new(path),
+            None => Command::new("ffmpeg"),
+        }
     }
 
     pub fn register_session(&mut self, session: impl IntoSession) {
@@ -39,101 +278,642 @@ impl Slicer {
             .insert(session.session_id.clone(), session);
     }
 
-    pub fn add_track(&mut self, session_id: &str, track: Track) {
-        let mut sessions = self.sessions.write().unwrap();
-        let session = sessions.get_mut(session_id).expect("session not found");
-        session.tracks.push(track);
-    }
-
     fn takes_iter(&self) -> impl Iterator<Item = &Take> {
         self.takes.values().flatten()
     }
 
-    pub fn perform_slicing(&self, output_dir: impl AsRef<Path>) -> anyhow::Result<()> {
+    /// Add the video of every session from `video_dir`, named `video-session-<id>.mp4`. The sync
+    /// offset comes from `given`, then `cache`, then the BWF time references of the session's
+    /// audio, and finally `syncer`. Offsets that weren't cached are added to `cache`.
+    pub fn sync_videos(
+        &mut self,
+        video_dir: &Path,
+        given: &[(String, Timestamp)],
+        cache: &mut SyncerCache,
+        syncer: &impl TrackSync,
+    ) -> anyhow::Result<()> {
+        for session in self.sessions.write().unwrap().values_mut() {
+            let session_id = session.session_id.clone();
+            let video_path = video_dir.join(format!("video-session-{}.mp4", &session_id));
+            if !video_path.exists() {
+                warn!("no video found for session {}", session_id);
+                self.report(Progress::NoVideo {
+                    session_id: &session_id,
+                });
+                continue;
+            }
+            info!("found video for session {}", session_id);
+            let file_name = video_path.file_name().unwrap().to_string_lossy();
+
+            let given = given
+                .iter()
+                .find(|(id, _)| *id == session_id)
+                .map(|(_, offset)| *offset);
+            let (sync_offset, source) = if let Some(timestamp) = given {
+                info!("using sync offset {} for {}", timestamp, file_name);
+                (timestamp, SyncSource::Given)
+            } else if let Some(timestamp) = cache.get(&file_name) {
+                info!(
+                    "using cached sync offset for {}: {:?}",
+                    file_name, timestamp
+                );
+                (timestamp, SyncSource::Cached)
+            } else {
+                let from_time_reference = TimeReferenceSyncer::for_session(session)
+                    .and_then(|syncer| syncer.find_sync_offset(&video_path).ok());
+                match from_time_reference {
+                    Some(timestamp) => {
+                        info!("synced {} by its BWF time reference", file_name);
+                        (timestamp, SyncSource::TimeReference)
+                    }
+                    None => (syncer.find_sync_offset(&video_path)?, SyncSource::Syncer),
+                }
+            };
+            if source != SyncSource::Cached {
+                cache.set(&file_name, sync_offset);
+            }
+
+            self.report(Progress::Synced {
+                session_id: &session_id,
+                file: &video_path,
+                sync_offset,
+                source,
+            });
+            session.tracks.push(Track {
+                file: video_path,
+                sync_offset,
+            });
+        }
+        Ok(())
+    }
+
+    /// Plan and slice every take into `output_dir`.
+    pub fn perform_slicing(
+        &self,
+        output_dir: impl AsRef<Path>,
+    ) -> anyhow::Result<Vec<SliceOutput>> {
+        let plan = self.plan_in(output_dir)?;
+        self.slice(&plan)
+    }
+
+    /// Work out which files slicing will write into the output directory set with
+    /// [`SlicerBuilder::output`]. Trimming happens here, since it moves the cuts.
+    pub fn plan(&self) -> anyhow::Result<SlicePlan> {
+        let output_dir = self
+            .output_dir
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("no output directory set"))?;
+        self.plan_in(output_dir)
+    }
+
+    /// Like [`Slicer::plan`], into another output directory.
+    pub fn plan_in(&self, output_dir: impl AsRef<Path>) -> anyhow::Result<SlicePlan> {
         let output_dir = output_dir.as_ref();
+        if self.options.profile.burn_in_subtitles && !self.options.subtitles {
+            anyhow::bail!(
+                "output profile {} burns in subtitles, which needs subtitles to be enabled",
+                self.options.profile.name
+            );
+        }
         info!("slicer outputting to {:?}", output_dir);
         std::fs::create_dir_all(output_dir)?;
 
-        let results: Vec<_> = self
-            .takes_iter()
-            .enumerate()
-            .par_bridge()
-            .map(|(idx, take)| self.slice_take(idx, take, output_dir))
+        // Takes are numbered in a fixed order, so that every run gives them the same files.
+        let mut takes: Vec<_> = self.takes_iter().collect();
+        takes.sort_by_key(|take| (take.session_id.clone(), export::chunk_order(take)));
+        let takes: Vec<_> = takes.into_iter().enumerate().collect();
+        let results: Vec<_> = takes
+            .into_par_iter()
+            .map(|(idx, take)| self.plan_take(idx, take, output_dir))
             .collect();
 
+        let mut slices = vec![];
         for result in results {
-            if let Err(e) = result {
-                error!("failed to slice: {}", e);
+            match result {
+                Ok(planned) => slices.extend(planned),
+                Err(e) => error!("failed to slice: {}", e),
             }
         }
 
-        Ok(())
+        Ok(SlicePlan {
+            output_dir: output_dir.to_owned(),
+            slices,
+        })
+    }
+
+    /// Cut every slice of a plan, in parallel.
+    pub fn slice(&self, plan: &SlicePlan) -> anyhow::Result<Vec<SliceOutput>> {
+        let total = plan.slices.len();
+        self.report(Progress::Planned { slices: total });
+
+        let done = AtomicUsize::new(0);
+        let results: Vec<_> = plan
+            .slices
+            .par_iter()
+            .map(|slice| {
+                let result = self.slice_planned(slice);
+                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                match &result {
+                    Ok(output) => self.report(Progress::Sliced {
+                        output,
+                        done,
+                        total,
+                    }),
+                    Err(error) => {
+                        error!("failed to slice {}: {}", slice.file.display(), error);
+                        self.report(Progress::Failed {
+                            file: &slice.file,
+                            error,
+                            done,
+                            total,
+                        });
+                    }
+                }
+                result
+            })
+            .collect();
+
+        Ok(results.into_iter().filter_map(Result::ok).collect())
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(callback) = &self.progress {
+            (callback.0)(&progress);
+        }
     }
 
-    fn slice_take(&self, index: usize, take: &Take, output_dir: &Path) -> anyhow::Result<()> {
+    fn plan_take(
+        &self,
+        index: usize,
+        take: &Take,
+        output_dir: &Path,
+    ) -> anyhow::Result<Vec<PlannedSlice>> {
         let sessions = self.sessions.read().unwrap();
-        for (track_idx, track) in sessions
-            .get(&take.session_id)
-            .unwrap()
-            .tracks
-            .iter()
-            .enumerate()
-        {
+        let session = sessions.get(&take.session_id).unwrap();
+
+        let session_dir;
+        let (index, output_dir) = if self.options.per_session_dirs {
+            session_dir = output_dir.join(&take.session_id);
+            std::fs::create_dir_all(&session_dir)?;
+            (take.take_index, session_dir.as_path())
+        } else {
+            (index, output_dir)
+        };
+        // Full quality slices and their proxies get the same names in sibling folders, so editors
+        // can relink from one to the other.
+        let (slice_dir, proxy_dir) = match self.options.profile.proxy {
+            Some(_) => {
+                let (full, proxy) = (output_dir.join("full"), output_dir.join("proxy"));
+                std::fs::create_dir_all(&full)?;
+                std::fs::create_dir_all(&proxy)?;
+                (full, Some(proxy))
+            }
+            None => (output_dir.to_owned(), None),
+        };
+
+        let mut take = take.clone();
+        let trim = match &self.options.trim {
+            Some(options) => self.trim_take(session, &take, options),
+            None => None,
+        };
+        if let Some(report) = trim {
+            take.start = report.start;
+            take.end = report.end;
+        }
+
+        let mut slices = vec![];
+        for (track_idx, track) in session.tracks.iter().enumerate() {
             let ext = track.file.extension().unwrap();
             let start = take.start + track.sync_offset;
             let end = take.end + track.sync_offset;
+            if start.is_negative() {
+                error!(
+                    "chunk {} take {} starts {} before the start of {}, skipping",
+                    take.chunk_id,
+                    take.take_index,
+                    -start,
+                    track.file.display()
+                );
+                continue;
+            }
+
+            for channels in self.channel_mixes(track_idx, track)? {
+                // Split channels of video tracks are extracted to audio files.
+                let ext = match &channels {
+                    Some(ChannelMix {
+                        suffix: Some(_), ..
+                    }) if !audio::is_audio_file(&track.file) => "wav",
+                    _ => ext.to_str().unwrap(),
+                };
+                let track_name = match channels.as_ref().and_then(|mix| mix.suffix.as_ref()) {
+                    Some(suffix) => format!("{track_idx}-{suffix}"),
+                    None => track_idx.to_string(),
+                };
+                let file_name = format!(
+                    "{}{}-{}.{}",
+                    slice_file_prefix(&take.chunk_id, index),
+                    track_name,
+                    take.mark,
+                    ext,
+                );
+                let out_file = slice_dir.join(&file_name);
+                let proxy = proxy_dir.as_ref().map(|dir| dir.join(&file_name));
+                if out_file.exists() {
+                    if proxy.as_ref().is_none_or(|proxy| proxy.exists()) {
+                        warn!("{} already exists, skipping", out_file.display());
+                        continue;
+                    }
+                    warn!("{} has no proxy, slicing it again", out_file.display());
+                }
+
+                slices.push(PlannedSlice {
+                    take_index: index,
+                    take: take.clone(),
+                    trim,
+                    track_index: track_idx,
+                    track: track.clone(),
+                    start,
+                    end,
+                    file: out_file,
+                    proxy,
+                    channels,
+                });
+            }
+        }
+
+        Ok(slices)
+    }
+
+    /// The channel mixes to slice a track into, from the profile's channel map for it. A single
+    /// `None` keeps the track's channels as they are.
+    fn channel_mixes(
+        &self,
+        track_idx: usize,
+        track: &Track,
+    ) -> anyhow::Result<Vec<Option<ChannelMix>>> {
+        let Some(map) = self.options.profile.channel_map(track_idx) else {
+            return Ok(vec![None]);
+        };
+        let layout = match ChannelLayout::read(&track.file) {
+            Ok(layout) => layout,
+            // Maps for every track skip tracks without audio.
+            Err(e) if map.track.is_none() => {
+                debug!("not mapping channels of {}: {}", track.file.display(), e);
+                return Ok(vec![None]);
+            }
+            Err(e) => {
+                return Err(e.context(format!(
+                    "failed to read the channel layout of {}",
+                    track.file.display()
+                )))
+            }
+        };
+        let mixes = map
+            .resolve(&layout)
+            .with_context(|| format!("failed to map the channels of {}", track.file.display()))?;
+        Ok(mixes.into_iter().map(Some).collect())
+    }
 
-            let file_name = format!(
-                "chunk-{}-take-{}-track-{}-{}.{}",
-                take.chunk_id,
-                index,
-                track_idx,
-                take.mark,
-                ext.to_str().unwrap(),
+    /// Cut one slice of a plan, for running plans somewhere else.
+    pub fn slice_planned(&self, slice: &PlannedSlice) -> anyhow::Result<SliceOutput> {
+        let PlannedSlice {
+            take,
+            track,
+            start,
+            end,
+            file: out_file,
+            ..
+        } = slice;
+        debug!("slicing {} to {}", track.file.display(), out_file.display());
+        // Slices are only planned over an existing one when its proxy is missing, and ffmpeg
+        // won't overwrite it.
+        if out_file.exists() {
+            std::fs::remove_file(out_file)?;
+        }
+
+        let mut metadata = SliceMetadata::new(take, slice.track_index, track);
+        metadata.trim = slice.trim;
+        if let Some(mix) = &slice.channels {
+            metadata.channels = mix.description.clone();
+        }
+
+        let profile = &self.options.profile;
+        let burn_in = profile.burn_in_subtitles;
+        let mut captions = None;
+        if let (true, Some(text)) = (self.options.subtitles, &take.chunk_text) {
+            let cues = subtitles::slice_cues(text, (take.end - take.start).saturating_duration());
+            match subtitles::write_captions(out_file, &cues) {
+                Ok(()) => captions = Some(out_file.with_extension("srt")),
+                Err(e) => error!("failed to write captions for {}: {}", out_file.display(), e),
+            }
+        }
+
+        if is_wav(&track.file) {
+            match WavFile::open(&track.file) {
+                Ok(wav) => metadata.set_recorder(&BwfInfo::read(&wav)),
+                Err(e) => debug!("failed to read {}: {}", track.file.display(), e),
+            }
+        }
+
+        let probed = self.probe.probe(&track.file);
+        let is_audio = match &probed {
+            Ok(info) => {
+                if let Some(rate) = info.first(StreamKind::Video).and_then(|s| s.frame_rate) {
+                    metadata.set_frame_rate(rate);
+                }
+                info.is_audio_only()
+            }
+            Err(e) => {
+                debug!("failed to probe {}: {}", track.file.display(), e);
+                audio::is_audio_file(&track.file)
+            }
+        };
+        // Channels extracted from video are cut like audio.
+        let kind = if is_audio || (slice.channels.is_some() && audio::is_audio_file(out_file)) {
+            CutKind::Audio {
+                sample_rate: probed.ok().and_then(|info| info.sample_rate()),
+                fade: self.options.audio_fade,
+            }
+        } else {
+            let burn_in = captions.filter(|_| burn_in);
+            CutKind::Video {
+                transcode: self.needs_transcode(
+                    track,
+                    *start,
+                    *end,
+                    out_file,
+                    burn_in.is_some() || !profile.video_encoders.is_empty(),
+                ),
+                proxy: slice
+                    .proxy
+                    .as_ref()
+                    .zip(profile.proxy.as_ref())
+                    .map(|(file, proxy)| ProxyCut {
+                        output: file.clone(),
+                        height: proxy.height,
+                        video_bitrate: proxy.video_bitrate.clone(),
+                        video_encoder: proxy.video_encoders.first().cloned(),
+                        audio_encoder: proxy.audio_encoders.first().cloned(),
+                    }),
+                burn_in,
+                video_encoder: profile.video_encoders.first().cloned(),
+                audio_encoder: profile.audio_encoders.first().cloned(),
+            }
+        };
+        if let (Some(file), Some(proxy)) = (&slice.proxy, &profile.proxy) {
+            metadata.renditions = renditions(out_file, file, &kind, proxy);
+        }
+        let backend = self.cut_backend();
+        let job = CutJob {
+            source: track.file.clone(),
+            output: out_file.clone(),
+            start: *start,
+            end: *end,
+            kind,
+            channels: slice.channels.clone(),
+            metadata,
+        };
+        backend.cut(&job)?;
+        let mut metadata = job.metadata;
+        debug!("sliced {:?}", out_file);
+
+        // A slice that isn't normalized is removed, so it's cut again rather than taken as done.
+        let remove_slice = || {
+            for file in std::iter::once(out_file).chain(&slice.proxy) {
+                if let Err(e) = std::fs::remove_file(file) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        warn!("failed to remove {}: {}", file.display(), e);
+                    }
+                }
+            }
+        };
+        if let Some(target) = self.options.profile.loudness {
+            let report = backend
+                .normalize_loudness(out_file, target)
+                .with_context(|| format!("failed to normalize loudness of {}", out_file.display()))
+                .inspect_err(|_| remove_slice())?;
+            debug!(
+                "normalized {:?} from {} LUFS to {} LUFS",
+                out_file, report.measured.integrated, report.normalized.integrated
             );
-            debug!("slicing {} to {}", track.file.display(), file_name);
-            let out_file = output_dir.join(file_name);
-            if out_file.exists() {
-                warn!("file already exists, skipping");
-                continue;
+            metadata.loudness = Some(report);
+        }
+
+        // Audio slices are light enough to be their own proxies, so theirs are plain copies rather
+        // than transcodes. Video proxies were cut with the slice, and are normalized on their own.
+        if let Some(proxy) = &slice.proxy {
+            if matches!(job.kind, CutKind::Audio { .. }) {
+                std::fs::copy(out_file, proxy)?;
+            } else if let Some(target) = self.options.profile.loudness {
+                backend
+                    .normalize_loudness(proxy, target)
+                    .with_context(|| format!("failed to normalize loudness of {}", proxy.display()))
+                    .inspect_err(|_| remove_slice())?;
             }
+        }
 
-            let args = if ext == "mp4" {
-                ffmpeg_args_transcode()
-            } else {
-                ffmpeg_args_remux()
-            };
+        if let Err(e) = self.write_sidecars(out_file, &metadata) {
+            error!("failed to write sidecar for {}: {}", out_file.display(), e);
+        }
 
-            let out = Command::new("ffmpeg")
-                .arg("-i")
-                .arg(track.file.as_os_str())
-                .arg("-ss")
-                .arg(start.to_string())
-                .arg("-to")
-                .arg(end.to_string())
-                .arg("-threads")
-                .arg("1")
-                .args(args)
-                .arg(&out_file)
-                .output()?;
-
-            if !out.status.success() {
-                error!("ffmpeg failed: {}", String::from_utf8_lossy(&out.stderr));
-            } else {
-                debug!("sliced {:?}", out_file);
+        Ok(SliceOutput {
+            take_index: slice.take_index,
+            track_index: slice.track_index,
+            file: out_file.clone(),
+            proxy: slice.proxy.clone(),
+            metadata,
+        })
+    }
+
+    /// Find where the speech in a take starts and ends, using the session's first audio track.
+    fn trim_take(
+        &self,
+        session: &Session,
+        take: &Take,
+        options: &TrimOptions,
+    ) -> Option<TrimReport> {
+        let Some(track) = session
+            .tracks
+            .iter()
+            .find(|track| audio::is_audio_file(&track.file))
+        else {
+            warn!(
+                "session {} has no audio track, not trimming",
+                session.session_id
+            );
+            return None;
+        };
+
+        match trim::trim(
+            &track.file,
+            take.start,
+            take.end,
+            track.sync_offset,
+            options,
+        ) {
+            Ok(Some(report)) => {
+                debug!(
+                    "trimmed chunk {} take {}..{} to {}..{}",
+                    take.chunk_id,
+                    report.original_start,
+                    report.original_end,
+                    report.start,
+                    report.end
+                );
+                Some(report)
+            }
+            Ok(None) => {
+                warn!(
+                    "no speech found in chunk {} take {}..{}, not trimming",
+                    take.chunk_id, take.start, take.end
+                );
+                None
             }
+            Err(e) => {
+                warn!(
+                    "failed to analyze chunk {} for trimming: {}",
+                    take.chunk_id, e
+                );
+                None
+            }
+        }
+    }
+
+    fn write_sidecars(&self, out_file: &Path, metadata: &SliceMetadata) -> anyhow::Result<()> {
+        metadata.write_json(&out_file.with_extension("json"))?;
+        if self.options.sidecar_xmp {
+            metadata.write_xmp(&out_file.with_extension("xmp"))?;
+        }
+        Ok(())
+    }
+
+    /// Whether a video slice has to be transcoded rather than stream copied.
+    fn needs_transcode(
+        &self,
+        track: &Track,
+        start: Timestamp,
+        end: Timestamp,
+        out_file: &Path,
+        always: bool,
+    ) -> bool {
+        let info = self.probe.probe(&track.file).ok();
+        if let Some(duration) = info.as_ref().and_then(|info| info.duration) {
+            if end.saturating_duration() > duration {
+                warn!(
+                    "{} ends {:?} after the end of {}, the slice will be short",
+                    out_file.display(),
+                    end.saturating_duration() - duration,
+                    track.file.display()
+                );
+            }
+        }
+
+        // Stream copying can only cut cleanly on a keyframe. Without a keyframe index, assume mp4
+        // files need transcoding. Burning in subtitles or a video encoder in the profile always
+        // means the video has to be transcoded.
+        let on_keyframe = info.and_then(|info| info.is_keyframe(start.saturating_duration()));
+        trace!("{:?} starts on a keyframe: {:?}", out_file, on_keyframe);
+        always
+            || match on_keyframe {
+                Some(on_keyframe) => !on_keyframe,
+                None => track.file.extension().unwrap() == "mp4",
+            }
+    }
+
+    /// What this slicer's ffmpeg supports, asked once and then remembered.
+    pub fn ffmpeg_capabilities(&self) -> anyhow::Result<&FfmpegCapabilities> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities);
         }
+        let capabilities = FfmpegCapabilities::query(|| self.ffmpeg_command())?;
+        Ok(self.capabilities.get_or_init(|| capabilities))
+    }
 
+    /// Check the output profile against what ffmpeg supports and pick its encoders, so a profile
+    /// that can't work is reported before slicing starts rather than for every take.
+    pub fn negotiate_profile(&mut self) -> anyhow::Result<()> {
+        if !self.options.profile.needs_ffmpeg_features() {
+            return Ok(());
+        }
+        let capabilities = self
+            .ffmpeg_capabilities()
+            .context("failed to ask ffmpeg for its encoders and filters")?;
+        let profile = capabilities.negotiate(&self.options.profile)?;
+        for (kind, encoders) in [
+            ("video", &profile.video_encoders),
+            ("audio", &profile.audio_encoders),
+        ] {
+            if let Some(encoder) = encoders.first() {
+                info!("encoding {} with {}", kind, encoder);
+            }
+        }
+        self.options.profile = profile;
         Ok(())
     }
+
+    /// The backend cutting slices, [`AutoBackend`] with this slicer's ffmpeg unless one was set.
+    pub fn cut_backend(&self) -> Arc<dyn CutBackend> {
+        match &self.backend {
+            Some(backend) => backend.clone(),
+            None => Arc::new(AutoBackend {
+                ffmpeg: FfmpegBackend {
+                    ffmpeg: self.ffmpeg.clone(),
+                },
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// The start of the names of every slice of a take, followed by the track.
+pub(crate) fn slice_file_prefix(chunk_id: &str, take_index: usize) -> String {
+    format!("chunk-{chunk_id}-take-{take_index}-track-")
 }
 
-const fn ffmpeg_args_remux() -> &'static [&'static str] {
-    &["-c", "copy"]
+/// Run an ffmpeg command, turning a non-zero exit status into an error with ffmpeg's stderr.
+pub(crate) fn run_ffmpeg(mut cmd: Command) -> anyhow::Result<Output> {
+    let out = cmd.output()?;
+    if !out.status.success() {
+        anyhow::bail!("ffmpeg failed: {}", String::from_utf8_lossy(&out.stderr));
+    }
+    Ok(out)
 }
 
-const fn ffmpeg_args_transcode() -> &'static [&'static str] {
-    &["-c:a", "copy"]
+/// The full slice and its proxy, relative to the folder holding `full/` and `proxy/`.
+fn renditions(full: &Path, proxy: &Path, kind: &CutKind, profile: &ProxyProfile) -> Vec<Rendition> {
+    let relative = |file: &Path| -> PathBuf {
+        file.parent()
+            .and_then(Path::parent)
+            .and_then(|root| file.strip_prefix(root).ok())
+            .unwrap_or(file)
+            .to_owned()
+    };
+    let (video_encoder, proxy_encoder, height) = match kind {
+        CutKind::Audio { .. } => (None, None, None),
+        CutKind::Video {
+            video_encoder,
+            proxy,
+            ..
+        } => (
+            video_encoder.clone(),
+            proxy.as_ref().and_then(|proxy| proxy.video_encoder.clone()),
+            Some(profile.height),
+        ),
+    };
+    vec![
+        Rendition {
+            kind: RenditionKind::Full,
+            file: relative(full),
+            height: None,
+            video_encoder,
+        },
+        Rendition {
+            kind: RenditionKind::Proxy,
+            file: relative(proxy),
+            height,
+            video_encoder: proxy_encoder,
+        },
+    ]
 }
 
 #[derive(Debug)]
@@ -142,16 +922,22 @@ pub struct Session {
     pub tracks: Vec<Track>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Track {
     pub file: PathBuf,
     pub sync_offset: Timestamp,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Take {
     pub session_id: String,
     pub chunk_id: String,
+    /// The script text being read in this take.
+    pub chunk_text: Option<String>,
+    /// The script section the chunk belongs to.
+    pub header: Option<String>,
+    /// Index of this take among the takes of its chunk.
+    pub take_index: usize,
     pub start: Timestamp,
     pub end: Timestamp,
     pub mark: String,
@@ -162,3 +948,378 @@ pub trait IntoSession {
 
     fn takes(&self) -> Vec<Take>;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        backend::{tests::fake_ffmpeg, RecordingBackend},
+        export::tests::take,
+        loudness::LoudnessTarget,
+        wav::tests::{write_test_bwf, write_test_wav, write_test_wav_channels},
+    };
+
+    /// Writes three seconds of silence at 1 kHz to `dir/audio.wav`.
+    fn write_audio(dir: &Path) -> PathBuf {
+        let audio = dir.join("audio.wav");
+        write_test_wav(&audio, 1000, &[0; 3000]);
+        audio
+    }
+
+    fn track(file: PathBuf, sync_offset: Timestamp) -> Track {
+        Track { file, sync_offset }
+    }
+
+    /// Registers a session with the given tracks and takes.
+    fn add_session(slicer: &mut Slicer, session_id: &str, tracks: Vec<Track>, takes: Vec<Take>) {
+        slicer.sessions.write().unwrap().insert(
+            session_id.to_owned(),
+            Session {
+                session_id: session_id.to_owned(),
+                tracks,
+            },
+        );
+        slicer.takes.insert(session_id.to_owned(), takes);
+    }
+
+    #[test]
+    fn plan_then_slice_with_progress() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let audio = write_audio(dir);
+
+        let events = Arc::new(Mutex::new(vec![]));
+        let recorded = events.clone();
+        let mut slicer = Slicer::builder()
+            .output(dir.join("out"))
+            .on_progress(move |progress| recorded.lock().unwrap().push(progress.to_string()))
+            .build();
+        add_session(
+            &mut slicer,
+            "session",
+            vec![track(audio, Timestamp::from_millis(500))],
+            vec![take("1", 0, 0, 1000), take("1", 1, 1500, 2000)],
+        );
+
+        let plan = slicer.plan().unwrap();
+        assert_eq!(plan.slices.len(), 2);
+        assert_eq!(plan.slices[1].start, Timestamp::from_millis(2000));
+        assert!(plan.slices.iter().all(|slice| !slice.file.exists()));
+
+        let outputs = slicer.slice(&plan).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert!(outputs.iter().all(|output| output.file.exists()));
+        let events = events.lock().unwrap();
+        assert_eq!(events[0], "slicing 2 files");
+        assert!(events.iter().any(|event| event.starts_with("[2/2] ")));
+
+        // Slices that are already there aren't planned again.
+        assert!(slicer.plan().unwrap().slices.is_empty());
+    }
+
+    #[test]
+    fn burn_in_needs_subtitles() {
+        let mut slicer = Slicer::builder()
+            .options(SliceOptions {
+                profile: OutputProfile {
+                    name: "burnt".to_owned(),
+                    burn_in_subtitles: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .build();
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        assert!(slicer.plan_in(dir).is_err());
+        slicer.options.subtitles = true;
+        assert!(slicer.plan_in(dir).unwrap().slices.is_empty());
+    }
+
+    #[test]
+    fn jobs_go_to_the_backend() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let audio = write_audio(dir);
+
+        let backend = Arc::new(RecordingBackend::default());
+        let mut slicer = Slicer::builder().backend(backend.clone()).build();
+        add_session(
+            &mut slicer,
+            "session",
+            vec![
+                track(audio, Timestamp::ZERO),
+                // Never opened, since nothing is really cut.
+                track(dir.join("video.mp4"), Timestamp::from_secs(2)),
+            ],
+            vec![take("1", 0, 500, 1500)],
+        );
+
+        let outputs = slicer.perform_slicing(dir.join("out")).unwrap();
+        assert_eq!(outputs.len(), 2);
+        let mut jobs = backend.jobs();
+        jobs.sort_by(|a, b| a.output.cmp(&b.output));
+        assert_eq!(
+            jobs[0].kind,
+            CutKind::Audio {
+                sample_rate: Some(1000),
+                fade: Duration::ZERO
+            }
+        );
+        assert_eq!(
+            jobs[1].kind,
+            CutKind::Video {
+                transcode: true,
+                burn_in: None,
+                video_encoder: None,
+                audio_encoder: None,
+                proxy: None,
+            }
+        );
+        assert_eq!(jobs[1].start, Timestamp::from_millis(2500));
+        assert!(jobs[1].output.ends_with("chunk-1-take-0-track-1-good.mp4"));
+        assert!(dir.join("out/chunk-1-take-0-track-1-good.json").exists());
+    }
+
+    #[test]
+    fn proxies_mirror_full_slices() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let audio = write_audio(dir);
+        let options = SliceOptions {
+            profile: OutputProfile {
+                proxy: Some(ProxyProfile::default()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Audio slices are copied.
+        let mut slicer = Slicer::builder().options(options.clone()).build();
+        add_session(
+            &mut slicer,
+            "session",
+            vec![track(audio, Timestamp::ZERO)],
+            vec![take("1", 0, 500, 1500)],
+        );
+        let outputs = slicer.perform_slicing(dir.join("out")).unwrap();
+        let name = "chunk-1-take-0-track-0-good.wav";
+        assert_eq!(outputs[0].file, dir.join("out/full").join(name));
+        assert_eq!(outputs[0].proxy, Some(dir.join("out/proxy").join(name)));
+        assert_eq!(
+            std::fs::read(dir.join("out/full").join(name)).unwrap(),
+            std::fs::read(dir.join("out/proxy").join(name)).unwrap()
+        );
+        let files: Vec<_> = outputs[0]
+            .metadata
+            .renditions
+            .iter()
+            .map(|rendition| (rendition.kind, rendition.file.clone()))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                (RenditionKind::Full, Path::new("full").join(name)),
+                (RenditionKind::Proxy, Path::new("proxy").join(name)),
+            ]
+        );
+        assert!(dir
+            .join("out/full/chunk-1-take-0-track-0-good.json")
+            .exists());
+
+        // A missing proxy is made again, along with its slice.
+        assert!(slicer.perform_slicing(dir.join("out")).unwrap().is_empty());
+        std::fs::remove_file(dir.join("out/proxy").join(name)).unwrap();
+        assert_eq!(slicer.perform_slicing(dir.join("out")).unwrap().len(), 1);
+        assert!(dir.join("out/proxy").join(name).exists());
+
+        // Video proxies are cut with the slice.
+        let backend = Arc::new(RecordingBackend::default());
+        let mut slicer = Slicer::builder()
+            .options(options)
+            .backend(backend.clone())
+            .build();
+        add_session(
+            &mut slicer,
+            "session",
+            vec![track(dir.join("video.mp4"), Timestamp::ZERO)],
+            vec![take("1", 0, 500, 1500)],
+        );
+        slicer.perform_slicing(dir.join("video-out")).unwrap();
+        let CutKind::Video {
+            proxy: Some(proxy), ..
+        } = &backend.jobs()[0].kind
+        else {
+            panic!("no proxy cut");
+        };
+        assert_eq!(proxy.height, 540);
+        assert_eq!(
+            proxy.output,
+            dir.join("video-out/proxy/chunk-1-take-0-track-0-good.mp4")
+        );
+    }
+
+    #[test]
+    fn split_channels_into_mono_slices() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let audio = dir.join("audio.wav");
+        let samples: Vec<i16> = (0..3000).flat_map(|_| [1, 2, 3]).collect();
+        write_test_wav_channels(&audio, 1000, 3, &samples);
+
+        let options = SliceOptions {
+            profile: OutputProfile {
+                channel_maps: vec![serde_json::from_str(
+                    r#"{ "track": 0, "mode": "split", "channels": [1, 2], "names": ["lav"] }"#,
+                )
+                .unwrap()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut slicer = Slicer::builder().options(options).build();
+        add_session(
+            &mut slicer,
+            "session",
+            vec![track(audio, Timestamp::ZERO)],
+            vec![take("1", 0, 500, 1500)],
+        );
+
+        let mut outputs = slicer.perform_slicing(dir.join("out")).unwrap();
+        outputs.sort_by(|a, b| a.file.cmp(&b.file));
+        let files: Vec<_> = outputs
+            .iter()
+            .map(|output| output.file.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "chunk-1-take-0-track-0-ch3-good.wav",
+                "chunk-1-take-0-track-0-lav-good.wav"
+            ]
+        );
+        assert_eq!(outputs[0].metadata.channels, ["FC"]);
+        assert_eq!(outputs[1].metadata.channels, ["FR"]);
+        let lav = WavFile::open(&outputs[1].file).unwrap();
+        assert_eq!(lav.format.channels, 1);
+        assert_eq!(lav.frames(), 1000);
+    }
+
+    #[test]
+    fn slices_that_fail_to_normalize_are_removed() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let audio = write_audio(dir);
+        let options = SliceOptions {
+            profile: OutputProfile {
+                loudness: Some(LoudnessTarget::PODCAST),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // Which can't normalize anything.
+        let backend = Arc::new(RecordingBackend::default());
+        let mut slicer = Slicer::builder()
+            .options(options)
+            .backend(backend.clone())
+            .output(dir.join("out"))
+            .build();
+        add_session(
+            &mut slicer,
+            "session",
+            vec![track(audio, Timestamp::ZERO)],
+            vec![take("1", 0, 500, 1500)],
+        );
+
+        let plan = slicer.plan().unwrap();
+        assert!(slicer.slice_planned(&plan.slices[0]).is_err());
+        assert_eq!(backend.jobs().len(), 1);
+        assert!(!plan.slices[0].file.exists());
+        assert_eq!(slicer.plan().unwrap().slices.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn normalized_slices_keep_bwf_metadata() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let audio = dir.join("audio.wav");
+        write_test_bwf(&audio, 1000, &[0; 3000], 5000);
+        let normalized = dir.join("normalized.wav");
+        write_test_wav(&normalized, 1000, &[0; 1000]);
+        let backend = AutoBackend {
+            ffmpeg: FfmpegBackend {
+                ffmpeg: Some(fake_ffmpeg(dir, &normalized)),
+            },
+            ..Default::default()
+        };
+        let options = SliceOptions {
+            profile: OutputProfile {
+                loudness: Some(LoudnessTarget::PODCAST),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut slicer = Slicer::builder()
+            .options(options)
+            .backend(Arc::new(backend))
+            .build();
+        add_session(
+            &mut slicer,
+            "session",
+            vec![track(audio, Timestamp::ZERO)],
+            vec![take("1", 0, 500, 1500)],
+        );
+
+        let outputs = slicer.perform_slicing(dir.join("out")).unwrap();
+        assert!(outputs[0].metadata.loudness.is_some());
+        let wav = WavFile::open(&outputs[0].file).unwrap();
+        let info = BwfInfo::read(&wav);
+        assert_eq!(info.bext.unwrap().time_reference, 5500);
+        assert_eq!(info.ixml.unwrap().scene.as_deref(), Some("1A"));
+        assert!(wav.chunk(b"LIST").unwrap().starts_with(b"INFOINAM"));
+    }
+
+    #[test]
+    fn takes_are_numbered_in_order() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let audio = write_audio(dir);
+        let mut slicer = Slicer::builder()
+            .backend(Arc::new(RecordingBackend::default()))
+            .build();
+        for session_id in ["b", "a"] {
+            let takes = ["10", "2", "1"]
+                .map(|chunk| Take {
+                    session_id: session_id.to_owned(),
+                    ..take(chunk, 0, 0, 1000)
+                })
+                .to_vec();
+            let tracks = vec![track(audio.clone(), Timestamp::ZERO)];
+            add_session(&mut slicer, session_id, tracks, takes);
+        }
+
+        let plan = slicer.plan_in(dir.join("out")).unwrap();
+        let mut numbered: Vec<_> = plan
+            .slices
+            .iter()
+            .map(|slice| {
+                let name = slice.file.file_name().unwrap().to_string_lossy();
+                (name.into_owned(), slice.take.session_id.clone())
+            })
+            .collect();
+        numbered.sort();
+        let expected = [
+            ("chunk-1-take-0-track-0-good.wav", "a"),
+            ("chunk-1-take-3-track-0-good.wav", "b"),
+            ("chunk-10-take-2-track-0-good.wav", "a"),
+            ("chunk-10-take-5-track-0-good.wav", "b"),
+            ("chunk-2-take-1-track-0-good.wav", "a"),
+            ("chunk-2-take-4-track-0-good.wav", "b"),
+        ]
+        .map(|(name, session)| (name.to_owned(), session.to_owned()));
+        assert_eq!(numbered, expected);
+    }
+}
@@ -0,0 +1,133 @@
+//! CMX3600 edit decision lists.
+
+use std::fmt::Write;
+
+use log::*;
+
+use super::Timeline;
+use crate::timecode::Timecode;
+
+/// Record timecode of the first event. Timelines conventionally start at one hour.
+const RECORD_START: &str = "01:00:00:00";
+
+pub fn to_edl(timeline: &Timeline) -> String {
+    let mut edl = String::new();
+    writeln!(edl, "TITLE: {}", timeline.name).unwrap();
+    if timeline.rate.is_drop_frame() {
+        writeln!(edl, "FCM: DROP FRAME").unwrap();
+    } else {
+        writeln!(edl, "FCM: NON-DROP FRAME").unwrap();
+    }
+    writeln!(edl).unwrap();
+
+    let rate = timeline.rate;
+    let record_start = Timecode::parse(RECORD_START, rate)
+        .expect("one hour is a timecode at every rate")
+        .frames;
+    let mut event = 0;
+    for clip in &timeline.clips {
+        let rec_in = record_start + timeline.frames(clip.record_in);
+        let rec_out = record_start + timeline.frames(clip.record_out());
+
+        let mut audio_channel = 0;
+        for track in &clip.tracks {
+            // Sources can't start before their first frame, so a take starting before its track
+            // (eg. from a negative sync offset) has the missing part cut off the event.
+            let source_in = rate.frames(track.source_in);
+            let source_out = rate.frames(track.source_out);
+            let missing = (-source_in).max(0);
+            if source_out <= 0 || rec_in + missing >= rec_out {
+                warn!(
+                    "{} is before the start of {}, leaving it out of the EDL",
+                    clip.name,
+                    track.file.display()
+                );
+                continue;
+            }
+
+            event += 1;
+            let channel = if track.is_audio {
+                audio_channel += 1;
+                if audio_channel == 1 {
+                    "A".to_owned()
+                } else {
+                    format!("A{audio_channel}")
+                }
+            } else {
+                "V".to_owned()
+            };
+            writeln!(
+                edl,
+                "{:03}  AX       {:<5} C        {} {} {} {}",
+                event,
+                channel,
+                Timecode::new(source_in + missing, rate),
+                Timecode::new(source_out, rate),
+                Timecode::new(rec_in + missing, rate),
+                Timecode::new(rec_out, rate)
+            )
+            .unwrap();
+            writeln!(edl, "* FROM CLIP NAME: {}", clip.name).unwrap();
+            writeln!(edl, "* SOURCE FILE: {}", track.file.display()).unwrap();
+            if let Some(text) = &clip.take.chunk_text {
+                writeln!(edl, "* COMMENT: {}", text.replace('\n', " ")).unwrap();
+            }
+            writeln!(edl).unwrap();
+        }
+    }
+    edl
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::timecode::FrameRate;
+
+    #[test]
+    fn events_reference_sources() {
+        let edl = to_edl(&crate::export::tests::timeline());
+
+        assert!(edl.starts_with("TITLE: test\nFCM: NON-DROP FRAME\n"));
+        assert!(edl.contains(
+            "001  AX       A     C        00:00:01:00 00:00:03:00 01:00:00:00 01:00:02:00\n* FROM CLIP NAME: chunk-1-take-0-good\n* SOURCE FILE: /media/audio.wav\n"
+        ));
+        assert!(edl.contains(
+            "004  AX       V     C        00:00:15:00 00:00:16:00 01:00:02:00 01:00:03:00\n"
+        ));
+    }
+
+    #[test]
+    fn record_starts_at_one_hour() {
+        for rate in [
+            FrameRate::FPS_23_976,
+            FrameRate::FPS_29_97,
+            FrameRate::FPS_29_97_DF,
+        ] {
+            let mut timeline = crate::export::tests::timeline();
+            timeline.rate = rate;
+            let edl = to_edl(&timeline);
+            let separator = if rate.is_drop_frame() { ';' } else { ':' };
+            assert!(
+                edl.contains(&format!(" 01:00:00{separator}00 01:00:02{separator}00\n")),
+                "{edl}"
+            );
+        }
+    }
+
+    #[test]
+    fn sources_before_the_start_are_cut() {
+        let mut timeline = crate::export::tests::timeline();
+        for track in &mut timeline.clips[0].tracks {
+            track.source_in = track.source_in - Duration::from_secs(2);
+            track.source_out = track.source_out - Duration::from_secs(2);
+        }
+        let edl = to_edl(&timeline);
+
+        assert!(edl.contains(
+            "001  AX       A     C        00:00:00:00 00:00:01:00 01:00:01:00 01:00:02:00\n"
+        ));
+        assert!(!edl.contains(" -"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Final Cut Pro XML (FCPXML 1.9).
+
+use std::{collections::BTreeMap, fmt::Write, path::PathBuf, time::Duration};
+
+use super::{file_url, Timeline};
+use crate::sidecar::xml_escape;
+
+fn time(timeline: &Timeline, duration: Duration) -> String {
+    let frames = timeline.frames(duration);
+    if frames == 0 {
+        "0s".to_owned()
+    } else {
+        format!(
+            "{}/{}s",
+            frames * timeline.rate.den() as i64,
+            timeline.rate.num()
+        )
+    }
+}
+
+pub fn to_fcpxml(timeline: &Timeline) -> String {
+    // Every source file becomes one asset, shared between the clips that use it.
+    let mut assets: BTreeMap<PathBuf, (String, bool)> = BTreeMap::new();
+    for track in timeline.clips.iter().flat_map(|clip| &clip.tracks) {
+        let next_id = format!("r{}", assets.len() + 2);
+        assets
+            .entry(track.file.clone())
+            .or_insert((next_id, track.is_audio));
+    }
+
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(xml, "<!DOCTYPE fcpxml>").unwrap();
+    writeln!(xml, r#"<fcpxml version="1.9">"#).unwrap();
+    writeln!(xml, "  <resources>").unwrap();
+    writeln!(
+        xml,
+        r#"    <format id="r1" frameDuration="{}/{}s"/>"#,
+        timeline.rate.den(),
+        timeline.rate.num()
+    )
+    .unwrap();
+    for (file, (id, is_audio)) in &assets {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        writeln!(
+            xml,
+            r#"    <asset id="{}" name="{}" start="0s" hasVideo="{}" hasAudio="1" format="r1">"#,
+            id,
+            xml_escape(&name),
+            if *is_audio { 0 } else { 1 }
+        )
+        .unwrap();
+        writeln!(
+            xml,
+            r#"      <media-rep kind="original-media" src="{}"/>"#,
+            xml_escape(&file_url(file))
+        )
+        .unwrap();
+        writeln!(xml, "    </asset>").unwrap();
+    }
+    writeln!(xml, "  </resources>").unwrap();
+
+    let name = xml_escape(&timeline.name);
+    writeln!(xml, "  <library>").unwrap();
+    writeln!(xml, r#"    <event name="{name}">"#).unwrap();
+    writeln!(xml, r#"      <project name="{name}">"#).unwrap();
+    writeln!(
+        xml,
+        r#"        <sequence format="r1" duration="{}" tcStart="0s" tcFormat="{}">"#,
+        time(timeline, timeline.duration()),
+        if timeline.rate.is_drop_frame() {
+            "DF"
+        } else {
+            "NDF"
+        }
+    )
+    .unwrap();
+    writeln!(xml, "          <spine>").unwrap();
+
+    for clip in &timeline.clips {
+        // The first video track goes in the spine, everything else is connected to it.
+        let primary = clip
+            .tracks
+            .iter()
+            .position(|track| !track.is_audio)
+            .unwrap_or(0);
+        let Some(track) = clip.tracks.get(primary) else {
+            continue;
+        };
+        let start = time(timeline, track.source_in.saturating_duration());
+        let duration = time(timeline, clip.duration());
+        writeln!(
+            xml,
+            r#"            <asset-clip ref="{}" name="{}" offset="{}" start="{}" duration="{}" format="r1">"#,
+            assets[&track.file].0,
+            xml_escape(&clip.name),
+            time(timeline, clip.record_in),
+            start,
+            duration
+        )
+        .unwrap();
+        if let Some(text) = &clip.take.chunk_text {
+            writeln!(xml, "              <note>{}</note>", xml_escape(text)).unwrap();
+        }
+
+        let (mut audio_lane, mut video_lane) = (0, 0);
+        for (idx, connected) in clip.tracks.iter().enumerate() {
+            if idx == primary {
+                continue;
+            }
+            let lane = if connected.is_audio {
+                audio_lane -= 1;
+                audio_lane
+            } else {
+                video_lane += 1;
+                video_lane
+            };
+            writeln!(
+                xml,
+                r#"              <asset-clip ref="{}" lane="{}" offset="{}" start="{}" duration="{}"/>"#,
+                assets[&connected.file].0,
+                lane,
+                start,
+                time(timeline, connected.source_in.saturating_duration()),
+                duration
+            )
+            .unwrap();
+        }
+        writeln!(xml, "            </asset-clip>").unwrap();
+    }
+
+    writeln!(xml, "          </spine>").unwrap();
+    writeln!(xml, "        </sequence>").unwrap();
+    writeln!(xml, "      </project>").unwrap();
+    writeln!(xml, "    </event>").unwrap();
+    writeln!(xml, "  </library>").unwrap();
+    writeln!(xml, "</fcpxml>").unwrap();
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn video_is_primary_and_audio_is_connected() {
+        let xml = to_fcpxml(&crate::export::tests::timeline());
+
+        assert!(xml.contains(r#"<asset id="r2" name="audio.wav" start="0s" hasVideo="0""#));
+        assert!(xml.contains(r#"<media-rep kind="original-media" src="file:///media/video.mp4"/>"#));
+        assert!(xml.contains(
+            r#"<asset-clip ref="r3" name="chunk-1-take-0-good" offset="0s" start="275/25s" duration="50/25s" format="r1">"#
+        ));
+        assert!(xml.contains(
+            r#"<asset-clip ref="r2" lane="-1" offset="275/25s" start="25/25s" duration="50/25s"/>"#
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+//! Export takes as timelines that reference the original media, for conforming in an NLE
+//! instead of slicing.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use log::*;
+
+use crate::{
+    data::{Session, Slicer, Take},
+    timecode::FrameRate,
+    timestamp::Timestamp,
+};
+
+pub mod cmx3600;
+pub mod fcpxml;
+pub mod otio;
+
+/// Selects which takes end up in an export.
+#[derive(Debug, Clone, Default)]
+pub struct TakeFilter {
+    /// Only include takes with one of these marks. Empty means all marks.
+    pub marks: Vec<String>,
+    /// Only include these chunks. Empty means all chunks.
+    pub chunks: Vec<String>,
+    /// Only include these sessions. Empty means all sessions.
+    pub sessions: Vec<String>,
+    /// Only include takes that start at or after this point of their session.
+    pub from: Option<Timestamp>,
+    /// Only include takes that end at or before this point of their session.
+    pub to: Option<Timestamp>,
+}
+
+impl TakeFilter {
+    pub fn matches(&self, take: &Take) -> bool {
+        (self.marks.is_empty() || self.marks.contains(&take.mark))
+            && (self.chunks.is_empty() || self.chunks.contains(&take.chunk_id))
+            && (self.sessions.is_empty() || self.sessions.contains(&take.session_id))
+            && self.from.is_none_or(|from| take.start >= from)
+            && self.to.is_none_or(|to| take.end <= to)
+    }
+}
+
+/// Sort key that orders chunks numerically when their ids are numbers.
+pub fn chunk_order(take: &Take) -> (Option<u64>, String, String, usize) {
+    (
+        take.chunk_id.parse().ok(),
+        take.chunk_id.clone(),
+        take.session_id.clone(),
+        take.take_index,
+    )
+}
+
+/// One track of a take, placed on a timeline.
+#[derive(Debug, Clone)]
+pub struct ClipTrack {
+    pub track_index: usize,
+    pub file: PathBuf,
+    pub is_audio: bool,
+    /// Position in the source file, with the sync offset applied.
+    pub source_in: Timestamp,
+    pub source_out: Timestamp,
+}
+
+impl ClipTrack {
+    /// Every track of `session`, cut to `take`.
+    pub fn for_take(take: &Take, session: &Session) -> Vec<Self> {
+        session
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(track_index, track)| ClipTrack {
+                track_index,
+                file: track.file.clone(),
+                is_audio: crate::audio::is_audio_file(&track.file),
+                source_in: take.start + track.sync_offset,
+                source_out: take.end + track.sync_offset,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Clip {
+    pub name: String,
+    pub take: Take,
+    /// Position on the timeline, relative to its start.
+    pub record_in: Duration,
+    pub tracks: Vec<ClipTrack>,
+}
+
+impl Clip {
+    pub fn duration(&self) -> Duration {
+        (self.take.end - self.take.start).saturating_duration()
+    }
+
+    pub fn record_out(&self) -> Duration {
+        self.record_in + self.duration()
+    }
+}
+
+/// Takes laid out back to back, in chunk order.
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    pub name: String,
+    pub rate: FrameRate,
+    pub clips: Vec<Clip>,
+}
+
+impl Timeline {
+    pub fn new(name: impl Into<String>, rate: FrameRate) -> Self {
+        Self {
+            name: name.into(),
+            rate,
+            clips: vec![],
+        }
+    }
+
+    /// Build a timeline out of every take known to the slicer that passes `filter`.
+    pub fn from_slicer(slicer: &Slicer, filter: &TakeFilter, name: &str, rate: FrameRate) -> Self {
+        let mut takes: Vec<&Take> = slicer
+            .takes
+            .values()
+            .flatten()
+            .filter(|take| filter.matches(take))
+            .collect();
+        takes.sort_by_key(|take| chunk_order(take));
+
+        let sessions = slicer.sessions.read().unwrap();
+        let mut timeline = Self::new(name, rate);
+        for take in takes {
+            let Some(session) = sessions.get(&take.session_id) else {
+                continue;
+            };
+            timeline.push(take.clone(), ClipTrack::for_take(take, session));
+        }
+        timeline
+    }
+
+    /// Append a take to the end of the timeline.
+    pub fn push(&mut self, take: Take, tracks: Vec<ClipTrack>) {
+        let record_in = self.duration();
+        self.clips.push(Clip {
+            name: format!(
+                "chunk-{}-take-{}-{}",
+                take.chunk_id, take.take_index, take.mark
+            ),
+            take,
+            record_in,
+            tracks,
+        });
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.clips
+            .last()
+            .map(|clip| clip.record_out())
+            .unwrap_or_default()
+    }
+
+    /// Convert a duration to a whole number of frames, rounding to the nearest frame.
+    pub fn frames(&self, duration: Duration) -> i64 {
+        self.rate.frames(Timestamp::ZERO + duration)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Cmx3600,
+    FcpXml,
+    Otio,
+}
+
+impl ExportFormat {
+    pub fn render(&self, timeline: &Timeline) -> anyhow::Result<String> {
+        Ok(match self {
+            ExportFormat::Cmx3600 => cmx3600::to_edl(timeline),
+            ExportFormat::FcpXml => fcpxml::to_fcpxml(timeline),
+            ExportFormat::Otio => serde_json::to_string_pretty(&otio::to_otio(timeline))?,
+        })
+    }
+
+    pub fn write(&self, timeline: &Timeline, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.render(timeline)?)?;
+        Ok(())
+    }
+}
+
+/// The frame rate of the first video track that has one, by session id.
+pub fn probed_frame_rate(slicer: &Slicer) -> Option<FrameRate> {
+    let sessions = slicer.sessions.read().unwrap();
+    let mut sessions: Vec<&Session> = sessions.values().collect();
+    sessions.sort_by_key(|session| &session.session_id);
+    sessions
+        .iter()
+        .flat_map(|session| &session.tracks)
+        .filter(|track| !crate::audio::is_audio_file(&track.file))
+        .find_map(|track| {
+            let info = slicer.probe.probe(&track.file).ok()?;
+            info.first(crate::probe::StreamKind::Video)?.frame_rate
+        })
+}
+
+/// The frame rate to export at: `given`, or that of the first video track, or 30 fps.
+pub fn timeline_frame_rate(slicer: &Slicer, given: Option<FrameRate>) -> FrameRate {
+    given.unwrap_or_else(|| {
+        probed_frame_rate(slicer).unwrap_or_else(|| {
+            info!("no video frame rate found, exporting at 30 fps");
+            FrameRate::FPS_30
+        })
+    })
+}
+
+/// Build a `file://` URL for a path, as used by FCPXML and OTIO media references.
+pub fn file_url(path: &Path) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    let mut url = "file://".to_owned();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' => url.push_str("%20"),
+            '#' => url.push_str("%23"),
+            '%' => url.push_str("%25"),
+            '?' => url.push_str("%3F"),
+            '\\' => url.push('/'),
+            c => url.push(c),
+        }
+    }
+    url
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn take(chunk: &str, take_index: usize, start_ms: i64, end_ms: i64) -> Take {
+        Take {
+            session_id: "session".to_owned(),
+            chunk_id: chunk.to_owned(),
+            chunk_text: None,
+            header: None,
+            take_index,
+            start: Timestamp::from_millis(start_ms),
+            end: Timestamp::from_millis(end_ms),
+            mark: "good".to_owned(),
+        }
+    }
+
+    pub(crate) fn timeline() -> Timeline {
+        let mut timeline = Timeline::new("test", FrameRate::FPS_25);
+        for take in [take("1", 0, 1000, 3000), take("2", 1, 5000, 6000)] {
+            let tracks = vec![
+                ClipTrack {
+                    track_index: 0,
+                    file: PathBuf::from("/media/audio.wav"),
+                    is_audio: true,
+                    source_in: take.start,
+                    source_out: take.end,
+                },
+                ClipTrack {
+                    track_index: 1,
+                    file: PathBuf::from("/media/video.mp4"),
+                    is_audio: false,
+                    source_in: take.start + Duration::from_secs(10),
+                    source_out: take.end + Duration::from_secs(10),
+                },
+            ];
+            timeline.push(take, tracks);
+        }
+        timeline
+    }
+
+    #[test]
+    fn chunks_sort_numerically() {
+        let mut takes = [take("10", 0, 0, 1), take("9", 0, 0, 1), take("9", 1, 0, 1)];
+        takes.sort_by_key(chunk_order);
+
+        let order: Vec<_> = takes
+            .iter()
+            .map(|t| (t.chunk_id.as_str(), t.take_index))
+            .collect();
+        assert_eq!(order, [("9", 0), ("9", 1), ("10", 0)]);
+    }
+
+    #[test]
+    fn clips_are_back_to_back() {
+        let timeline = timeline();
+
+        assert_eq!(timeline.clips[0].record_in, Duration::ZERO);
+        assert_eq!(timeline.clips[1].record_in, Duration::from_secs(2));
+        assert_eq!(timeline.duration(), Duration::from_secs(3));
+    }
+}
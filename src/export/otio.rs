@@ -0,0 +1,113 @@
+//! OpenTimelineIO JSON (`.otio`).
+
+use serde_json::{json, Value};
+
+use super::{file_url, Timeline};
+
+fn rational_time(timeline: &Timeline, duration: std::time::Duration) -> Value {
+    json!({
+        "OTIO_SCHEMA": "RationalTime.1",
+        "rate": timeline.rate.as_f64(),
+        "value": timeline.frames(duration) as f64,
+    })
+}
+
+pub fn to_otio(timeline: &Timeline) -> Value {
+    // One OTIO track per slicer track index, so that every take's tracks line up.
+    let track_count = timeline
+        .clips
+        .iter()
+        .map(|clip| clip.tracks.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut tracks = vec![];
+    for track_index in 0..track_count {
+        let mut children = vec![];
+        let mut position = std::time::Duration::ZERO;
+        let mut kind = "Video";
+        for clip in &timeline.clips {
+            let Some(track) = clip.tracks.iter().find(|t| t.track_index == track_index) else {
+                continue;
+            };
+            if track.is_audio {
+                kind = "Audio";
+            }
+            if clip.record_in > position {
+                children.push(json!({
+                    "OTIO_SCHEMA": "Gap.1",
+                    "name": "",
+                    "source_range": {
+                        "OTIO_SCHEMA": "TimeRange.1",
+                        "start_time": rational_time(timeline, std::time::Duration::ZERO),
+                        "duration": rational_time(timeline, clip.record_in - position),
+                    },
+                }));
+            }
+            children.push(json!({
+                "OTIO_SCHEMA": "Clip.1",
+                "name": clip.name,
+                "metadata": {
+                    "session_slicer": {
+                        "session_id": clip.take.session_id,
+                        "chunk_index": clip.take.chunk_id,
+                        "take_index": clip.take.take_index,
+                        "mark": clip.take.mark,
+                        "chunk_text": clip.take.chunk_text,
+                    },
+                },
+                "source_range": {
+                    "OTIO_SCHEMA": "TimeRange.1",
+                    "start_time": rational_time(timeline, track.source_in.saturating_duration()),
+                    "duration": rational_time(timeline, clip.duration()),
+                },
+                "media_reference": {
+                    "OTIO_SCHEMA": "ExternalReference.1",
+                    "target_url": file_url(&track.file),
+                },
+            }));
+            position = clip.record_out();
+        }
+        tracks.push(json!({
+            "OTIO_SCHEMA": "Track.1",
+            "name": format!("track {track_index}"),
+            "kind": kind,
+            "children": children,
+        }));
+    }
+
+    json!({
+        "OTIO_SCHEMA": "Timeline.1",
+        "name": timeline.name,
+        "global_start_time": null,
+        "tracks": {
+            "OTIO_SCHEMA": "Stack.1",
+            "name": "tracks",
+            "children": tracks,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_otio_track_per_slicer_track() {
+        let otio = to_otio(&crate::export::tests::timeline());
+
+        let tracks = otio["tracks"]["children"].as_array().unwrap();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[0]["kind"], "Audio");
+        assert_eq!(tracks[1]["kind"], "Video");
+
+        let clip = &tracks[1]["children"][1];
+        assert_eq!(clip["name"], "chunk-2-take-1-good");
+        assert_eq!(clip["source_range"]["start_time"]["value"], 375.0);
+        assert_eq!(clip["source_range"]["duration"]["value"], 25.0);
+        assert_eq!(
+            clip["media_reference"]["target_url"],
+            "file:///media/video.mp4"
+        );
+    }
+}
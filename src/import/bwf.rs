@@ -0,0 +1,110 @@
+//! Field recordings, named by the scene and take their recorder wrote into iXML.
+
+use std::path::Path;
+
+use crate::{
+    bwf::BwfInfo,
+    data::{Session, Take, Track},
+    import::{ImportedSession, Importer},
+    timestamp::Timestamp,
+    wav::WavFile,
+};
+
+/// A WAV file with a scene and take in its iXML chunk, as one take covering the whole file.
+pub struct BwfImporter;
+
+impl BwfImporter {
+    fn info(path: &Path) -> Option<(WavFile, BwfInfo)> {
+        let wav = WavFile::open(path).ok()?;
+        let info = BwfInfo::read(&wav);
+        Some((wav, info))
+    }
+}
+
+impl Importer for BwfImporter {
+    fn name(&self) -> &'static str {
+        "bwf"
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        path.is_file()
+            && crate::audio::is_audio_file(path)
+            && Self::info(path).is_some_and(|(_, info)| {
+                info.ixml
+                    .is_some_and(|ixml| ixml.scene.is_some() || ixml.take.is_some())
+            })
+    }
+
+    fn import(&self, path: &Path) -> anyhow::Result<ImportedSession> {
+        let wav = WavFile::open(path)?;
+        let ixml = BwfInfo::read(&wav).ixml.unwrap_or_default();
+        let session_id = path.file_stem().unwrap().to_string_lossy().into_owned();
+
+        let take = Take {
+            session_id: session_id.clone(),
+            chunk_id: ixml.scene.clone().unwrap_or_else(|| session_id.clone()),
+            chunk_text: ixml.note.clone(),
+            header: ixml.project.clone(),
+            take_index: ixml
+                .take
+                .as_deref()
+                .and_then(|take| {
+                    take.trim_start_matches(|c: char| !c.is_ascii_digit())
+                        .parse()
+                        .ok()
+                })
+                .unwrap_or_default(),
+            start: Timestamp::ZERO,
+            end: wav.duration().try_into()?,
+            mark: if ixml.circled { "good" } else { "unmarked" }.to_owned(),
+        };
+        Ok(ImportedSession {
+            session: Session {
+                session_id,
+                tracks: vec![Track {
+                    file: path.to_owned(),
+                    sync_offset: Timestamp::ZERO,
+                }],
+            },
+            takes: vec![take],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{bwf::tests::IXML, wav::tests::write_test_wav};
+
+    #[test]
+    fn scene_and_take_name_the_take() {
+        let tmp = crate::tests::temp_dir();
+        let path = tmp.path().join("recording.wav");
+        write_test_wav(&path, 1000, &[0; 1500]);
+        assert!(!BwfImporter.detect(&path));
+
+        let mut wav = WavFile::open(&path).unwrap();
+        wav.set_chunk(*b"iXML", IXML.as_bytes().to_vec());
+        let tagged = path.with_file_name("tagged.wav");
+        wav.write_slice(
+            &tagged,
+            crate::audio::SampleRange {
+                start: 0,
+                end: 1500,
+                sample_rate: 1000,
+            },
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert!(BwfImporter.detect(&tagged));
+
+        let session = BwfImporter.import(&tagged).unwrap();
+        let take = &session.takes[0];
+        assert_eq!(take.chunk_id, "1A");
+        assert_eq!(take.take_index, 3);
+        assert_eq!(take.mark, "good");
+        assert_eq!(take.end, Timestamp::from_millis(1500));
+    }
+}
@@ -0,0 +1,680 @@
+//! Takes marked as regions or ranged markers in a DAW or NLE, read from its project or marker
+//! export.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use log::*;
+
+use crate::{
+    data::{Session, Take, Track},
+    import::{ImportedSession, Importer},
+    timecode::{FrameRate, Timecode},
+    timestamp::Timestamp,
+};
+
+/// Extensions of media that marker exports without a media reference are looked for with.
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "wav", "flac", "aif", "aiff", "mp3", "m4a", "mov", "mp4", "mxf",
+];
+
+/// Settings shared by the marker importers, for what the marker files don't say themselves.
+#[derive(Debug, Clone, Default)]
+pub struct MarkerOptions {
+    /// The marked media. Defaults to the media referenced by the markers, or the media with the
+    /// same name next to them.
+    pub media: Option<PathBuf>,
+    /// Rate of the timecodes in Premiere and Resolve exports.
+    pub frame_rate: Option<FrameRate>,
+    /// Subtracted from every marker, eg. `01:00:00:00@25` for a timeline starting at one hour.
+    pub time_base: Timestamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkerFormat {
+    /// Regions of a Reaper `.RPP` project.
+    Reaper,
+    /// Marker list CSV exported from Audition.
+    Audition,
+    /// Marker list exported from Premiere Pro.
+    Premiere,
+    /// Timeline markers exported from Resolve as an EDL.
+    Resolve,
+    /// Label track exported from Audacity.
+    Audacity,
+}
+
+impl MarkerFormat {
+    pub const ALL: [MarkerFormat; 5] = [
+        MarkerFormat::Reaper,
+        MarkerFormat::Audition,
+        MarkerFormat::Premiere,
+        MarkerFormat::Resolve,
+        MarkerFormat::Audacity,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MarkerFormat::Reaper => "reaper",
+            MarkerFormat::Audition => "audition",
+            MarkerFormat::Premiere => "premiere",
+            MarkerFormat::Resolve => "resolve",
+            MarkerFormat::Audacity => "audacity",
+        }
+    }
+
+    /// Whether `path` looks like a file of this format, by extension and first line.
+    pub fn detect(self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let expected: &[&str] = match self {
+            MarkerFormat::Reaper => &["rpp"],
+            MarkerFormat::Audition => &["csv"],
+            MarkerFormat::Premiere => &["csv", "txt"],
+            MarkerFormat::Resolve => &["edl"],
+            MarkerFormat::Audacity => &["txt"],
+        };
+        if !path.is_file() || !expected.contains(&extension.as_str()) {
+            return false;
+        }
+        let Ok(text) = read_text(path) else {
+            return false;
+        };
+        let first_line = text.lines().find(|line| !line.trim().is_empty());
+        match self {
+            MarkerFormat::Reaper => text.trim_start().starts_with("<REAPER_PROJECT"),
+            MarkerFormat::Audition => {
+                first_line.is_some_and(|line| line.starts_with("Name\tStart\tDuration"))
+            }
+            MarkerFormat::Premiere => first_line
+                .is_some_and(|line| line.starts_with("Marker Name\t") && line.contains("\tIn\t")),
+            MarkerFormat::Resolve => text.lines().any(|line| line.contains("|M:")),
+            MarkerFormat::Audacity => first_line.is_some_and(|line| {
+                let mut fields = line.split('\t');
+                fields.next().is_some_and(|f| f.parse::<f64>().is_ok())
+                    && fields.next().is_some_and(|f| f.parse::<f64>().is_ok())
+            }),
+        }
+    }
+
+    /// Read the ranged markers of a file, and the media it references, if it does.
+    pub fn parse(
+        self,
+        text: &str,
+        options: &MarkerOptions,
+    ) -> anyhow::Result<(Vec<Marker>, Option<MarkedMedia>)> {
+        match self {
+            MarkerFormat::Reaper => parse_reaper(text),
+            MarkerFormat::Audition => Ok((parse_audition(text, options)?, None)),
+            MarkerFormat::Premiere => Ok((parse_premiere(text, options)?, None)),
+            MarkerFormat::Resolve => Ok((parse_resolve(text, options)?, None)),
+            MarkerFormat::Audacity => Ok((parse_audacity(text)?, None)),
+        }
+    }
+}
+
+/// A marked range, in the time of the project or timeline it was marked in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub name: String,
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub mark: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// Media referenced by a marker file, and where project time 0 is in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkedMedia {
+    pub file: PathBuf,
+    pub sync_offset: Timestamp,
+}
+
+/// Read a text file, which Premiere writes as UTF-16.
+fn read_text(path: &Path) -> anyhow::Result<String> {
+    let bytes = std::fs::read(path)?;
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| from([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16(&units).context("invalid UTF-16")
+    };
+    match bytes.as_slice() {
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xef, 0xbb, 0xbf, rest @ ..] => Ok(String::from_utf8(rest.to_vec())?),
+        _ => Ok(String::from_utf8(bytes)?),
+    }
+}
+
+/// Split a line of a Reaper project into tokens, which may be quoted with `"`, `'` or `` ` ``.
+fn reaper_tokens(line: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let mut token = String::new();
+        if matches!(c, '"' | '\'' | '`') {
+            chars.next();
+            token.extend(chars.by_ref().take_while(|&next| next != c));
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+        }
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Regions of a Reaper project, and the source of its first media item.
+///
+/// A region is a pair of `MARKER` lines with the same index and the region flag set, the first
+/// holding the start and name, the second the end.
+fn parse_reaper(text: &str) -> anyhow::Result<(Vec<Marker>, Option<MarkedMedia>)> {
+    let mut open_regions: HashMap<String, (Timestamp, String)> = HashMap::new();
+    let mut markers = vec![];
+    let (mut position, mut offset) = (Timestamp::ZERO, Timestamp::ZERO);
+    let mut media = None;
+    for line in text.lines() {
+        let tokens = reaper_tokens(line);
+        let Some(keyword) = tokens.first() else {
+            continue;
+        };
+        let time = |i: usize| -> anyhow::Result<Timestamp> {
+            let value = tokens.get(i).context("missing time")?;
+            Ok(Timestamp::from_secs_f64(value.parse().with_context(
+                || format!("invalid time {value:?} in {:?}", line.trim()),
+            )?))
+        };
+        match keyword.as_str() {
+            "MARKER" => {
+                let is_region = tokens
+                    .get(4)
+                    .and_then(|flags| flags.parse::<u32>().ok())
+                    .is_some_and(|flags| flags & 1 != 0);
+                if !is_region {
+                    continue;
+                }
+                let index = tokens.get(1).context("marker without index")?.clone();
+                let at = time(2)?;
+                match open_regions.remove(&index) {
+                    Some((start, name)) => markers.push(Marker {
+                        name,
+                        start,
+                        end: at,
+                        mark: None,
+                        comment: None,
+                    }),
+                    None => {
+                        let name = tokens.get(3).cloned().unwrap_or_default();
+                        open_regions.insert(index, (at, name));
+                    }
+                }
+            }
+            "<ITEM" => (position, offset) = (Timestamp::ZERO, Timestamp::ZERO),
+            "POSITION" => position = time(1)?,
+            "SOFFS" => offset = time(1)?,
+            "FILE" if media.is_none() => {
+                let file = tokens.get(1).context("FILE without a path")?;
+                media = Some(MarkedMedia {
+                    file: PathBuf::from(file),
+                    sync_offset: offset - position,
+                });
+            }
+            _ => {}
+        }
+    }
+    for (index, (_, name)) in open_regions {
+        warn!("region {index} ({name:?}) has no end, skipping it");
+    }
+    markers.sort_by_key(|marker| marker.start);
+    Ok((markers, media))
+}
+
+/// Rows of a tab separated marker list, by column name.
+fn tab_rows(text: &str) -> Vec<HashMap<&str, &str>> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return vec![];
+    };
+    let columns: Vec<&str> = header.split('\t').map(str::trim).collect();
+    lines
+        .map(|line| {
+            columns
+                .iter()
+                .copied()
+                .zip(line.split('\t').map(str::trim))
+                .collect()
+        })
+        .collect()
+}
+
+fn required<'a>(
+    row: &HashMap<&str, &'a str>,
+    column: &str,
+    line: usize,
+) -> anyhow::Result<&'a str> {
+    row.get(column)
+        .copied()
+        .filter(|value| !value.is_empty())
+        .with_context(|| format!("marker {line} has no {column}"))
+}
+
+fn optional(row: &HashMap<&str, &str>, column: &str) -> Option<String> {
+    row.get(column)
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+/// A timecode at the rate given with the options.
+fn timecode(value: &str, options: &MarkerOptions) -> anyhow::Result<Timestamp> {
+    let rate = options
+        .frame_rate
+        .context("timecode markers need a frame rate")?;
+    Ok(Timecode::parse(value, rate)?.to_timestamp() - options.time_base)
+}
+
+/// Audition writes times as `M:SS.mmm`, or as timecode when the session displays timecode.
+fn audition_time(value: &str, options: &MarkerOptions) -> anyhow::Result<Timestamp> {
+    if value.matches([':', ';']).count() == 3 {
+        timecode(value, options)
+    } else {
+        Ok(Timestamp::parse(value)? - options.time_base)
+    }
+}
+
+fn parse_audition(text: &str, options: &MarkerOptions) -> anyhow::Result<Vec<Marker>> {
+    let mut markers = vec![];
+    for (i, row) in tab_rows(text).iter().enumerate() {
+        let line = i + 1;
+        let start = audition_time(required(row, "Start", line)?, options)?;
+        let duration =
+            audition_time(required(row, "Duration", line)?, options)? + options.time_base;
+        if duration <= Timestamp::ZERO {
+            debug!("skipping cue marker {line}");
+            continue;
+        }
+        markers.push(Marker {
+            name: optional(row, "Name").unwrap_or_else(|| line.to_string()),
+            start,
+            end: start + duration,
+            mark: None,
+            comment: optional(row, "Description"),
+        });
+    }
+    Ok(markers)
+}
+
+fn parse_premiere(text: &str, options: &MarkerOptions) -> anyhow::Result<Vec<Marker>> {
+    let mut markers = vec![];
+    for (i, row) in tab_rows(text).iter().enumerate() {
+        let line = i + 1;
+        let start = timecode(required(row, "In", line)?, options)?;
+        let end = timecode(required(row, "Out", line)?, options)?;
+        if end <= start {
+            debug!("skipping point marker {line}");
+            continue;
+        }
+        markers.push(Marker {
+            name: optional(row, "Marker Name").unwrap_or_else(|| line.to_string()),
+            start,
+            end,
+            mark: None,
+            comment: optional(row, "Description"),
+        });
+    }
+    Ok(markers)
+}
+
+/// Resolve writes every marker as an event, followed by a line like
+/// `|C:ResolveColorBlue |M:Intro |D:50` with its color, name and duration in frames.
+fn parse_resolve(text: &str, options: &MarkerOptions) -> anyhow::Result<Vec<Marker>> {
+    let rate = options
+        .frame_rate
+        .context("Resolve markers need a frame rate")?;
+    let mut markers = vec![];
+    let mut start = None;
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first().is_some_and(|f| f.parse::<u32>().is_ok()) && fields.len() >= 8 {
+            // The record in point.
+            start = Some(timecode(fields[6], options)?);
+            continue;
+        }
+        if !line.contains("|M:") {
+            continue;
+        }
+        let Some(start) = start.take() else {
+            continue;
+        };
+        let mut name = String::new();
+        let (mut color, mut frames) = (None, 1);
+        for field in line.split('|').map(str::trim) {
+            if let Some(value) = field.strip_prefix("M:") {
+                name = value.to_owned();
+            } else if let Some(value) = field.strip_prefix("C:") {
+                color = Some(value.trim_start_matches("ResolveColor").to_lowercase());
+            } else if let Some(value) = field.strip_prefix("D:") {
+                frames = value
+                    .parse()
+                    .with_context(|| format!("invalid marker duration {value:?}"))?;
+            }
+        }
+        if frames <= 1 {
+            debug!("skipping point marker {name:?}");
+            continue;
+        }
+        markers.push(Marker {
+            name,
+            start,
+            end: start + rate.timestamp(frames),
+            mark: color,
+            comment: None,
+        });
+    }
+    Ok(markers)
+}
+
+/// Audacity labels are `start\tend\tlabel` in seconds. Lines starting with `\` hold the frequency
+/// range of the label above.
+fn parse_audacity(text: &str) -> anyhow::Result<Vec<Marker>> {
+    let mut markers = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('\\') {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let mut time = |what| -> anyhow::Result<Timestamp> {
+            let value = fields.next().unwrap_or_default().trim();
+            Ok(Timestamp::from_secs_f64(value.parse().with_context(
+                || format!("invalid {what} {value:?} on line {}", i + 1),
+            )?))
+        };
+        let (start, end) = (time("start")?, time("end")?);
+        let name = fields.next().unwrap_or_default().trim().to_owned();
+        if end <= start {
+            debug!("skipping point label {name:?}");
+            continue;
+        }
+        markers.push(Marker {
+            name,
+            start,
+            end,
+            mark: None,
+            comment: None,
+        });
+    }
+    Ok(markers)
+}
+
+/// Turn markers into takes, with every marker name a chunk and repeated names more takes of it.
+/// Unnamed markers are chunks of their own, named after their position, eg. `marker-3`.
+pub fn takes(session_id: &str, markers: &[Marker]) -> Vec<Take> {
+    let mut takes_per_chunk: HashMap<String, usize> = HashMap::new();
+    markers
+        .iter()
+        .enumerate()
+        .map(|(i, marker)| {
+            let chunk_id = if marker.name.is_empty() {
+                format!("marker-{}", i + 1)
+            } else {
+                marker.name.clone()
+            };
+            let take_index = takes_per_chunk.entry(chunk_id.clone()).or_default();
+            *take_index += 1;
+            Take {
+                session_id: session_id.to_owned(),
+                chunk_id,
+                chunk_text: marker.comment.clone(),
+                header: None,
+                take_index: *take_index - 1,
+                start: marker.start,
+                end: marker.end,
+                mark: marker.mark.clone().unwrap_or_else(|| "unmarked".to_owned()),
+            }
+        })
+        .collect()
+}
+
+/// The media next to `path` with the same name, eg. `take1.wav` for `take1.txt`.
+fn sibling_media(path: &Path) -> Option<PathBuf> {
+    MEDIA_EXTENSIONS
+        .iter()
+        .flat_map(|ext| [ext.to_string(), ext.to_uppercase()])
+        .map(|ext| path.with_extension(ext))
+        .find(|candidate| candidate.is_file())
+}
+
+pub struct MarkerImporter {
+    pub format: MarkerFormat,
+    pub options: MarkerOptions,
+}
+
+impl Importer for MarkerImporter {
+    fn name(&self) -> &'static str {
+        self.format.name()
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        self.format.detect(path)
+    }
+
+    fn import(&self, path: &Path) -> anyhow::Result<ImportedSession> {
+        let text = read_text(path)?;
+        let (markers, referenced) = self.format.parse(&text, &self.options)?;
+
+        let media = match (&self.options.media, referenced) {
+            (Some(file), _) => MarkedMedia {
+                file: file.clone(),
+                sync_offset: Timestamp::ZERO,
+            },
+            (None, Some(media)) => media,
+            (None, None) => MarkedMedia {
+                file: sibling_media(path).with_context(|| {
+                    format!(
+                        "no media named like {} found, give one with --marker-media",
+                        path.display()
+                    )
+                })?,
+                sync_offset: Timestamp::ZERO,
+            },
+        };
+        let file = match path.parent() {
+            Some(dir) if media.file.is_relative() => dir.join(&media.file),
+            _ => media.file,
+        };
+
+        let session_id = path.file_stem().unwrap().to_string_lossy().into_owned();
+        Ok(ImportedSession {
+            takes: takes(&session_id, &markers),
+            session: Session {
+                session_id,
+                tracks: vec![Track {
+                    file,
+                    sync_offset: media.sync_offset,
+                }],
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: i64) -> Timestamp {
+        Timestamp::from_millis(ms)
+    }
+
+    fn ranges(markers: &[Marker]) -> Vec<(&str, Timestamp, Timestamp)> {
+        markers
+            .iter()
+            .map(|m| (m.name.as_str(), m.start, m.end))
+            .collect()
+    }
+
+    #[test]
+    fn reaper_regions() {
+        let rpp = r#"<REAPER_PROJECT 0.1 "6.80/linux64" 1690000000
+  MARKER 1 1.5 "Intro take" 1 0 1 B {6F3A} 0
+  MARKER 2 3 cue 0 0 1
+  MARKER 1 4 "" 1
+  MARKER 3 6 'Outro "b"' 1
+  MARKER 3 8.25 "" 1
+  <TRACK {1}
+    NAME VO
+    <ITEM
+      POSITION 0.5
+      SOFFS 2
+      <SOURCE WAVE
+        FILE "vo/take 1.wav"
+      >
+    >
+  >
+>"#;
+        let (markers, media) = MarkerFormat::Reaper
+            .parse(rpp, &MarkerOptions::default())
+            .unwrap();
+
+        assert_eq!(
+            ranges(&markers),
+            [
+                ("Intro take", ms(1500), ms(4000)),
+                ("Outro \"b\"", ms(6000), ms(8250))
+            ]
+        );
+        assert_eq!(
+            media,
+            Some(MarkedMedia {
+                file: "vo/take 1.wav".into(),
+                sync_offset: ms(1500),
+            })
+        );
+    }
+
+    #[test]
+    fn audition_markers() {
+        let csv = "Name\tStart\tDuration\tTime Format\tType\tDescription\n\
+                   Intro\t0:01.500\t0:02.000\tdecimal\tCue\tfirst try\n\
+                   Marker 02\t0:05.000\t0:00.000\tdecimal\tCue\t\n";
+        let markers = parse_audition(csv, &MarkerOptions::default()).unwrap();
+
+        assert_eq!(ranges(&markers), [("Intro", ms(1500), ms(3500))]);
+        assert_eq!(markers[0].comment.as_deref(), Some("first try"));
+    }
+
+    #[test]
+    fn premiere_markers() {
+        let text = "Marker Name\tDescription\tIn\tOut\tDuration\tMarker Type\n\
+                    Intro\t\t00:00:01:12\t00:00:03:00\t00:00:01:13\tComment\n\
+                    Note\t\t00:00:04:00\t00:00:04:00\t00:00:00:00\tComment\n";
+        let options = MarkerOptions {
+            frame_rate: Some(FrameRate::FPS_25),
+            ..Default::default()
+        };
+        let markers = parse_premiere(text, &options).unwrap();
+
+        assert_eq!(ranges(&markers), [("Intro", ms(1480), ms(3000))]);
+        assert!(parse_premiere(text, &MarkerOptions::default()).is_err());
+    }
+
+    #[test]
+    fn resolve_markers() {
+        let edl = "TITLE: Timeline 1\nFCM: NON-DROP FRAME\n\n\
+                   001  001      V     C        01:00:02:00 01:00:02:01 01:00:02:00 01:00:02:01  \n \
+                   |C:ResolveColorGreen |M:Intro |D:50\n\
+                   002  001      V     C        01:00:05:00 01:00:05:01 01:00:05:00 01:00:05:01  \n \
+                   |C:ResolveColorBlue |M:Note |D:1\n";
+        let options = MarkerOptions {
+            frame_rate: Some(FrameRate::FPS_25),
+            time_base: Timecode::parse("01:00:00:00", FrameRate::FPS_25)
+                .unwrap()
+                .to_timestamp(),
+            ..Default::default()
+        };
+        let markers = parse_resolve(edl, &options).unwrap();
+
+        assert_eq!(ranges(&markers), [("Intro", ms(2000), ms(4000))]);
+        assert_eq!(markers[0].mark.as_deref(), Some("green"));
+    }
+
+    #[test]
+    fn audacity_labels_become_takes() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let labels = dir.join("vo.txt");
+        std::fs::write(
+            &labels,
+            "1.5\t3.25\tIntro\n\\\t100.0\t2000.0\n4\t4\tpoint\n5\t7\tIntro\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("vo.wav"), "").unwrap();
+
+        let importer = MarkerImporter {
+            format: MarkerFormat::Audacity,
+            options: MarkerOptions::default(),
+        };
+        assert!(importer.detect(&labels));
+        assert!(!MarkerFormat::Premiere.detect(&labels));
+        let session = importer.import(&labels).unwrap();
+
+        assert_eq!(session.session.session_id, "vo");
+        assert_eq!(session.session.tracks[0].file, dir.join("vo.wav"));
+        let takes: Vec<_> = session
+            .takes
+            .iter()
+            .map(|t| (t.chunk_id.as_str(), t.take_index, t.start, t.end))
+            .collect();
+        assert_eq!(
+            takes,
+            [
+                ("Intro", 0, ms(1500), ms(3250)),
+                ("Intro", 1, ms(5000), ms(7000))
+            ]
+        );
+    }
+
+    #[test]
+    fn unnamed_markers_are_chunks_of_their_own() {
+        let marker = |name: &str, start_ms| Marker {
+            name: name.to_owned(),
+            start: ms(start_ms),
+            end: ms(start_ms + 500),
+            mark: None,
+            comment: None,
+        };
+        let markers = [
+            marker("", 0),
+            marker("2", 1000),
+            marker("", 2000),
+            marker("2", 3000),
+            marker("", 4000),
+        ];
+
+        let takes: Vec<_> = takes("session", &markers)
+            .iter()
+            .map(|t| (t.chunk_id.clone(), t.take_index))
+            .collect();
+
+        assert_eq!(
+            takes,
+            [
+                ("marker-1".to_owned(), 0),
+                ("2".to_owned(), 0),
+                ("marker-3".to_owned(), 0),
+                ("2".to_owned(), 1),
+                ("marker-5".to_owned(), 0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Turn the entries of a sessions directory into sessions, whatever recorded them.
+
+use std::path::{Path, PathBuf};
+
+use log::*;
+
+use crate::{
+    data::{IntoSession, Session, Slicer, Take},
+    import::markers::{MarkerFormat, MarkerImporter, MarkerOptions},
+};
+
+pub mod bwf;
+pub mod markers;
+pub mod take_list;
+pub mod teleprompt;
+
+/// Reads one kind of recording session from disk.
+pub trait Importer: Send + Sync {
+    /// Name used to pick this importer with `--session-format`.
+    fn name(&self) -> &'static str;
+
+    /// Whether `path` looks like a session this importer can read.
+    fn detect(&self, path: &Path) -> bool;
+
+    fn import(&self, path: &Path) -> anyhow::Result<ImportedSession>;
+}
+
+/// A session read by an [`Importer`], ready to be registered with a [`Slicer`].
+#[derive(Debug)]
+pub struct ImportedSession {
+    pub session: Session,
+    pub takes: Vec<Take>,
+}
+
+impl IntoSession for ImportedSession {
+    fn into_session(self) -> Session {
+        self.session
+    }
+
+    fn takes(&self) -> Vec<Take> {
+        self.takes.clone()
+    }
+}
+
+/// What happened to the entries of a sessions directory.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Session ids that were registered.
+    pub imported: Vec<String>,
+    /// Entries that no importer recognised, or that failed to import.
+    pub failed: Vec<(PathBuf, anyhow::Error)>,
+}
+
+pub struct ImporterRegistry {
+    importers: Vec<Box<dyn Importer>>,
+}
+
+impl Default for ImporterRegistry {
+    fn default() -> Self {
+        Self::new(MarkerOptions::default())
+    }
+}
+
+impl ImporterRegistry {
+    /// The builtin importers: teleprompt-studio sessions, the marker formats with `markers`, and
+    /// field recordings.
+    pub fn new(markers: MarkerOptions) -> Self {
+        let mut registry = Self::empty();
+        registry.register(teleprompt::TelepromptStudioImporter);
+        for format in MarkerFormat::ALL {
+            registry.register(MarkerImporter {
+                format,
+                options: markers.clone(),
+            });
+        }
+        registry.register(bwf::BwfImporter);
+        registry
+    }
+
+    pub fn empty() -> Self {
+        Self { importers: vec![] }
+    }
+
+    /// Add an importer. Importers registered earlier win when several detect the same entry.
+    pub fn register(&mut self, importer: impl Importer + 'static) {
+        self.importers.push(Box::new(importer));
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.importers.iter().map(|i| i.name()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<&dyn Importer> {
+        self.importers
+            .iter()
+            .find(|i| i.name() == name)
+            .map(|i| i.as_ref())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "unknown session format {:?}, expected one of: {}",
+                    name,
+                    self.names().join(", ")
+                )
+            })
+    }
+
+    /// The first importer that recognises `path`.
+    pub fn detect(&self, path: &Path) -> Option<&dyn Importer> {
+        self.importers
+            .iter()
+            .find(|i| i.detect(path))
+            .map(|i| i.as_ref())
+    }
+
+    /// Import `path` with the importer called `format`, or the one that recognises it.
+    pub fn import(&self, path: &Path, format: Option<&str>) -> anyhow::Result<ImportedSession> {
+        let importer = match format {
+            Some(format) => self.get(format)?,
+            None => self.detect(path).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "not a session of any known format ({})",
+                    self.names().join(", ")
+                )
+            })?,
+        };
+        debug!("importing {} as {}", path.display(), importer.name());
+        importer.import(path)
+    }
+
+    /// Import every entry of `dir` into `slicer`. Entries that fail are reported and skipped, and
+    /// hidden entries like `.DS_Store` are ignored.
+    pub fn import_dir(
+        &self,
+        dir: &Path,
+        format: Option<&str>,
+        slicer: &mut Slicer,
+    ) -> anyhow::Result<ImportReport> {
+        if let Some(format) = format {
+            self.get(format)?;
+        }
+        let mut entries = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+
+        let mut report = ImportReport::default();
+        for path in entries {
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden {
+                debug!("ignoring {}", path.display());
+                continue;
+            }
+            match self.import(&path, format) {
+                Ok(session) => {
+                    trace!("imported session: {:?}", session);
+                    report.imported.push(session.session.session_id.clone());
+                    slicer.register_session(session);
+                }
+                Err(e) => {
+                    warn!("skipping {}: {:#}", path.display(), e);
+                    report.failed.push((path, e));
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_teleprompt_session(dir: &Path) {
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(
+            dir.join("metadata.json"),
+            r#"{"SyncOffset": "00:00:01.000"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("takes.csv"),
+            "header,chunk_index,chunk_text,take_index,take_mark,take_start,take_end\n\
+             Intro,0,Hello,0,good,00:00:02.000,00:00:04.000\n",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn bad_entries_are_skipped() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        write_teleprompt_session(&dir.join("1"));
+        std::fs::create_dir_all(dir.join("2")).unwrap();
+        std::fs::write(dir.join("2/metadata.json"), "{}").unwrap();
+        std::fs::write(dir.join("2/takes.csv"), "").unwrap();
+        std::fs::write(dir.join(".DS_Store"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let mut slicer = Slicer::new();
+        let report = ImporterRegistry::default()
+            .import_dir(dir, None, &mut slicer)
+            .unwrap();
+
+        assert_eq!(report.imported, ["1"]);
+        let failed: Vec<_> = report
+            .failed
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(failed, ["2", "notes.txt"]);
+        assert_eq!(slicer.takes["1"].len(), 1);
+    }
+
+    #[test]
+    fn unknown_format_is_an_error() {
+        let registry = ImporterRegistry::default();
+        let mut slicer = Slicer::new();
+        let tmp = crate::tests::temp_dir();
+        let err = registry
+            .import_dir(tmp.path(), Some("nope"), &mut slicer)
+            .unwrap_err();
+        assert!(err.to_string().contains("teleprompt-studio"));
+    }
+}
@@ -0,0 +1,463 @@
+//! Take lists from spreadsheets and other tools, as CSV or JSON, read through a column mapping.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{Session, Take, Track},
+    import::{ImportedSession, Importer},
+    timecode::{FrameRate, Timecode},
+    timestamp::Timestamp,
+};
+
+/// Which columns of a take list hold what. Columns are named by their header, or by their
+/// index when the CSV has no header row.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TakeListColumns {
+    pub start: String,
+    /// Either `end` or `duration` has to be given.
+    pub end: Option<String>,
+    pub duration: Option<String>,
+    pub mark: Option<String>,
+    /// Groups takes into chunks. Rows without a label become a chunk of their own.
+    pub label: Option<String>,
+    /// Index of the take among the takes of its chunk. Counted in order of appearance if missing.
+    pub take: Option<String>,
+    pub text: Option<String>,
+    pub header: Option<String>,
+}
+
+/// How the times in a take list are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeFormat {
+    /// Anything [`Timestamp::parse`] accepts.
+    #[default]
+    Timestamp,
+    Seconds,
+    Milliseconds,
+    /// `HH:MM:SS:FF` at `frame_rate`.
+    Timecode,
+    /// A frame count at `frame_rate`.
+    Frames,
+    /// A sample count at `sample_rate`.
+    Samples,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TakeListMapping {
+    pub columns: TakeListColumns,
+    pub time_format: TimeFormat,
+    pub frame_rate: Option<FrameRate>,
+    pub sample_rate: Option<u32>,
+    /// Subtracted from every time, eg. `01:00:00:00@25` for lists in record timecode.
+    pub time_base: Timestamp,
+    /// Mark of takes without one.
+    pub default_mark: String,
+    /// Whether the first CSV row holds the column names.
+    pub has_headers: bool,
+    /// JSON pointer to the array of takes, when it isn't the whole document.
+    pub records: Option<String>,
+    /// Session id, instead of the take list's file name.
+    pub session_id: Option<String>,
+    /// The media the takes were recorded in. Relative paths are relative to the take list.
+    pub media: Option<PathBuf>,
+    pub sync_offset: Timestamp,
+}
+
+impl Default for TakeListMapping {
+    fn default() -> Self {
+        Self {
+            columns: TakeListColumns::default(),
+            time_format: TimeFormat::default(),
+            frame_rate: None,
+            sample_rate: None,
+            time_base: Timestamp::ZERO,
+            default_mark: "unmarked".to_owned(),
+            has_headers: true,
+            records: None,
+            session_id: None,
+            media: None,
+            sync_offset: Timestamp::ZERO,
+        }
+    }
+}
+
+impl TakeListMapping {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let mapping = serde_json::from_reader(file)?;
+        Ok(mapping)
+    }
+
+    fn parse_time(&self, value: &str) -> anyhow::Result<Timestamp> {
+        let value = value.trim();
+        let frame_rate = || {
+            self.frame_rate
+                .context("the time format needs a frame_rate in the mapping")
+        };
+        let time = match self.time_format {
+            TimeFormat::Timestamp => Timestamp::parse(value)?,
+            TimeFormat::Seconds => Timestamp::from_secs_f64(value.parse()?),
+            TimeFormat::Milliseconds => Timestamp::from_secs_f64(value.parse::<f64>()? / 1000.0),
+            TimeFormat::Timecode => Timecode::parse(value, frame_rate()?)?.to_timestamp(),
+            TimeFormat::Frames => frame_rate()?.timestamp(value.parse()?),
+            TimeFormat::Samples => Timestamp::from_samples(
+                value.parse()?,
+                self.sample_rate
+                    .context("the time format needs a sample_rate in the mapping")?,
+            ),
+        };
+        Ok(time - self.time_base)
+    }
+
+    /// Whether `columns` has what the mapping needs to find where takes start and end.
+    fn has_time_columns(&self, columns: &[String]) -> bool {
+        let has = |column: &Option<String>| column.as_ref().is_some_and(|c| columns.contains(c));
+        columns.contains(&self.columns.start)
+            && (has(&self.columns.end) || has(&self.columns.duration))
+    }
+
+    /// Turn the rows of a take list into takes of `session_id`.
+    pub fn takes(
+        &self,
+        session_id: &str,
+        rows: &[HashMap<String, String>],
+    ) -> anyhow::Result<Vec<Take>> {
+        let columns = &self.columns;
+        if columns.end.is_none() && columns.duration.is_none() {
+            anyhow::bail!("the mapping needs an end or a duration column");
+        }
+
+        let mut takes_per_chunk = HashMap::new();
+        let mut takes = vec![];
+        for (i, row) in rows.iter().enumerate() {
+            let line = i + 1;
+            let get = |column: &Option<String>| {
+                column
+                    .as_ref()
+                    .and_then(|column| row.get(column))
+                    .map(|value| value.trim())
+                    .filter(|value| !value.is_empty())
+            };
+            let time = |column: &Option<String>, what| {
+                let value = get(column).with_context(|| format!("take {line} has no {what}"))?;
+                self.parse_time(value)
+                    .with_context(|| format!("invalid {what} {value:?} of take {line}"))
+            };
+
+            let start = time(&Some(columns.start.clone()), "start")?;
+            let end = match &columns.end {
+                Some(_) => time(&columns.end, "end")?,
+                None => start + (time(&columns.duration, "duration")? + self.time_base),
+            };
+            let chunk_id = get(&columns.label)
+                .map(str::to_owned)
+                .unwrap_or_else(|| line.to_string());
+            let count = takes_per_chunk.entry(chunk_id.clone()).or_insert(0);
+            let take_index = match get(&columns.take) {
+                Some(take) => take
+                    .parse()
+                    .with_context(|| format!("invalid take number {take:?} of take {line}"))?,
+                None => *count,
+            };
+            *count += 1;
+
+            takes.push(Take {
+                session_id: session_id.to_owned(),
+                chunk_id,
+                chunk_text: get(&columns.text).map(str::to_owned),
+                header: get(&columns.header).map(str::to_owned),
+                take_index,
+                start,
+                end,
+                mark: get(&columns.mark).unwrap_or(&self.default_mark).to_owned(),
+            });
+        }
+        Ok(takes)
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+/// The columns of a CSV take list, from its header or, without one, its first row.
+fn csv_columns(path: &Path, has_headers: bool) -> anyhow::Result<Vec<String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .flexible(true)
+        .from_path(path)?;
+    if has_headers {
+        return Ok(reader
+            .headers()?
+            .iter()
+            .map(|h| h.trim().to_owned())
+            .collect());
+    }
+    let len = reader.records().next().transpose()?.map_or(0, |r| r.len());
+    Ok((0..len).map(|i| i.to_string()).collect())
+}
+
+/// Read the rows of a CSV take list.
+fn csv_rows(path: &Path, has_headers: bool) -> anyhow::Result<Vec<HashMap<String, String>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .flexible(true)
+        .from_path(path)?;
+    let headers: Vec<String> = if has_headers {
+        reader
+            .headers()?
+            .iter()
+            .map(|h| h.trim().to_owned())
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record?;
+        let row = record
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let column = headers.get(i).cloned().unwrap_or_else(|| i.to_string());
+                (column, value.to_owned())
+            })
+            .collect();
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+/// Read the rows of a JSON take list, an array of objects.
+fn json_rows(path: &Path, records: Option<&str>) -> anyhow::Result<Vec<HashMap<String, String>>> {
+    let file = std::fs::File::open(path)?;
+    let document: serde_json::Value = serde_json::from_reader(std::io::BufReader::new(file))?;
+    let records = match records {
+        Some(pointer) => document
+            .pointer(pointer)
+            .with_context(|| format!("no {pointer:?} in the take list"))?,
+        None => &document,
+    };
+    let records = records
+        .as_array()
+        .context("the take list is not an array of takes")?;
+
+    records
+        .iter()
+        .map(|record| {
+            let record = record.as_object().context("a take is not an object")?;
+            Ok(record
+                .iter()
+                .filter_map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::String(s) => s.clone(),
+                        serde_json::Value::Number(n) => n.to_string(),
+                        serde_json::Value::Bool(b) => b.to_string(),
+                        _ => return None,
+                    };
+                    Some((key.clone(), value))
+                })
+                .collect())
+        })
+        .collect()
+}
+
+/// A `.csv` or `.json` take list, with the takes in the media given by its mapping.
+pub struct TakeListImporter {
+    pub mapping: TakeListMapping,
+}
+
+impl Importer for TakeListImporter {
+    fn name(&self) -> &'static str {
+        "take-list"
+    }
+
+    /// Only lists with the mapped start and end columns are take lists, so other CSV and JSON
+    /// files, eg. a syncer cache, are left alone.
+    fn detect(&self, path: &Path) -> bool {
+        if !path.is_file() {
+            return false;
+        }
+        let columns = if is_json(path) {
+            json_rows(path, self.mapping.records.as_deref())
+                .map(|rows| rows.first().map(|row| row.keys().cloned().collect()))
+                .map(Option::unwrap_or_default)
+        } else if is_csv(path) {
+            csv_columns(path, self.mapping.has_headers)
+        } else {
+            return false;
+        };
+        columns.is_ok_and(|columns| self.mapping.has_time_columns(&columns))
+    }
+
+    fn import(&self, path: &Path) -> anyhow::Result<ImportedSession> {
+        let mapping = &self.mapping;
+        let media = mapping
+            .media
+            .as_ref()
+            .context("take lists need a media file in the mapping, or --take-list-media")?;
+        let media = match path.parent() {
+            Some(dir) if media.is_relative() => dir.join(media),
+            _ => media.clone(),
+        };
+
+        let rows = if is_json(path) {
+            json_rows(path, mapping.records.as_deref())?
+        } else {
+            csv_rows(path, mapping.has_headers)?
+        };
+
+        let session_id = match &mapping.session_id {
+            Some(id) => id.clone(),
+            None => path.file_stem().unwrap().to_string_lossy().into_owned(),
+        };
+        let takes = mapping.takes(&session_id, &rows)?;
+        Ok(ImportedSession {
+            session: Session {
+                session_id,
+                tracks: vec![Track {
+                    file: media,
+                    sync_offset: mapping.sync_offset,
+                }],
+            },
+            takes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: i64) -> Timestamp {
+        Timestamp::from_millis(ms)
+    }
+
+    #[test]
+    fn csv_with_timecodes() {
+        let tmp = crate::tests::temp_dir();
+        let path = tmp.path().join("take-list.csv");
+        std::fs::write(
+            &path,
+            "Scene,Take,In,Out,Circled\n\
+             1A,1,01:00:01:00,01:00:02:12,\n\
+             1A,2,01:00:03:00,01:00:04:00,good\n\
+             ,,01:00:05:00,01:00:06:00,\n",
+        )
+        .unwrap();
+        let mapping: TakeListMapping = serde_json::from_str(
+            r#"{
+                "columns": {"start": "In", "end": "Out", "mark": "Circled", "label": "Scene", "take": "Take"},
+                "time_format": "timecode",
+                "frame_rate": "25",
+                "time_base": "01:00:00:00@25",
+                "default_mark": "ok",
+                "media": "/media/A001.mov"
+            }"#,
+        )
+        .unwrap();
+
+        let importer = TakeListImporter { mapping };
+        assert!(importer.detect(&path));
+        let notes = tmp.path().join("notes.csv");
+        std::fs::write(&notes, "Scene,Note\n1A,too fast\n").unwrap();
+        assert!(!importer.detect(&notes));
+        let session = importer.import(&path).unwrap();
+
+        assert_eq!(session.session.session_id, "take-list");
+        assert_eq!(session.session.tracks[0].file, Path::new("/media/A001.mov"));
+        let takes: Vec<_> = session
+            .takes
+            .iter()
+            .map(|t| {
+                (
+                    t.chunk_id.as_str(),
+                    t.take_index,
+                    t.start,
+                    t.end,
+                    t.mark.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            takes,
+            [
+                ("1A", 1, ms(1000), ms(2480), "ok"),
+                ("1A", 2, ms(3000), ms(4000), "good"),
+                ("3", 0, ms(5000), ms(6000), "ok"),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_with_durations() {
+        let tmp = crate::tests::temp_dir();
+        let path = tmp.path().join("take-list.json");
+        std::fs::write(
+            &path,
+            r#"{"takes": [
+                {"label": "intro", "at": 1.5, "length": 2},
+                {"label": "intro", "at": 10, "length": 0.5}
+            ]}"#,
+        )
+        .unwrap();
+        let mapping: TakeListMapping = serde_json::from_str(
+            r#"{
+                "columns": {"start": "at", "duration": "length", "label": "label"},
+                "time_format": "seconds",
+                "records": "/takes",
+                "media": "audio.wav"
+            }"#,
+        )
+        .unwrap();
+
+        let importer = TakeListImporter { mapping };
+        assert!(importer.detect(&path));
+        let cache = tmp.path().join("syncer_cache.json");
+        std::fs::write(&cache, r#"{"entries": {"A001.mov": "00:00:01.000"}}"#).unwrap();
+        assert!(!importer.detect(&cache));
+        let session = importer.import(&path).unwrap();
+
+        assert_eq!(session.session.tracks[0].file, tmp.path().join("audio.wav"));
+        let takes: Vec<_> = session
+            .takes
+            .iter()
+            .map(|t| (t.take_index, t.start, t.end))
+            .collect();
+        assert_eq!(takes, [(0, ms(1500), ms(3500)), (1, ms(10000), ms(10500))]);
+    }
+
+    #[test]
+    fn bad_times_name_the_take() {
+        let mapping = TakeListMapping {
+            columns: TakeListColumns {
+                start: "start".to_owned(),
+                end: Some("end".to_owned()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let rows = [HashMap::from([
+            ("start".to_owned(), "1".to_owned()),
+            ("end".to_owned(), "soon".to_owned()),
+        ])];
+
+        let err = mapping.takes("session", &rows).unwrap_err();
+        assert_eq!(err.to_string(), "invalid end \"soon\" of take 1");
+    }
+}
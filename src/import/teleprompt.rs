@@ -0,0 +1,31 @@
+//! Sessions recorded by teleprompt-studio.
+
+use std::path::Path;
+
+use crate::{
+    data::IntoSession,
+    import::{ImportedSession, Importer},
+    session::{TelepromptStudioSession, META_JSON, TAKES_CSV},
+};
+
+/// A directory with a `metadata.json`, a `takes.csv` and an `audio.wav`.
+pub struct TelepromptStudioImporter;
+
+impl Importer for TelepromptStudioImporter {
+    fn name(&self) -> &'static str {
+        "teleprompt-studio"
+    }
+
+    fn detect(&self, path: &Path) -> bool {
+        path.join(META_JSON).is_file() && path.join(TAKES_CSV).is_file()
+    }
+
+    fn import(&self, path: &Path) -> anyhow::Result<ImportedSession> {
+        let session = TelepromptStudioSession::from_path(path)?;
+        let takes = session.takes();
+        Ok(ImportedSession {
+            session: session.into_session(),
+            takes,
+        })
+    }
+}
@@ -0,0 +1,78 @@
+//! Slice recorded reading sessions into one file per take.
+//!
+//! A session is a set of tracks (audio, video) recorded at the same time, and a list of takes
+//! with their start and end relative to the session's audio. Sessions are read with
+//! [`import::ImporterRegistry`], lined up with [`Slicer::sync_videos`], planned with
+//! [`Slicer::plan`] and cut with [`Slicer::slice`]:
+//!
+//! ```no_run
+//! use session_slicer::{import::ImporterRegistry, synchronizer::{AskUserSyncer, SyncerCache}, Slicer};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let mut slicer = Slicer::builder()
+//!     .ffmpeg("/usr/local/bin/ffmpeg")
+//!     .output("project/video/slicer_output")
+//!     .on_progress(|progress| println!("{progress}"))
+//!     .build();
+//! ImporterRegistry::default().import_dir("project/sessions".as_ref(), None, &mut slicer)?;
+//!
+//! let mut cache = SyncerCache::load("project/video/syncer_cache.json").unwrap_or_default();
+//! slicer.sync_videos("project/video".as_ref(), &[], &mut cache, &AskUserSyncer::new())?;
+//!
+//! let plan = slicer.plan()?;
+//! let outputs = slicer.slice(&plan)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Project`] runs these steps over a project directory the way the command line does. The
+//! command line is behind the default `cli` feature, so the library can be used without clap.
+
+pub mod assemble;
+pub mod audio;
+pub mod backend;
+pub mod bwf;
+pub mod capabilities;
+pub mod channels;
+pub mod check;
+pub mod data;
+pub mod export;
+pub mod import;
+pub mod loudness;
+pub mod probe;
+pub mod profile;
+pub mod project;
+pub mod queue;
+pub mod review;
+pub mod session;
+pub mod sidecar;
+pub mod subtitles;
+pub mod synchronizer;
+pub mod timecode;
+pub mod timestamp;
+pub mod trim;
+pub mod tui;
+pub mod validate;
+pub mod watch;
+pub mod wav;
+
+pub use crate::{
+    backend::CutBackend,
+    data::{
+        Progress, Session, SliceOptions, SliceOutput, SlicePlan, Slicer, SlicerBuilder, Take, Track,
+    },
+    project::Project,
+    synchronizer::{SyncerCache, TrackSync},
+    timestamp::Timestamp,
+};
+
+#[cfg(test)]
+pub(crate) mod tests {
+    /// A new empty directory for a test's files, removed again when it's dropped.
+    pub(crate) fn temp_dir() -> tempfile::TempDir {
+        tempfile::Builder::new()
+            .prefix("session-slicer-")
+            .tempdir()
+            .unwrap()
+    }
+}
@@ -0,0 +1,170 @@
+//! Two-pass loudness normalization with ffmpeg's `loudnorm` filter (EBU R128).
+
+use serde::{Deserialize, Serialize};
+
+/// The loudness a slice should be normalized to.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessTarget {
+    /// Integrated loudness, in LUFS.
+    pub integrated: f64,
+    /// Maximum true peak, in dBTP.
+    pub true_peak: f64,
+    /// Loudness range, in LU.
+    pub range: f64,
+}
+
+impl LoudnessTarget {
+    pub const PODCAST: Self = Self {
+        integrated: -16.0,
+        true_peak: -1.5,
+        range: 11.0,
+    };
+
+    pub const BROADCAST: Self = Self {
+        integrated: -23.0,
+        true_peak: -1.0,
+        range: 7.0,
+    };
+
+    /// The filter for the first, analysis-only pass.
+    pub fn measure_filter(&self) -> String {
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}:print_format=json",
+            self.integrated, self.true_peak, self.range
+        )
+    }
+
+    /// The filter for the second pass, which applies a linear gain based on the first pass.
+    pub fn normalize_filter(&self, measured: &LoudnessMeasurement) -> String {
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true:print_format=json",
+            self.integrated,
+            self.true_peak,
+            self.range,
+            measured.integrated,
+            measured.true_peak,
+            measured.range,
+            measured.threshold,
+            measured.target_offset
+        )
+    }
+}
+
+/// Loudness of a piece of audio, as measured by `loudnorm`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessMeasurement {
+    /// Integrated loudness, in LUFS.
+    pub integrated: f64,
+    /// True peak, in dBTP.
+    pub true_peak: f64,
+    /// Loudness range, in LU.
+    pub range: f64,
+    pub threshold: f64,
+    pub target_offset: f64,
+}
+
+/// `loudnorm` prints every value as a string.
+#[derive(Debug, Deserialize)]
+struct LoudnormJson {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+    output_i: String,
+    output_tp: String,
+    output_lra: String,
+    output_thresh: String,
+}
+
+/// Parse the JSON report that `loudnorm` prints at the end of ffmpeg's stderr.
+///
+/// Returns the measurements of the input and the output, in that order.
+pub fn parse_loudnorm_output(
+    stderr: &str,
+) -> anyhow::Result<(LoudnessMeasurement, LoudnessMeasurement)> {
+    let start = stderr
+        .rfind('{')
+        .ok_or(anyhow::anyhow!("no loudnorm report in ffmpeg output"))?;
+    let end = stderr[start..].find('}').ok_or(anyhow::anyhow!(
+        "unterminated loudnorm report in ffmpeg output"
+    ))?;
+    let json: LoudnormJson = serde_json::from_str(&stderr[start..=start + end])?;
+
+    let target_offset = json.target_offset.parse()?;
+    let input = LoudnessMeasurement {
+        integrated: json.input_i.parse()?,
+        true_peak: json.input_tp.parse()?,
+        range: json.input_lra.parse()?,
+        threshold: json.input_thresh.parse()?,
+        target_offset,
+    };
+    let output = LoudnessMeasurement {
+        integrated: json.output_i.parse()?,
+        true_peak: json.output_tp.parse()?,
+        range: json.output_lra.parse()?,
+        threshold: json.output_thresh.parse()?,
+        target_offset,
+    };
+    Ok((input, output))
+}
+
+/// What the loudness stage did to a slice.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessReport {
+    pub target: LoudnessTarget,
+    /// Loudness of the slice before normalization.
+    pub measured: LoudnessMeasurement,
+    /// Loudness of the slice after normalization.
+    pub normalized: LoudnessMeasurement,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FFMPEG_STDERR: &str = r#"
+size=N/A time=00:00:05.00 bitrate=N/A speed= 200x
+[Parsed_loudnorm_0 @ 0x55d0c8a0c240]
+{
+	"input_i" : "-27.61",
+	"input_tp" : "-4.47",
+	"input_lra" : "18.06",
+	"input_thresh" : "-39.20",
+	"output_i" : "-16.58",
+	"output_tp" : "-1.50",
+	"output_lra" : "14.78",
+	"output_thresh" : "-27.71",
+	"normalization_type" : "dynamic",
+	"target_offset" : "0.58"
+}
+"#;
+
+    #[test]
+    fn parse_loudnorm_report() {
+        let (input, output) = parse_loudnorm_output(FFMPEG_STDERR).unwrap();
+
+        assert_eq!(input.integrated, -27.61);
+        assert_eq!(input.true_peak, -4.47);
+        assert_eq!(input.range, 18.06);
+        assert_eq!(input.threshold, -39.2);
+        assert_eq!(input.target_offset, 0.58);
+        assert_eq!(output.integrated, -16.58);
+        assert_eq!(output.true_peak, -1.5);
+    }
+
+    #[test]
+    fn parse_loudnorm_report_missing() {
+        assert!(parse_loudnorm_output("Output file is empty, nothing was encoded").is_err());
+    }
+
+    #[test]
+    fn normalize_filter_uses_measurement() {
+        let (input, _) = parse_loudnorm_output(FFMPEG_STDERR).unwrap();
+
+        assert_eq!(
+            LoudnessTarget::PODCAST.normalize_filter(&input),
+            "loudnorm=I=-16:TP=-1.5:LRA=11:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:measured_thresh=-39.2:offset=0.58:linear=true:print_format=json"
+        );
+    }
+}
@@ -1,17 +1,13 @@
+use std::time::Duration;
+
 use clap::Parser;
 use log::*;
 
-use crate::{
-    data::Slicer,
-    synchronizer::{SyncerCache, TrackSync},
+use session_slicer::{
+    assemble, check, export, queue, review, synchronizer::AskUserSyncer, validate, watch,
 };
 
 mod cli;
-mod data;
-mod session;
-mod synchronizer;
-pub mod timestamp;
-mod tui;
 
 fn main() -> anyhow::Result<()> {
     let args = cli::Args::parse();
@@ -20,92 +16,121 @@ fn main() -> anyhow::Result<()> {
 
     debug!("args: {:?}", args);
 
-    let sessions_dir = args
-        .sessions
-        .unwrap_or_else(|| args.project.clone().unwrap().join("sessions"));
-
-    debug!("sessions_dir: {:?}", sessions_dir);
-
-    let mut slicer = Slicer::new();
-    let sessions = std::fs::read_dir(sessions_dir)?;
-    for session in sessions {
-        let session = session?;
-        let session_path = session.path();
-        let session = session::TelepromptStudioSession::from_path(session_path.as_path())?;
+    match &args.command {
+        Some(cli::Command::Worker(worker_args)) => {
+            let name = worker_args
+                .name
+                .clone()
+                .unwrap_or_else(queue::Worker::default_name);
+            let slicer = args.slicer_builder()?.build();
+            let mut worker = queue::Worker::new(&worker_args.server, name, slicer);
+            worker.poll = Duration::from_secs(worker_args.poll_secs);
+            worker.run()?;
+            return Ok(());
+        }
+        Some(cli::Command::Check(check_args)) => {
+            let passed = check::check_and_report(
+                &args.sessions_dir()?,
+                &args.importers()?,
+                &check_args.known_marks,
+                check_args.format,
+                check_args.deny_warnings,
+                &mut std::io::stdout().lock(),
+            )?;
+            if !passed {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
 
-        trace!("parsed session: {:?}", session);
+    let project = args.project()?;
+    debug!("project: {:?}", project);
+    let importers = args.importers()?;
 
-        slicer.register_session(session);
+    let mut slicer = args.slicer_builder()?.output(&project.output_dir).build();
+    let slices = !args.skip_slicing && !matches!(args.command, Some(cli::Command::Assemble(_)));
+    if slices {
+        slicer.negotiate_profile()?;
+    }
+    project.load_probe_cache(&slicer);
+
+    if let Some(cli::Command::Watch(watch_args)) = &args.command {
+        let config = watch::WatchConfig {
+            sessions_dir: project.sessions_dir,
+            video_dir: project.video_dir,
+            output_dir: project.output_dir,
+            session_format: args.session_format.clone(),
+            interval: Duration::from_secs(watch_args.interval_secs),
+            settle: Duration::from_secs(watch_args.settle_secs),
+            wait_for_video: !watch_args.no_wait_for_video,
+            sync_offsets: args.sync_offsets.clone(),
+            out_of_range: args.out_of_range,
+        };
+        std::fs::create_dir_all(&config.output_dir)?;
+        return watch::Watcher::new(config).run(&mut slicer, &importers);
     }
-    info!(
-        "found {} sessions, {} takes",
-        slicer.sessions.read().unwrap().len(),
-        slicer.takes.values().map(|v| v.len()).sum::<usize>()
-    );
-
-    info!("searching for corresponding video tracks");
-    let video_dir = args
-        .video
-        .unwrap_or_else(|| args.project.clone().unwrap().join("video"));
-
-    let syncer_cache_path = video_dir.join("syncer_cache.json");
-    let (mut syncer_cache, mut should_save_syncer) = match SyncerCache::load(&syncer_cache_path) {
-        Ok(cache) => (cache, false),
-        Err(e) => {
-            warn!("failed to load syncer cache: {}", e);
-            (SyncerCache::default(), true)
-        }
-    };
 
-    for session in slicer.sessions.write().unwrap().values_mut() {
-        let session_id = session.session_id.clone();
-        let video_path = video_dir.join(format!("video-session-{}.mp4", &session_id));
-        if video_path.exists() {
-            info!("found video for session {}", session_id);
-            // let syncer = synchronizer::FileTrackSyncer::new();
-
-            let file_name = video_path.file_name().unwrap().to_str().unwrap();
-
-            let sync_offset = match syncer_cache.get(file_name) {
-                Some(timestamp) => {
-                    info!(
-                        "using cached sync offset for {}: {:?}",
-                        file_name, timestamp
-                    );
-                    session.tracks.push(data::Track {
-                        file: video_path,
-                        sync_offset: timestamp,
-                    });
-                    continue;
-                }
-                None => {
-                    let syncer = synchronizer::AskUserSyncer::new();
-                    let timestamp = syncer.find_sync_offset(&video_path)?;
-
-                    syncer_cache.set(file_name, timestamp);
-                    should_save_syncer = true;
-                    timestamp
-                }
-            };
+    project.import(&mut slicer, &importers, args.session_format.as_deref())?;
+    project.sync(
+        &mut slicer,
+        &args.sync_offsets,
+        args.recorder_dir.as_deref(),
+        &AskUserSyncer::new(),
+    )?;
+
+    let issues = validate::validate_takes(&mut slicer, args.out_of_range)?;
+    if !issues.is_empty() {
+        warn!(
+            "{} takes don't fit inside their media, see above",
+            issues.len()
+        );
+    }
 
-            session.tracks.push(data::Track {
-                file: video_path,
-                sync_offset,
-            });
-        } else {
-            warn!("no video found for session {}", session_id);
-        }
+    let rate = export::timeline_frame_rate(&slicer, args.fps);
+    project.export(&slicer, &args.take_filter(), rate, &args.exports())?;
+
+    if let Some(cli::Command::Assemble(assemble_args)) = &args.command {
+        let rules = assemble::SelectionRules {
+            mark_priority: assemble_args.mark_priority.clone(),
+            tie_break: assemble_args.tie_break,
+        };
+        assemble::assemble(
+            &slicer,
+            &rules,
+            rate,
+            &assemble_args.output,
+            assemble_args.edl.clone(),
+        )?;
+        info!("Done!");
+        return Ok(());
     }
 
-    if should_save_syncer {
-        syncer_cache.save(&syncer_cache_path)?;
+    if args.skip_slicing {
+        info!("Done!");
+        return Ok(());
     }
 
-    let output_dir = args
-        .output
-        .unwrap_or_else(|| args.project.clone().unwrap().join("video/slicer_output/"));
+    let plan = slicer.plan()?;
+    let outputs = match &args.command {
+        Some(cli::Command::Serve(serve_args)) => {
+            let config = queue::QueueConfig {
+                lease: Duration::from_secs(serve_args.lease_secs),
+                max_attempts: serve_args.max_attempts,
+                ..Default::default()
+            };
+            queue::serve(&serve_args.listen, plan, slicer.options.clone(), config)?
+        }
+        _ => slicer.slice(&plan)?,
+    };
+    info!("wrote {} slices", outputs.len());
+    project.save_probe_cache(&slicer);
 
-    slicer.perform_slicing(output_dir)?;
+    if args.review {
+        let page = review::build_review(&slicer, &outputs, &project.output_dir, args.poster_at)?;
+        info!("wrote review page to {}", page.display());
+    }
 
     info!("Done!");
     Ok(())
@@ -0,0 +1,469 @@
+//! Inspect media files instead of guessing from their extension.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use log::*;
+use serde::{Deserialize, Serialize};
+use symphonia::core::{codecs::CODEC_TYPE_NULL, units::Time};
+
+use crate::{audio, timecode::FrameRate};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+    Audio,
+    Video,
+    Subtitle,
+    Data,
+    /// A stream the backend couldn't identify.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StreamInfo {
+    pub index: usize,
+    pub kind: StreamKind,
+    pub codec: String,
+    pub duration: Option<Duration>,
+    /// Frame rate of video streams, drop-frame if the stream's timecode is.
+    pub frame_rate: Option<FrameRate>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaInfo {
+    pub container: String,
+    pub duration: Option<Duration>,
+    pub streams: Vec<StreamInfo>,
+    /// Times of the keyframes of the first video stream. Empty if the file has no video, or the
+    /// backend can't index keyframes.
+    pub keyframes: Vec<Duration>,
+}
+
+impl MediaInfo {
+    pub fn first(&self, kind: StreamKind) -> Option<&StreamInfo> {
+        self.streams.iter().find(|s| s.kind == kind)
+    }
+
+    pub fn has_video(&self) -> bool {
+        self.first(StreamKind::Video).is_some()
+    }
+
+    /// Whether the file only has audio streams.
+    pub fn is_audio_only(&self) -> bool {
+        self.first(StreamKind::Audio).is_some()
+            && self
+                .streams
+                .iter()
+                .all(|s| matches!(s.kind, StreamKind::Audio | StreamKind::Data))
+    }
+
+    /// Sample rate of the first audio stream.
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.first(StreamKind::Audio).and_then(|s| s.sample_rate)
+    }
+
+    /// The last keyframe at or before `at`.
+    pub fn keyframe_before(&self, at: Duration) -> Option<Duration> {
+        let i = self.keyframes.partition_point(|&k| k <= at);
+        i.checked_sub(1).map(|i| self.keyframes[i])
+    }
+
+    /// Whether a cut at `at` lands on a keyframe, within half a frame. `None` when there's no
+    /// keyframe index to tell.
+    pub fn is_keyframe(&self, at: Duration) -> Option<bool> {
+        if self.keyframes.is_empty() {
+            return None;
+        }
+        let fps = self
+            .first(StreamKind::Video)
+            .and_then(|s| s.frame_rate)
+            .map(|rate| rate.as_f64())
+            .unwrap_or(30.0);
+        let tolerance = Duration::from_secs_f64(0.5 / fps);
+        Some(
+            self.keyframe_before(at + tolerance)
+                .is_some_and(|k| at.abs_diff(k) <= tolerance),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ProbeBackend {
+    /// Read the container with symphonia. Only audio streams are described in detail, and there is
+    /// no keyframe index.
+    #[default]
+    Symphonia,
+    /// Run `ffprobe`, at the given path.
+    Ffprobe(PathBuf),
+}
+
+impl ProbeBackend {
+    fn probe(&self, path: &Path) -> anyhow::Result<MediaInfo> {
+        match self {
+            ProbeBackend::Symphonia => probe_symphonia(path),
+            ProbeBackend::Ffprobe(ffprobe) => probe_ffprobe(ffprobe, path),
+        }
+    }
+}
+
+/// Identifies a version of a file, so cached info is dropped when the file changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    modified: SystemTime,
+    size: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> anyhow::Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        Ok(Self {
+            modified: meta.modified()?,
+            size: meta.len(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    stamp: FileStamp,
+    info: Arc<MediaInfo>,
+}
+
+/// Probes media files, caching the results by path, modification time and size.
+#[derive(Debug, Default)]
+pub struct MediaProbe {
+    pub backend: ProbeBackend,
+    cache: Mutex<HashMap<PathBuf, CacheEntry>>,
+}
+
+impl MediaProbe {
+    pub fn new(backend: ProbeBackend) -> Self {
+        Self {
+            backend,
+            ..Default::default()
+        }
+    }
+
+    pub fn probe(&self, path: impl AsRef<Path>) -> anyhow::Result<Arc<MediaInfo>> {
+        let path = path.as_ref();
+        let stamp = FileStamp::of(path)?;
+        if let Some(entry) = self.cache.lock().unwrap().get(path) {
+            if entry.stamp == stamp {
+                return Ok(entry.info.clone());
+            }
+        }
+
+        debug!("probing {}", path.display());
+        let info = Arc::new(self.backend.probe(path)?);
+        trace!("{}: {:?}", path.display(), info);
+        self.cache.lock().unwrap().insert(
+            path.to_owned(),
+            CacheEntry {
+                stamp,
+                info: info.clone(),
+            },
+        );
+        Ok(info)
+    }
+
+    /// Load cached results from a previous run.
+    pub fn load_cache(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = File::open(path)?;
+        let entries: HashMap<PathBuf, CacheEntry> = serde_json::from_reader(file)?;
+        self.cache.lock().unwrap().extend(entries);
+        Ok(())
+    }
+
+    pub fn save_cache(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, &*self.cache.lock().unwrap())?;
+        Ok(())
+    }
+}
+
+fn time_to_duration(time: Time) -> Duration {
+    Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac)
+}
+
+fn probe_symphonia(path: &Path) -> anyhow::Result<MediaInfo> {
+    let format = audio::open_format(path)?;
+    let codecs = symphonia::default::get_codecs();
+
+    let streams: Vec<StreamInfo> = format
+        .tracks()
+        .iter()
+        .enumerate()
+        .map(|(index, track)| {
+            let params = &track.codec_params;
+            let duration = match (params.time_base, params.n_frames) {
+                (Some(time_base), Some(n_frames)) => {
+                    Some(time_to_duration(time_base.calc_time(n_frames)))
+                }
+                _ => None,
+            };
+            let codec = codecs
+                .get_codec(params.codec)
+                .map(|d| d.short_name.to_owned())
+                .unwrap_or_else(|| "unknown".to_owned());
+            // Symphonia only decodes audio, everything else shows up as a null codec.
+            let kind = if params.codec != CODEC_TYPE_NULL && params.sample_rate.is_some() {
+                StreamKind::Audio
+            } else {
+                StreamKind::Unknown
+            };
+            StreamInfo {
+                index,
+                kind,
+                codec,
+                duration,
+                frame_rate: None,
+                sample_rate: params.sample_rate,
+                channels: params.channels.map(|c| c.count()),
+            }
+        })
+        .collect();
+
+    Ok(MediaInfo {
+        container: path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default(),
+        duration: streams.iter().filter_map(|s| s.duration).max(),
+        streams,
+        keyframes: vec![],
+    })
+}
+
+fn probe_ffprobe(ffprobe: &Path, path: &Path) -> anyhow::Result<MediaInfo> {
+    let out = Command::new(ffprobe)
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_format", "-show_streams"])
+        .arg(path)
+        .output()?;
+    if !out.status.success() {
+        anyhow::bail!("ffprobe failed: {}", String::from_utf8_lossy(&out.stderr));
+    }
+    let mut info = parse_ffprobe_output(&serde_json::from_slice(&out.stdout)?)?;
+
+    if info.has_video() {
+        let out = Command::new(ffprobe)
+            .args(["-v", "error", "-print_format", "json"])
+            .args([
+                "-select_streams",
+                "v:0",
+                "-show_entries",
+                "packet=pts_time,flags",
+            ])
+            .arg(path)
+            .output()?;
+        if out.status.success() {
+            info.keyframes = parse_ffprobe_keyframes(&serde_json::from_slice(&out.stdout)?);
+        } else {
+            warn!(
+                "failed to index keyframes of {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&out.stderr)
+            );
+        }
+    }
+    Ok(info)
+}
+
+/// ffprobe writes most numbers as strings.
+fn number<T: std::str::FromStr>(value: &serde_json::Value) -> Option<T> {
+    match value {
+        serde_json::Value::String(s) => s.parse().ok(),
+        value => value.to_string().parse().ok(),
+    }
+}
+
+fn seconds(value: &serde_json::Value) -> Option<Duration> {
+    number::<f64>(value)
+        .filter(|s| s.is_finite() && *s >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// The frame rate of a video stream. ffprobe gives rates as fractions, eg. `30000/1001`, and
+/// `0/0` when it doesn't know.
+fn frame_rate(stream: &serde_json::Value) -> Option<FrameRate> {
+    let rate = ["avg_frame_rate", "r_frame_rate"]
+        .iter()
+        .find_map(|key| stream[key].as_str()?.parse::<FrameRate>().ok())?;
+    let drop_frame = stream["tags"]["timecode"]
+        .as_str()
+        .is_some_and(|timecode| timecode.contains(';'));
+    if drop_frame {
+        rate.drop_frame().ok().or(Some(rate))
+    } else {
+        Some(rate)
+    }
+}
+
+fn parse_ffprobe_output(json: &serde_json::Value) -> anyhow::Result<MediaInfo> {
+    let format = &json["format"];
+    let streams = json["streams"]
+        .as_array()
+        .ok_or(anyhow::anyhow!("ffprobe output has no streams"))?
+        .iter()
+        .enumerate()
+        .map(|(i, stream)| {
+            let kind = match stream["codec_type"].as_str() {
+                Some("audio") => StreamKind::Audio,
+                Some("video") => StreamKind::Video,
+                Some("subtitle") => StreamKind::Subtitle,
+                Some("data") => StreamKind::Data,
+                _ => StreamKind::Unknown,
+            };
+            StreamInfo {
+                index: number(&stream["index"]).unwrap_or(i),
+                kind,
+                codec: stream["codec_name"]
+                    .as_str()
+                    .unwrap_or("unknown")
+                    .to_owned(),
+                duration: seconds(&stream["duration"]),
+                frame_rate: if kind == StreamKind::Video {
+                    frame_rate(stream)
+                } else {
+                    None
+                },
+                sample_rate: number(&stream["sample_rate"]),
+                channels: number(&stream["channels"]),
+            }
+        })
+        .collect();
+
+    Ok(MediaInfo {
+        container: format["format_name"]
+            .as_str()
+            .unwrap_or("unknown")
+            .to_owned(),
+        duration: seconds(&format["duration"]),
+        streams,
+        keyframes: vec![],
+    })
+}
+
+fn parse_ffprobe_keyframes(json: &serde_json::Value) -> Vec<Duration> {
+    let mut keyframes: Vec<Duration> = json["packets"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|p| p["flags"].as_str().is_some_and(|f| f.starts_with('K')))
+        .filter_map(|p| seconds(&p["pts_time"]))
+        .collect();
+    // Packets are in decode order.
+    keyframes.sort();
+    keyframes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::tests::write_test_wav;
+
+    #[test]
+    fn probe_wav_with_symphonia() {
+        let tmp = crate::tests::temp_dir();
+        let path = tmp.path().join("probe.wav");
+        write_test_wav(&path, 8000, &[0; 12000]);
+
+        let info = MediaProbe::default().probe(&path).unwrap();
+
+        assert_eq!(info.container, "wav");
+        assert_eq!(info.duration, Some(Duration::from_millis(1500)));
+        assert_eq!(info.sample_rate(), Some(8000));
+        assert_eq!(info.streams[0].channels, Some(1));
+        assert!(info.is_audio_only());
+    }
+
+    #[test]
+    fn cache_is_dropped_when_file_changes() {
+        let tmp = crate::tests::temp_dir();
+        let path = tmp.path().join("probe.wav");
+        write_test_wav(&path, 8000, &[0; 8000]);
+        let probe = MediaProbe::default();
+
+        let first = probe.probe(&path).unwrap();
+        assert!(Arc::ptr_eq(&first, &probe.probe(&path).unwrap()));
+
+        write_test_wav(&path, 8000, &[0; 4000]);
+        let second = probe.probe(&path).unwrap();
+        assert_eq!(second.duration, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn parse_ffprobe() {
+        let json = serde_json::json!({
+            "streams": [
+                {
+                    "index": 0,
+                    "codec_name": "h264",
+                    "codec_type": "video",
+                    "r_frame_rate": "30000/1001",
+                    "avg_frame_rate": "30000/1001",
+                    "duration": "10.010000",
+                    "tags": { "timecode": "01:00:00;00" }
+                },
+                {
+                    "index": 1,
+                    "codec_name": "aac",
+                    "codec_type": "audio",
+                    "sample_rate": "48000",
+                    "channels": 2,
+                    "duration": "10.000000"
+                }
+            ],
+            "format": {
+                "format_name": "mov,mp4,m4a,3gp,3g2,mj2",
+                "duration": "10.010000"
+            }
+        });
+
+        let info = parse_ffprobe_output(&json).unwrap();
+
+        assert_eq!(info.container, "mov,mp4,m4a,3gp,3g2,mj2");
+        assert_eq!(info.duration, Some(Duration::from_millis(10010)));
+        let video = info.first(StreamKind::Video).unwrap();
+        assert_eq!(video.codec, "h264");
+        assert_eq!(video.frame_rate, Some(FrameRate::FPS_29_97_DF));
+        assert_eq!(info.sample_rate(), Some(48000));
+        assert_eq!(info.streams[1].channels, Some(2));
+        assert!(!info.is_audio_only());
+    }
+
+    #[test]
+    fn keyframe_lookup() {
+        let json = serde_json::json!({
+            "packets": [
+                { "pts_time": "0.000000", "flags": "K__" },
+                { "pts_time": "0.066667", "flags": "___" },
+                { "pts_time": "0.033333", "flags": "___" },
+                { "pts_time": "2.000000", "flags": "K__" }
+            ]
+        });
+        let info = MediaInfo {
+            container: "mp4".to_owned(),
+            duration: None,
+            streams: vec![],
+            keyframes: parse_ffprobe_keyframes(&json),
+        };
+
+        assert_eq!(info.keyframes, [Duration::ZERO, Duration::from_secs(2)]);
+        assert_eq!(
+            info.keyframe_before(Duration::from_millis(1999)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(info.is_keyframe(Duration::from_millis(2010)), Some(true));
+        assert_eq!(info.is_keyframe(Duration::from_millis(1000)), Some(false));
+    }
+}
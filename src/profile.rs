@@ -0,0 +1,118 @@
+//! Output profiles control what happens to each slice after it has been cut.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{channels::ChannelMap, loudness::LoudnessTarget};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputProfile {
+    pub name: String,
+    /// Normalize the loudness of every slice to this target.
+    pub loudness: Option<LoudnessTarget>,
+    /// Burn the script text into video slices as subtitles. Needs
+    /// [`SliceOptions::subtitles`](crate::SliceOptions::subtitles), since the captions written
+    /// next to the slices are what is burnt in.
+    pub burn_in_subtitles: bool,
+    /// Encoders to transcode video slices with, best first. If ffmpeg has none of them, the
+    /// fallbacks in [`ENCODER_FALLBACKS`](crate::capabilities::ENCODER_FALLBACKS) are tried.
+    /// Setting any means every video slice is transcoded.
+    pub video_encoders: Vec<String>,
+    /// Encoders for the audio of transcoded video slices, best first. The audio is copied if
+    /// there are none.
+    pub audio_encoders: Vec<String>,
+    /// Also make a lightweight proxy of every slice. Slices then go in `full/` and proxies with
+    /// the same names in `proxy/`, so an editor can relink between them. Proxies of audio slices
+    /// are copies of them, only video is transcoded.
+    pub proxy: Option<ProxyProfile>,
+    /// Which audio channels of each track end up in the slices. Tracks without a map keep all
+    /// of their channels.
+    pub channel_maps: Vec<ChannelMap>,
+}
+
+/// How proxies are encoded. Proxies of video slices are made by the same ffmpeg run as the full
+/// slice, from a single decode. Audio slices are light already, so their proxies are copies.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyProfile {
+    /// Height of the picture, the width keeps the aspect ratio.
+    pub height: u32,
+    pub video_bitrate: String,
+    /// Encoders to try, best first, as in [`OutputProfile::video_encoders`].
+    pub video_encoders: Vec<String>,
+    pub audio_encoders: Vec<String>,
+}
+
+impl Default for ProxyProfile {
+    fn default() -> Self {
+        Self {
+            height: 540,
+            video_bitrate: "2M".to_owned(),
+            video_encoders: vec!["libx264".to_owned()],
+            audio_encoders: vec!["aac".to_owned()],
+        }
+    }
+}
+
+impl OutputProfile {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let profile = serde_json::from_reader(file)?;
+        Ok(profile)
+    }
+
+    /// Look up one of the profiles that ship with the slicer.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (loudness, video_encoders) = match name {
+            "default" => (None, vec![]),
+            "podcast" => (Some(LoudnessTarget::PODCAST), vec![]),
+            "broadcast" => (Some(LoudnessTarget::BROADCAST), vec![]),
+            "hevc" => (None, vec!["libx265".to_owned()]),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_owned(),
+            loudness,
+            video_encoders,
+            ..Default::default()
+        })
+    }
+
+    /// Whether slicing with this profile needs ffmpeg features beyond cutting.
+    pub fn needs_ffmpeg_features(&self) -> bool {
+        self.loudness.is_some()
+            || self.burn_in_subtitles
+            || !self.video_encoders.is_empty()
+            || !self.audio_encoders.is_empty()
+            || self.proxy.is_some()
+            || !self.channel_maps.is_empty()
+    }
+
+    /// The channel map for the track at `index`. A map for that track wins over one for every
+    /// track.
+    pub fn channel_map(&self, index: usize) -> Option<&ChannelMap> {
+        self.channel_maps
+            .iter()
+            .find(|map| map.track == Some(index))
+            .or_else(|| self.channel_maps.iter().find(|map| map.track.is_none()))
+    }
+
+    /// Resolve a profile given on the command line, either the name of a builtin profile or the
+    /// path to a profile JSON file.
+    pub fn resolve(name_or_path: &str) -> anyhow::Result<Self> {
+        if let Some(profile) = Self::builtin(name_or_path) {
+            return Ok(profile);
+        }
+        let path = Path::new(name_or_path);
+        if !path.exists() {
+            anyhow::bail!("unknown output profile: {}", name_or_path);
+        }
+        let mut profile = Self::from_path(path)?;
+        if profile.name.is_empty() {
+            profile.name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        }
+        Ok(profile)
+    }
+}
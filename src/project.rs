@@ -0,0 +1,205 @@
+//! The steps the command line runs over a project directory, for tools that want to run them the
+//! same way.
+
+use std::path::{Path, PathBuf};
+
+use log::*;
+
+use crate::{
+    export::{ExportFormat, TakeFilter, Timeline},
+    import::{ImportReport, ImporterRegistry},
+    synchronizer::{self, SyncerCache, TrackSync},
+    timecode::FrameRate,
+    timestamp::Timestamp,
+    Slicer,
+};
+
+/// Where a project's sessions, videos and slices are.
+#[derive(Debug, Clone)]
+pub struct Project {
+    /// Used for exported timelines.
+    pub name: String,
+    pub sessions_dir: PathBuf,
+    pub video_dir: PathBuf,
+    pub output_dir: PathBuf,
+}
+
+impl Project {
+    /// A project laid out the usual way, with `sessions/`, `video/` and `video/slicer_output/` in
+    /// `root`.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        let root = root.as_ref();
+        Self {
+            name: root
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "session-slicer".to_owned()),
+            sessions_dir: root.join("sessions"),
+            video_dir: root.join("video"),
+            output_dir: root.join("video/slicer_output/"),
+        }
+    }
+
+    fn syncer_cache_path(&self) -> PathBuf {
+        self.video_dir.join("syncer_cache.json")
+    }
+
+    fn probe_cache_path(&self) -> PathBuf {
+        self.video_dir.join("probe_cache.json")
+    }
+
+    /// Register every session of the sessions directory with `slicer`. Entries that fail to
+    /// import are reported and skipped.
+    pub fn import(
+        &self,
+        slicer: &mut Slicer,
+        importers: &ImporterRegistry,
+        session_format: Option<&str>,
+    ) -> anyhow::Result<ImportReport> {
+        let report = importers.import_dir(&self.sessions_dir, session_format, slicer)?;
+        if !report.failed.is_empty() {
+            warn!(
+                "skipped {} entries of {}, see above",
+                report.failed.len(),
+                self.sessions_dir.display()
+            );
+        }
+        info!(
+            "found {} sessions, {} takes",
+            slicer.sessions.read().unwrap().len(),
+            slicer.takes.values().map(|v| v.len()).sum::<usize>()
+        );
+        Ok(report)
+    }
+
+    /// Add the video of every session, and the WAVs in `recorder_dir` to the sessions they were
+    /// recorded during. Sync offsets are kept in the syncer cache of the video directory.
+    pub fn sync(
+        &self,
+        slicer: &mut Slicer,
+        given: &[(String, Timestamp)],
+        recorder_dir: Option<&Path>,
+        syncer: &impl TrackSync,
+    ) -> anyhow::Result<()> {
+        info!("searching for corresponding video tracks");
+        let cache_path = self.syncer_cache_path();
+        let (mut cache, should_save) = match SyncerCache::load(&cache_path) {
+            Ok(cache) => (cache, false),
+            Err(e) => {
+                warn!("failed to load syncer cache: {}", e);
+                (SyncerCache::default(), true)
+            }
+        };
+        slicer.sync_videos(&self.video_dir, given, &mut cache, syncer)?;
+
+        if let Some(recorder_dir) = recorder_dir {
+            let mut recordings = std::fs::read_dir(recorder_dir)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            recordings.retain(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("wav"))
+            });
+            recordings.sort();
+            let unmatched =
+                synchronizer::attach_recordings(&mut slicer.sessions.write().unwrap(), &recordings);
+            for recording in unmatched {
+                warn!(
+                    "{} wasn't recorded during any session, skipping",
+                    recording.display()
+                );
+            }
+        }
+
+        if should_save || cache.is_dirty() {
+            cache.save(&cache_path)?;
+        }
+        Ok(())
+    }
+
+    /// Reuse what earlier runs found out about the project's media.
+    pub fn load_probe_cache(&self, slicer: &Slicer) {
+        let path = self.probe_cache_path();
+        if path.exists() {
+            if let Err(e) = slicer.probe.load_cache(&path) {
+                warn!("failed to load probe cache: {}", e);
+            }
+        }
+    }
+
+    pub fn save_probe_cache(&self, slicer: &Slicer) {
+        if let Err(e) = slicer.probe.save_cache(self.probe_cache_path()) {
+            warn!("failed to save probe cache: {}", e);
+        }
+    }
+
+    /// Export the takes that pass `filter` as a timeline in every format of `exports`.
+    pub fn export(
+        &self,
+        slicer: &Slicer,
+        filter: &TakeFilter,
+        rate: FrameRate,
+        exports: &[(ExportFormat, PathBuf)],
+    ) -> anyhow::Result<()> {
+        if exports.is_empty() {
+            return Ok(());
+        }
+        let timeline = Timeline::from_slicer(slicer, filter, &self.name, rate);
+        for (format, path) in exports {
+            format.write(&timeline, path)?;
+            info!(
+                "exported {} clips to {}",
+                timeline.clips.len(),
+                path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{synchronizer::AskUserSyncer, wav::tests::write_test_wav};
+
+    #[test]
+    fn import_and_sync_a_project() {
+        let tmp = crate::tests::temp_dir();
+        let project = Project::new(tmp.path().join("Film"));
+        assert_eq!(project.name, "Film");
+        assert!(project.output_dir.starts_with(&project.video_dir));
+
+        let session = project.sessions_dir.join("1");
+        std::fs::create_dir_all(&session).unwrap();
+        std::fs::create_dir_all(&project.video_dir).unwrap();
+        write_test_wav(&session.join("audio.wav"), 1000, &[0; 3000]);
+        std::fs::write(
+            session.join("metadata.json"),
+            r#"{"SyncOffset": "00:00:00.000"}"#,
+        )
+        .unwrap();
+        std::fs::write(
+            session.join("takes.csv"),
+            "header,chunk_index,chunk_text,take_index,take_mark,take_start,take_end\n\
+             Intro,0,Hi,0,good,00:00:00.500,00:00:01.500\n",
+        )
+        .unwrap();
+        std::fs::write(project.video_dir.join("video-session-1.mp4"), "video").unwrap();
+
+        let mut slicer = Slicer::new();
+        let report = project
+            .import(&mut slicer, &ImporterRegistry::default(), None)
+            .unwrap();
+        assert_eq!(report.imported, ["1"]);
+        let given = [("1".to_owned(), Timestamp::from_secs(2))];
+        project
+            .sync(&mut slicer, &given, None, &AskUserSyncer::new())
+            .unwrap();
+        assert_eq!(slicer.sessions.read().unwrap()["1"].tracks.len(), 2);
+        let cache = SyncerCache::load(project.syncer_cache_path()).unwrap();
+        assert_eq!(
+            cache.get("video-session-1.mp4"),
+            Some(Timestamp::from_secs(2))
+        );
+    }
+}
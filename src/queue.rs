@@ -0,0 +1,938 @@
+//! Spread the slices of a plan over several machines. `serve` hands out jobs over HTTP and
+//! `worker`s lease them, cut them and report back. Workers have to see the media and output
+//! directories at the same paths as the server, e.g. on shared storage.
+//!
+//! The API is JSON over HTTP:
+//!
+//! - `POST /lease` with `{"worker": name}` returns `{"job": lease or null, "finished": bool}`.
+//! - `POST /renew` with `{"job": id, "token": token}` extends a lease.
+//! - `POST /complete` with `{"job": id, "token": token, "outcome": outcome}` reports a result.
+//! - `GET /status` counts the jobs in every state.
+//!
+//! A lease that isn't renewed or completed in time goes back to the queue, and so does a failed
+//! job, until it has been tried `max_attempts` times. Workers cut into a `.lease-*` folder next to
+//! the slice, and only move the files into place once the server has taken the slice.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use log::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::data::{PlannedSlice, SliceOptions, SliceOutput, SlicePlan, Slicer};
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig {
+    /// How long a worker has to renew or complete a job before it's handed to someone else.
+    pub lease: Duration,
+    /// How often a job is tried before it's given up on.
+    pub max_attempts: u32,
+    /// How long to keep telling workers that everything is done before the server stops.
+    pub linger: Duration,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            lease: Duration::from_secs(300),
+            max_attempts: 3,
+            linger: Duration::from_secs(10),
+        }
+    }
+}
+
+/// A job handed to a worker.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lease {
+    pub job: usize,
+    /// Identifies this lease of the job, so late reports of expired leases can be told apart.
+    pub token: u64,
+    /// 1 for the first try.
+    pub attempt: u32,
+    /// How long until the lease has to be renewed.
+    pub lease: Duration,
+    pub slice: PlannedSlice,
+    pub options: SliceOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "status", content = "result")]
+pub enum JobOutcome {
+    Sliced(Box<SliceOutput>),
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueueStatus {
+    pub pending: usize,
+    pub leased: usize,
+    pub done: usize,
+    pub failed: usize,
+}
+
+/// What came of a served plan.
+#[derive(Debug, Default)]
+pub struct QueueReport {
+    pub outputs: Vec<SliceOutput>,
+    /// Slices that failed on every attempt, with the last error.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+#[derive(Debug)]
+enum JobState {
+    Pending,
+    Leased {
+        worker: String,
+        token: u64,
+        until: Instant,
+    },
+    Done(Box<SliceOutput>),
+    Failed,
+}
+
+#[derive(Debug)]
+struct Job {
+    slice: PlannedSlice,
+    state: JobState,
+    attempts: u32,
+    last_error: Option<String>,
+}
+
+/// The jobs of a plan and who is working on them.
+#[derive(Debug)]
+pub struct JobQueue {
+    config: QueueConfig,
+    options: SliceOptions,
+    jobs: Vec<Job>,
+    next_token: u64,
+}
+
+impl JobQueue {
+    /// A queue with a job for every slice of `plan`. Paths are made absolute, since workers don't
+    /// run in the same directory.
+    pub fn new(
+        plan: SlicePlan,
+        options: SliceOptions,
+        config: QueueConfig,
+    ) -> anyhow::Result<Self> {
+        let jobs = plan
+            .slices
+            .into_iter()
+            .map(|mut slice| {
+                slice.file = std::path::absolute(&slice.file)?;
+                slice.proxy = slice.proxy.map(std::path::absolute).transpose()?;
+                slice.track.file = std::path::absolute(&slice.track.file)?;
+                Ok(Job {
+                    slice,
+                    state: JobState::Pending,
+                    attempts: 0,
+                    last_error: None,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self {
+            config,
+            options,
+            jobs,
+            next_token: 1,
+        })
+    }
+
+    /// Hand the next job to `worker`, if there is one.
+    pub fn lease(&mut self, worker: &str, now: Instant) -> Option<Lease> {
+        self.expire(now);
+        let (id, job) = self
+            .jobs
+            .iter_mut()
+            .enumerate()
+            .find(|(_, job)| matches!(job.state, JobState::Pending))?;
+        job.attempts += 1;
+        let token = self.next_token;
+        self.next_token += 1;
+        job.state = JobState::Leased {
+            worker: worker.to_owned(),
+            token,
+            until: now + self.config.lease,
+        };
+        debug!(
+            "leased {} to {}, attempt {}",
+            job.slice.file.display(),
+            worker,
+            job.attempts
+        );
+        Some(Lease {
+            job: id,
+            token,
+            attempt: job.attempts,
+            lease: self.config.lease,
+            slice: job.slice.clone(),
+            options: self.options.clone(),
+        })
+    }
+
+    pub fn renew(&mut self, id: usize, token: u64, now: Instant) -> anyhow::Result<()> {
+        self.expire(now);
+        let lease = self.config.lease;
+        match self.leased(id, token)? {
+            JobState::Leased { until, .. } => *until = now + lease,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    pub fn complete(
+        &mut self,
+        id: usize,
+        token: u64,
+        outcome: JobOutcome,
+        now: Instant,
+    ) -> anyhow::Result<()> {
+        self.expire(now);
+        let max_attempts = self.config.max_attempts;
+        let worker = match self.leased(id, token)? {
+            JobState::Leased { worker, .. } => std::mem::take(worker),
+            _ => unreachable!(),
+        };
+        let job = &mut self.jobs[id];
+        match outcome {
+            JobOutcome::Sliced(output) => {
+                info!("{} sliced {}", worker, job.slice.file.display());
+                job.state = JobState::Done(output);
+            }
+            JobOutcome::Failed(error) => {
+                warn!(
+                    "{} failed to slice {}: {}",
+                    worker,
+                    job.slice.file.display(),
+                    error
+                );
+                job.last_error = Some(error);
+                job.state = Self::retry_or_fail(job.attempts, max_attempts);
+            }
+        }
+        Ok(())
+    }
+
+    /// The state of a job, if `token` is its current lease.
+    fn leased(&mut self, id: usize, token: u64) -> anyhow::Result<&mut JobState> {
+        let job = self
+            .jobs
+            .get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("no job {id}"))?;
+        match &job.state {
+            JobState::Leased { token: current, .. } if *current == token => Ok(&mut job.state),
+            _ => anyhow::bail!("the lease of job {id} has expired"),
+        }
+    }
+
+    /// Put jobs whose lease ran out back in the queue.
+    fn expire(&mut self, now: Instant) {
+        for job in &mut self.jobs {
+            if let JobState::Leased { worker, until, .. } = &job.state {
+                if *until <= now {
+                    warn!(
+                        "{} didn't finish {} in time",
+                        worker,
+                        job.slice.file.display()
+                    );
+                    job.last_error = Some(format!("lease held by {worker} expired"));
+                    job.state = Self::retry_or_fail(job.attempts, self.config.max_attempts);
+                }
+            }
+        }
+    }
+
+    fn retry_or_fail(attempts: u32, max_attempts: u32) -> JobState {
+        if attempts < max_attempts {
+            JobState::Pending
+        } else {
+            JobState::Failed
+        }
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        let mut status = QueueStatus::default();
+        for job in &self.jobs {
+            match job.state {
+                JobState::Pending => status.pending += 1,
+                JobState::Leased { .. } => status.leased += 1,
+                JobState::Done(_) => status.done += 1,
+                JobState::Failed => status.failed += 1,
+            }
+        }
+        status
+    }
+
+    /// Whether every job is done or has failed for good.
+    pub fn is_finished(&self) -> bool {
+        self.jobs
+            .iter()
+            .all(|job| matches!(job.state, JobState::Done(_) | JobState::Failed))
+    }
+
+    pub fn into_report(self) -> QueueReport {
+        let mut report = QueueReport::default();
+        for job in self.jobs {
+            match job.state {
+                JobState::Done(output) => report.outputs.push(*output),
+                _ => report.failed.push((
+                    job.slice.file,
+                    job.last_error.unwrap_or_else(|| "never sliced".to_owned()),
+                )),
+            }
+        }
+        report
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LeaseRequest {
+    worker: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LeaseResponse {
+    job: Option<Lease>,
+    finished: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RenewRequest {
+    job: usize,
+    token: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CompleteRequest {
+    job: usize,
+    token: u64,
+    outcome: JobOutcome,
+}
+
+/// Serves a [`JobQueue`] to workers over HTTP.
+pub struct QueueServer {
+    server: tiny_http::Server,
+    queue: JobQueue,
+}
+
+impl QueueServer {
+    /// Listen on `addr`, e.g. `0.0.0.0:7878`. Port 0 picks a free port.
+    pub fn bind(addr: &str, queue: JobQueue) -> anyhow::Result<Self> {
+        let server = tiny_http::Server::http(addr)
+            .map_err(|e| anyhow::anyhow!("failed to listen on {addr}: {e}"))?;
+        Ok(Self { server, queue })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Answer workers until every job has been dealt with.
+    pub fn run(mut self) -> anyhow::Result<QueueReport> {
+        info!(
+            "serving {} jobs on {:?}",
+            self.queue.jobs.len(),
+            self.local_addr()
+        );
+        let mut finished_at = None;
+        loop {
+            if let Some(request) = self.server.recv_timeout(Duration::from_millis(100))? {
+                self.handle(request);
+            }
+            if self.queue.is_finished() {
+                let finished_at = *finished_at.get_or_insert_with(Instant::now);
+                if finished_at.elapsed() >= self.queue.config.linger {
+                    break;
+                }
+            }
+        }
+        Ok(self.queue.into_report())
+    }
+
+    fn handle(&mut self, mut request: tiny_http::Request) {
+        let now = Instant::now();
+        let method = request.method().clone();
+        let url = request.url().to_owned();
+        trace!("{} {}", method, url);
+        let result = match (method, url.as_str()) {
+            (tiny_http::Method::Get, "/status") => to_json(&self.queue.status()),
+            (tiny_http::Method::Post, "/lease") => read_json::<LeaseRequest>(&mut request)
+                .and_then(|body| {
+                    let job = self.queue.lease(&body.worker, now);
+                    to_json(&LeaseResponse {
+                        job,
+                        finished: self.queue.is_finished(),
+                    })
+                }),
+            (tiny_http::Method::Post, "/renew") => read_json::<RenewRequest>(&mut request)
+                .and_then(|body| self.queue.renew(body.job, body.token, now))
+                .and_then(|()| to_json(&serde_json::json!({}))),
+            (tiny_http::Method::Post, "/complete") => read_json::<CompleteRequest>(&mut request)
+                .and_then(|body| self.queue.complete(body.job, body.token, body.outcome, now))
+                .and_then(|()| to_json(&serde_json::json!({}))),
+            _ => {
+                let _ = request.respond(tiny_http::Response::empty(404));
+                return;
+            }
+        };
+        let (status, body) = match result {
+            Ok(body) => (200, body),
+            Err(e) => (
+                409,
+                serde_json::json!({ "error": e.to_string() }).to_string(),
+            ),
+        };
+        let header = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+        let response = tiny_http::Response::from_string(body)
+            .with_status_code(status)
+            .with_header(header);
+        if let Err(e) = request.respond(response) {
+            debug!("failed to respond to {}: {}", url, e);
+        }
+    }
+}
+
+/// Hand `plan` out to workers on `addr` until every slice is made or given up on, and return the
+/// slices that were made.
+pub fn serve(
+    addr: &str,
+    plan: SlicePlan,
+    options: SliceOptions,
+    config: QueueConfig,
+) -> anyhow::Result<Vec<SliceOutput>> {
+    let queue = JobQueue::new(plan, options, config)?;
+    let report = QueueServer::bind(addr, queue)?.run()?;
+    for (file, error) in &report.failed {
+        error!("gave up on {}: {}", file.display(), error);
+    }
+    Ok(report.outputs)
+}
+
+fn read_json<T: DeserializeOwned>(request: &mut tiny_http::Request) -> anyhow::Result<T> {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+fn to_json(value: &impl Serialize) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(value)?)
+}
+
+/// What a worker got done.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerReport {
+    pub sliced: usize,
+    pub failed: usize,
+}
+
+/// Leases jobs from a [`QueueServer`] and cuts them with its own [`Slicer`], so it uses the
+/// ffmpeg and backend of the machine it runs on.
+pub struct Worker {
+    /// Base URL of the server, e.g. `http://10.0.0.2:7878`.
+    pub server: String,
+    pub name: String,
+    /// How long to wait before asking again when no job is free.
+    pub poll: Duration,
+    /// How often in a row the server may be unreachable before giving up.
+    pub max_retries: u32,
+    pub slicer: Slicer,
+    agent: ureq::Agent,
+}
+
+impl Worker {
+    pub fn new(server: impl Into<String>, name: impl Into<String>, slicer: Slicer) -> Self {
+        Self {
+            server: server.into().trim_end_matches('/').to_owned(),
+            name: name.into(),
+            poll: Duration::from_secs(2),
+            max_retries: 5,
+            slicer,
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(30))
+                .build(),
+        }
+    }
+
+    /// The host name and process id, for workers that aren't given a name.
+    pub fn default_name() -> String {
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_owned());
+        format!("{host}-{}", std::process::id())
+    }
+
+    /// Work until the server says everything is done.
+    pub fn run(&mut self) -> anyhow::Result<WorkerReport> {
+        info!("{} working for {}", self.name, self.server);
+        let mut report = WorkerReport::default();
+        let mut retries = 0;
+        loop {
+            let request = LeaseRequest {
+                worker: self.name.clone(),
+            };
+            let response: LeaseResponse = match post(&self.agent, &self.server, "lease", &request) {
+                Ok(response) => {
+                    retries = 0;
+                    response
+                }
+                Err(e) if retries < self.max_retries => {
+                    retries += 1;
+                    warn!("failed to reach {}: {:#}", self.server, e);
+                    std::thread::sleep(self.poll);
+                    continue;
+                }
+                Err(e) => return Err(e.context(format!("giving up on {}", self.server))),
+            };
+
+            let Some(lease) = response.job else {
+                if response.finished {
+                    info!(
+                        "{} done, sliced {} and failed {}",
+                        self.name, report.sliced, report.failed
+                    );
+                    return Ok(report);
+                }
+                std::thread::sleep(self.poll);
+                continue;
+            };
+
+            self.slicer.options = lease.options.clone();
+            let staging = Staging::new(&lease);
+            let outcome = match self.slicer.negotiate_profile() {
+                Ok(()) => self.work(&lease, &staging),
+                Err(e) => {
+                    error!("{:#}", e);
+                    Some(JobOutcome::Failed(format!("{}: {e:#}", self.name)))
+                }
+            };
+            let Some(outcome) = outcome else {
+                staging.discard();
+                continue;
+            };
+            let sliced = matches!(outcome, JobOutcome::Sliced(_));
+            let request = CompleteRequest {
+                job: lease.job,
+                token: lease.token,
+                outcome,
+            };
+            // Only slices the server took are moved into place and counted. Otherwise the job
+            // is leased again once this lease runs out.
+            if let Err(e) =
+                post::<_, serde_json::Value>(&self.agent, &self.server, "complete", &request)
+            {
+                warn!(
+                    "failed to report {}, it will be retried: {:#}",
+                    lease.slice.file.display(),
+                    e
+                );
+                staging.discard();
+                continue;
+            }
+            if !sliced {
+                report.failed += 1;
+                staging.discard();
+            } else if let Err(e) = staging.publish() {
+                error!(
+                    "failed to move {} into place: {:#}",
+                    lease.slice.file.display(),
+                    e
+                );
+                report.failed += 1;
+            } else {
+                report.sliced += 1;
+            }
+        }
+    }
+
+    /// Cut the slice of a lease into `staging`, renewing the lease while it runs. Returns nothing
+    /// when the server turned a renewal down, since the job is someone else's then.
+    fn work(&self, lease: &Lease, staging: &Staging) -> Option<JobOutcome> {
+        let file = &lease.slice.file;
+        info!("slicing {}, attempt {}", file.display(), lease.attempt);
+        let slice = match staging.slice(&lease.slice) {
+            Ok(slice) => slice,
+            Err(e) => {
+                return Some(JobOutcome::Failed(format!(
+                    "failed to stage {}: {e}",
+                    file.display()
+                )))
+            }
+        };
+
+        let lost = AtomicBool::new(false);
+        let (done, renewals) = mpsc::channel::<()>();
+        let (agent, server, lost_lease) = (&self.agent, &self.server, &lost);
+        let result = std::thread::scope(|scope| {
+            scope.spawn(move || loop {
+                match renewals.recv_timeout(lease.lease / 3) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+                let request = RenewRequest {
+                    job: lease.job,
+                    token: lease.token,
+                };
+                match post::<_, serde_json::Value>(agent, server, "renew", &request) {
+                    Ok(_) => {}
+                    Err(e) if e.is::<Rejected>() => {
+                        warn!("lost the lease of {}: {:#}", file.display(), e);
+                        lost_lease.store(true, Ordering::SeqCst);
+                        return;
+                    }
+                    Err(e) => warn!("failed to renew the lease of {}: {:#}", file.display(), e),
+                }
+            });
+            let result = self.slicer.slice_planned(&slice);
+            drop(done);
+            result
+        });
+
+        if lost.load(Ordering::SeqCst) {
+            warn!("dropping {}, another worker has it now", file.display());
+            return None;
+        }
+        Some(match result {
+            Ok(mut output) => {
+                output.file = lease.slice.file.clone();
+                output.proxy = lease.slice.proxy.clone();
+                JobOutcome::Sliced(Box::new(output))
+            }
+            Err(e) => {
+                error!("failed to slice {}: {:#}", file.display(), e);
+                JobOutcome::Failed(format!("{e:#}"))
+            }
+        })
+    }
+}
+
+/// Where the files of a lease are written until the server has taken the slice, so that a worker
+/// whose lease ran out never writes over the files of the worker that has the job now. It mirrors
+/// the folders of the slice and its proxy, so the paths between them stay the same.
+struct Staging {
+    root: PathBuf,
+    dir: PathBuf,
+}
+
+impl Staging {
+    fn new(lease: &Lease) -> Self {
+        let slice = &lease.slice;
+        let mut root = slice.file.parent().unwrap_or(Path::new("")).to_owned();
+        if let Some(proxy) = &slice.proxy {
+            while !proxy.starts_with(&root) && root.pop() {}
+        }
+        let dir = root.join(format!(".lease-{}-{}", lease.job, lease.token));
+        Self { root, dir }
+    }
+
+    fn path(&self, file: &Path) -> PathBuf {
+        self.dir.join(file.strip_prefix(&self.root).unwrap_or(file))
+    }
+
+    /// `slice`, written into the staging folder.
+    fn slice(&self, slice: &PlannedSlice) -> std::io::Result<PlannedSlice> {
+        let file = self.path(&slice.file);
+        let proxy = slice.proxy.as_deref().map(|proxy| self.path(proxy));
+        for file in std::iter::once(&file).chain(&proxy) {
+            std::fs::create_dir_all(file.parent().unwrap())?;
+        }
+        Ok(PlannedSlice {
+            file,
+            proxy,
+            ..slice.clone()
+        })
+    }
+
+    /// Move everything that was written to where it belongs.
+    fn publish(&self) -> std::io::Result<()> {
+        move_files(&self.dir, &self.root)?;
+        std::fs::remove_dir_all(&self.dir)
+    }
+
+    fn discard(&self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("failed to remove {}: {}", self.dir.display(), e);
+            }
+        }
+    }
+}
+
+/// Move the files in `from` to the same places in `to`, replacing what is there.
+fn move_files(from: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            std::fs::create_dir_all(&dest)?;
+            move_files(&entry.path(), &dest)?;
+        } else {
+            std::fs::rename(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// The server turned a request down, rather than not being reachable.
+#[derive(Debug, thiserror::Error)]
+#[error("server answered {status}: {body}")]
+struct Rejected {
+    status: u16,
+    body: String,
+}
+
+fn post<B: Serialize, T: DeserializeOwned>(
+    agent: &ureq::Agent,
+    server: &str,
+    path: &str,
+    body: &B,
+) -> anyhow::Result<T> {
+    let response = match agent.post(&format!("{server}/{path}")).send_json(body) {
+        Ok(response) => response,
+        Err(ureq::Error::Status(status, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            return Err(Rejected { status, body }.into());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    response
+        .into_json()
+        .with_context(|| format!("invalid response to /{path}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        backend::{CutBackend, CutJob, NativeBackend},
+        data::{Session, Track},
+        export::tests::take,
+        timestamp::Timestamp,
+        wav::tests::write_test_wav,
+    };
+
+    fn plan(takes: usize) -> (tempfile::TempDir, SlicePlan) {
+        let dir = crate::tests::temp_dir();
+        let audio = dir.path().join("audio.wav");
+        write_test_wav(&audio, 1000, &[0; 10000]);
+
+        let slicer = Slicer::new();
+        slicer.sessions.write().unwrap().insert(
+            "session".to_owned(),
+            Session {
+                session_id: "session".to_owned(),
+                tracks: vec![Track {
+                    file: audio,
+                    sync_offset: Timestamp::ZERO,
+                }],
+            },
+        );
+        let takes = (0..takes as i64)
+            .map(|i| take("1", i as usize, i * 1000, i * 1000 + 500))
+            .collect();
+        let slicer = Slicer {
+            takes: [("session".to_owned(), takes)].into(),
+            ..slicer
+        };
+        let plan = slicer.plan_in(dir.path().join("out")).unwrap();
+        (dir, plan)
+    }
+
+    #[test]
+    fn leases_expire_and_jobs_are_retried() {
+        let (_dir, plan) = plan(1);
+        let config = QueueConfig {
+            lease: Duration::from_secs(10),
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let mut queue = JobQueue::new(plan, SliceOptions::default(), config).unwrap();
+        let start = Instant::now();
+
+        let first = queue.lease("a", start).unwrap();
+        assert!(queue.lease("b", start).is_none());
+        queue
+            .renew(first.job, first.token, start + Duration::from_secs(8))
+            .unwrap();
+        assert!(queue.lease("b", start + Duration::from_secs(12)).is_none());
+
+        // The lease runs out, so the job goes to b and a's late report is turned down.
+        let second = queue.lease("b", start + Duration::from_secs(20)).unwrap();
+        assert_eq!(second.attempt, 2);
+        let failed = JobOutcome::Failed("slow".to_owned());
+        let now = start + Duration::from_secs(21);
+        assert!(queue.complete(first.job, first.token, failed, now).is_err());
+
+        let failed = JobOutcome::Failed("broken".to_owned());
+        queue
+            .complete(second.job, second.token, failed, now)
+            .unwrap();
+        assert!(queue.is_finished());
+        assert_eq!(
+            queue.status(),
+            QueueStatus {
+                failed: 1,
+                ..Default::default()
+            }
+        );
+        let report = queue.into_report();
+        assert_eq!(report.failed[0].1, "broken");
+    }
+
+    /// Fails the first cut it's given, whichever worker that is.
+    #[derive(Debug, Default)]
+    struct FailOnce {
+        failed: AtomicBool,
+    }
+
+    impl CutBackend for FailOnce {
+        fn name(&self) -> &'static str {
+            "fail-once"
+        }
+
+        fn supports(&self, job: &CutJob) -> bool {
+            NativeBackend.supports(job)
+        }
+
+        fn cut(&self, job: &CutJob) -> anyhow::Result<()> {
+            if !self.failed.swap(true, Ordering::SeqCst) {
+                anyhow::bail!("disk full");
+            }
+            NativeBackend.cut(job)
+        }
+    }
+
+    #[test]
+    fn workers_on_localhost() {
+        let (dir, plan) = plan(6);
+        let config = QueueConfig {
+            linger: Duration::from_millis(500),
+            ..Default::default()
+        };
+        let queue = JobQueue::new(plan, SliceOptions::default(), config).unwrap();
+        let server = QueueServer::bind("127.0.0.1:0", queue).unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        let server = std::thread::spawn(move || server.run().unwrap());
+
+        let backend = Arc::new(FailOnce::default());
+        let workers: Vec<_> = (0..3)
+            .map(|i| {
+                let slicer = Slicer::builder().backend(backend.clone()).build();
+                let mut worker = Worker::new(&url, format!("worker-{i}"), slicer);
+                worker.poll = Duration::from_millis(20);
+                std::thread::spawn(move || worker.run().unwrap())
+            })
+            .collect();
+
+        let reports: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        assert_eq!(reports.iter().map(|r| r.sliced).sum::<usize>(), 6);
+        assert_eq!(reports.iter().map(|r| r.failed).sum::<usize>(), 1);
+
+        let report = server.join().unwrap();
+        assert_eq!(report.outputs.len(), 6);
+        assert!(report.failed.is_empty());
+        for i in 0..6 {
+            let file = dir
+                .path()
+                .join(format!("out/chunk-1-take-{i}-track-0-good.wav"));
+            assert!(file.exists(), "{} is missing", file.display());
+        }
+        // Nothing is left in staging.
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("out")).unwrap().count(),
+            12
+        );
+    }
+
+    /// Cuts natively after a while.
+    #[derive(Debug)]
+    struct Slow(Duration);
+
+    impl CutBackend for Slow {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        fn supports(&self, job: &CutJob) -> bool {
+            NativeBackend.supports(job)
+        }
+
+        fn cut(&self, job: &CutJob) -> anyhow::Result<()> {
+            std::thread::sleep(self.0);
+            NativeBackend.cut(job)
+        }
+    }
+
+    /// A server that hands out `lease` once and turns every renewal and report down. Returns the
+    /// paths requested, once the worker has been told everything is done.
+    fn grudging_server(lease: Lease) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut lease = Some(lease);
+            let mut paths = vec![];
+            for request in server.incoming_requests() {
+                let path = request.url().to_owned();
+                let (status, body) = match path.as_str() {
+                    "/lease" => {
+                        let response = LeaseResponse {
+                            job: lease.take(),
+                            finished: true,
+                        };
+                        (200, to_json(&response).unwrap())
+                    }
+                    _ => (409, r#"{"error": "expired"}"#.to_owned()),
+                };
+                let done = path == "/lease" && paths.contains(&path);
+                paths.push(path);
+                request
+                    .respond(tiny_http::Response::from_string(body).with_status_code(status))
+                    .unwrap();
+                if done {
+                    return paths;
+                }
+            }
+            paths
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn slices_the_server_turns_down_are_dropped() {
+        for (lease_time, cut_time, expected) in [
+            // The report is turned down.
+            (300_000, 0, ["/lease", "/complete", "/lease"].as_slice()),
+            // A renewal is turned down, so nothing is reported.
+            (30, 200, ["/lease", "/renew", "/lease"].as_slice()),
+        ] {
+            let (dir, plan) = plan(1);
+            let mut queue =
+                JobQueue::new(plan, SliceOptions::default(), Default::default()).unwrap();
+            let lease = Lease {
+                lease: Duration::from_millis(lease_time),
+                ..queue.lease("worker", Instant::now()).unwrap()
+            };
+            let file = lease.slice.file.clone();
+            let (url, server) = grudging_server(lease);
+
+            let backend = Arc::new(Slow(Duration::from_millis(cut_time)));
+            let slicer = Slicer::builder().backend(backend).build();
+            let mut worker = Worker::new(&url, "worker", slicer);
+            worker.poll = Duration::from_millis(20);
+            assert_eq!(worker.run().unwrap(), WorkerReport::default());
+            assert_eq!(server.join().unwrap(), expected);
+            assert!(!file.exists());
+            assert_eq!(
+                std::fs::read_dir(dir.path().join("out")).unwrap().count(),
+                0
+            );
+        }
+    }
+}
@@ -0,0 +1,387 @@
+//! Poster frames, contact sheets and an HTML page for reviewing slices without opening them.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use log::*;
+
+use crate::{
+    audio,
+    data::{self, SliceOutput, Slicer},
+    sidecar::{xml_escape, SliceMetadata},
+};
+
+/// Width of poster frames, in pixels. Height follows the aspect ratio.
+const THUMBNAIL_WIDTH: u32 = 320;
+/// Number of thumbnails per row of a contact sheet.
+const CONTACT_SHEET_COLUMNS: usize = 6;
+
+/// Where in a take the poster frame is taken from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PosterPoint {
+    /// A fraction of the way through the take, between 0 and 1.
+    Fraction(f64),
+    /// A fixed time after the start of the take.
+    Offset(Duration),
+}
+
+impl Default for PosterPoint {
+    fn default() -> Self {
+        PosterPoint::Fraction(0.5)
+    }
+}
+
+impl PosterPoint {
+    pub fn resolve(&self, take_len: Duration) -> Duration {
+        match self {
+            PosterPoint::Fraction(f) => take_len.mul_f64(f.clamp(0.0, 1.0)),
+            PosterPoint::Offset(offset) => (*offset).min(take_len),
+        }
+    }
+}
+
+impl FromStr for PosterPoint {
+    type Err = anyhow::Error;
+
+    /// Either a percentage, eg. `25%`, or seconds, eg. `1.5`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(percent) = s.strip_suffix('%') {
+            let percent: f64 = percent.trim().parse()?;
+            if !(0.0..=100.0).contains(&percent) {
+                anyhow::bail!("poster point must be between 0% and 100%");
+            }
+            return Ok(PosterPoint::Fraction(percent / 100.0));
+        }
+        let secs: f64 = s.trim_end_matches('s').parse()?;
+        if secs < 0.0 {
+            anyhow::bail!("poster point can't be negative");
+        }
+        Ok(PosterPoint::Offset(Duration::from_secs_f64(secs)))
+    }
+}
+
+/// Escape text for ffmpeg's `drawtext` filter, given as a command argument.
+fn drawtext_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' | '\'' | ':' | '%' => {
+                escaped.push('\\');
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ',' | ';' | '[' | ']' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A take, with every slice that was made of it.
+struct ReviewEntry<'a> {
+    metadata: &'a SliceMetadata,
+    files: Vec<&'a Path>,
+    thumbnail: Option<PathBuf>,
+}
+
+/// Orders takes by chunk, numerically when possible, then take. The index of the take in the
+/// slicer keeps takes that share a chunk and take index apart.
+type EntryKey<'a> = (Option<u64>, &'a str, usize, usize);
+
+struct ReviewSession<'a> {
+    session_id: &'a str,
+    contact_sheet: Option<PathBuf>,
+    entries: Vec<ReviewEntry<'a>>,
+}
+
+fn relative<'a>(path: &'a Path, base: &Path) -> &'a Path {
+    path.strip_prefix(base).unwrap_or(path)
+}
+
+fn href(path: &Path, base: &Path) -> String {
+    xml_escape(&relative(path, base).to_string_lossy())
+}
+
+/// Extract a poster frame of every video slice, tile them into a contact sheet per session, and
+/// write `review.html` into `output_dir`.
+pub fn build_review(
+    slicer: &Slicer,
+    outputs: &[SliceOutput],
+    output_dir: &Path,
+    poster_at: PosterPoint,
+) -> anyhow::Result<PathBuf> {
+    let thumbnail_dir = output_dir.join("thumbnails");
+    std::fs::create_dir_all(&thumbnail_dir)?;
+
+    let mut grouped: BTreeMap<&str, BTreeMap<EntryKey, ReviewEntry>> = BTreeMap::new();
+    for output in outputs {
+        let meta = &output.metadata;
+        let key = (
+            meta.chunk_index.parse().ok(),
+            meta.chunk_index.as_str(),
+            meta.take_index,
+            output.take_index,
+        );
+        let entry = grouped
+            .entry(meta.session_id.as_str())
+            .or_default()
+            .entry(key)
+            .or_insert_with(|| ReviewEntry {
+                metadata: meta,
+                files: vec![],
+                thumbnail: None,
+            });
+        entry.files.push(&output.file);
+
+        if entry.thumbnail.is_some() || audio::is_audio_file(&output.file) {
+            continue;
+        }
+        let thumbnail = thumbnail_dir.join(output.file.with_extension("jpg").file_name().unwrap());
+        match extract_poster_frame(slicer, meta, poster_at, &thumbnail) {
+            Ok(()) => entry.thumbnail = Some(thumbnail),
+            Err(e) => warn!(
+                "failed to extract poster frame of {}: {}",
+                output.file.display(),
+                e
+            ),
+        }
+    }
+
+    let mut sessions = vec![];
+    for (session_id, entries) in grouped {
+        let entries: Vec<ReviewEntry> = entries.into_values().collect();
+        let thumbnails: Vec<&Path> = entries
+            .iter()
+            .filter_map(|entry| entry.thumbnail.as_deref())
+            .collect();
+        let mut contact_sheet = None;
+        if !thumbnails.is_empty() {
+            let path = output_dir.join(format!("contact-sheet-{session_id}.jpg"));
+            match write_contact_sheet(slicer, &thumbnails, &path) {
+                Ok(()) => contact_sheet = Some(path),
+                Err(e) => warn!("failed to make contact sheet for {}: {}", session_id, e),
+            }
+        }
+        sessions.push(ReviewSession {
+            session_id,
+            contact_sheet,
+            entries,
+        });
+    }
+
+    let page = output_dir.join("review.html");
+    std::fs::write(&page, to_html(&sessions, output_dir))?;
+    Ok(page)
+}
+
+/// Grab a single, labelled frame from the source of a slice.
+fn extract_poster_frame(
+    slicer: &Slicer,
+    meta: &SliceMetadata,
+    poster_at: PosterPoint,
+    out: &Path,
+) -> anyhow::Result<()> {
+    let at = meta.source_in
+        + poster_at.resolve((meta.source_out - meta.source_in).saturating_duration());
+    let mut cmd = slicer.ffmpeg_command();
+    cmd.arg("-y")
+        .arg("-ss")
+        .arg(at.to_string())
+        .arg("-i")
+        .arg(&meta.source_file)
+        .args(["-frames:v", "1"])
+        .arg("-vf")
+        .arg(format!(
+            "scale={THUMBNAIL_WIDTH}:-2,drawtext=text='{}':x=4:y=h-th-4:fontcolor=white:box=1:boxcolor=black@0.6",
+            drawtext_escape(&meta.title())
+        ))
+        .arg(out);
+    data::run_ffmpeg(cmd)?;
+    Ok(())
+}
+
+/// Render the review page. Links are relative to `base`, where the page is written.
+fn to_html(sessions: &[ReviewSession], base: &Path) -> String {
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>").unwrap();
+    writeln!(
+        html,
+        "<html><head><meta charset=\"utf-8\"><title>Review</title>"
+    )
+    .unwrap();
+    writeln!(
+        html,
+        "<style>body{{font-family:sans-serif}} .take{{display:inline-block;vertical-align:top;width:{THUMBNAIL_WIDTH}px;margin:8px}} .take img{{width:100%}} .text{{color:#444}}</style>"
+    )
+    .unwrap();
+    writeln!(html, "</head><body>").unwrap();
+
+    for session in sessions {
+        writeln!(html, "<h2>Session {}</h2>", xml_escape(session.session_id)).unwrap();
+        if let Some(contact_sheet) = &session.contact_sheet {
+            writeln!(
+                html,
+                "<p><a href=\"{}\">Contact sheet</a></p>",
+                href(contact_sheet, base)
+            )
+            .unwrap();
+        }
+        for entry in &session.entries {
+            let meta = entry.metadata;
+            writeln!(html, "<div class=\"take\">").unwrap();
+            if let Some(thumbnail) = &entry.thumbnail {
+                // Link the poster frame to the video slice it was taken from.
+                let clip = entry
+                    .files
+                    .iter()
+                    .find(|f| !audio::is_audio_file(f))
+                    .unwrap_or(&entry.files[0]);
+                writeln!(
+                    html,
+                    "<a href=\"{}\"><img src=\"{}\" alt=\"{}\"></a>",
+                    href(clip, base),
+                    href(thumbnail, base),
+                    xml_escape(&meta.title())
+                )
+                .unwrap();
+            }
+            writeln!(html, "<div><b>{}</b></div>", xml_escape(&meta.title())).unwrap();
+            if let Some(header) = &meta.header {
+                writeln!(html, "<div>{}</div>", xml_escape(header)).unwrap();
+            }
+            if let Some(text) = &meta.chunk_text {
+                writeln!(html, "<p class=\"text\">{}</p>", xml_escape(text)).unwrap();
+            }
+            for file in &entry.files {
+                let name = file.file_name().unwrap_or_default().to_string_lossy();
+                writeln!(
+                    html,
+                    "<div><a href=\"{}\">{}</a></div>",
+                    href(file, base),
+                    xml_escape(&name)
+                )
+                .unwrap();
+            }
+            writeln!(html, "</div>").unwrap();
+        }
+    }
+    writeln!(html, "</body></html>").unwrap();
+    html
+}
+
+/// Tile thumbnails into a single image.
+fn write_contact_sheet(slicer: &Slicer, thumbnails: &[&Path], out: &Path) -> anyhow::Result<()> {
+    let list = out.with_extension("txt");
+    let mut contents = String::new();
+    for thumbnail in thumbnails {
+        let path = std::path::absolute(thumbnail)?;
+        writeln!(
+            contents,
+            "file '{}'",
+            path.to_string_lossy().replace('\'', "'\\''")
+        )
+        .unwrap();
+    }
+    std::fs::write(&list, contents)?;
+
+    let rows = thumbnails.len().div_ceil(CONTACT_SHEET_COLUMNS);
+    let columns = thumbnails.len().min(CONTACT_SHEET_COLUMNS);
+    let mut cmd = slicer.ffmpeg_command();
+    cmd.arg("-y")
+        .args(["-f", "concat", "-safe", "0", "-i"])
+        .arg(&list)
+        .arg("-vf")
+        .arg(format!("tile={columns}x{rows}:padding=4:margin=4"))
+        .args(["-frames:v", "1"])
+        .arg(out);
+    let result = data::run_ffmpeg(cmd);
+    std::fs::remove_file(&list)?;
+    result?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::Timestamp;
+
+    #[test]
+    fn parse_poster_point() {
+        assert_eq!(
+            "25%".parse::<PosterPoint>().unwrap(),
+            PosterPoint::Fraction(0.25)
+        );
+        assert_eq!(
+            "1.5".parse::<PosterPoint>().unwrap(),
+            PosterPoint::Offset(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            "2s".parse::<PosterPoint>().unwrap(),
+            PosterPoint::Offset(Duration::from_secs(2))
+        );
+        assert!("150%".parse::<PosterPoint>().is_err());
+    }
+
+    #[test]
+    fn poster_point_stays_inside_take() {
+        let take_len = Duration::from_secs(4);
+
+        assert_eq!(
+            PosterPoint::Fraction(0.5).resolve(take_len),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            PosterPoint::Offset(Duration::from_secs(10)).resolve(take_len),
+            take_len
+        );
+    }
+
+    #[test]
+    fn review_page_links_thumbnails_to_clips() {
+        let take = crate::export::tests::take("3", 2, 0, 1000);
+        let track = crate::data::Track {
+            file: PathBuf::from("/media/video.mp4"),
+            sync_offset: Timestamp::ZERO,
+        };
+        let mut metadata = SliceMetadata::new(&take, 0, &track);
+        metadata.chunk_text = Some("Fish & chips".to_owned());
+        let base = Path::new("/out");
+        let sessions = [ReviewSession {
+            session_id: "session",
+            contact_sheet: Some(base.join("contact-sheet-session.jpg")),
+            entries: vec![ReviewEntry {
+                metadata: &metadata,
+                files: vec![
+                    Path::new("/out/chunk-3-take-2-track-0-good.wav"),
+                    Path::new("/out/chunk-3-take-2-track-1-good.mp4"),
+                ],
+                thumbnail: Some(base.join("thumbnails/chunk-3-take-2-track-1-good.jpg")),
+            }],
+        }];
+
+        let html = to_html(&sessions, base);
+
+        assert!(html.contains("<a href=\"contact-sheet-session.jpg\">Contact sheet</a>"));
+        assert!(html.contains(
+            "<a href=\"chunk-3-take-2-track-1-good.mp4\"><img src=\"thumbnails/chunk-3-take-2-track-1-good.jpg\" alt=\"chunk 3 take 2 (good)\"></a>"
+        ));
+        assert!(html.contains("<p class=\"text\">Fish &amp; chips</p>"));
+        assert!(html.contains("<a href=\"chunk-3-take-2-track-0-good.wav\">"));
+    }
+
+    #[test]
+    fn drawtext_is_escaped() {
+        assert_eq!(
+            drawtext_escape("chunk 1: it's 100%"),
+            "chunk 1\\\\: it\\\\'s 100\\\\%"
+        );
+    }
+}
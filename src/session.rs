@@ -1,9 +1,9 @@
 //! Stuff for managing recording sessions outputted by teleprompt-studio.
 
 use std::path::{Path, PathBuf};
-use std::time::Duration;
 
-use serde::{Deserialize, Deserializer};
+use anyhow::Context;
+use serde::Deserialize;
 
 use crate::data::{IntoSession, Take, Track};
 use crate::timestamp::Timestamp;
@@ -39,13 +39,7 @@ impl TelepromptStudioSession {
         let mut takes = vec![];
 
         for take in self.takes.takes() {
-            takes.push(Take {
-                session_id: self.get_session_id(),
-                chunk_id: take.chunk_index.to_string(),
-                start: take.start(),
-                end: take.end(),
-                mark: take.mark().to_owned(),
-            });
+            takes.push(take.to_take(&self.get_session_id()));
         }
 
         takes
@@ -80,8 +74,10 @@ pub struct SessionMeta {
 
 impl SessionMeta {
     fn from_path(path: &Path) -> anyhow::Result<Self> {
-        let file = std::fs::File::open(path)?;
-        let meta = serde_json::from_reader(file)?;
+        let file =
+            std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let meta =
+            serde_json::from_reader(file).with_context(|| format!("parsing {}", path.display()))?;
         Ok(meta)
     }
 }
@@ -92,11 +88,13 @@ pub struct SessionTakes {
 }
 impl SessionTakes {
     fn from_path(path: &Path) -> anyhow::Result<Self> {
-        let mut reader = csv::Reader::from_path(path)?;
+        let mut reader =
+            csv::Reader::from_path(path).with_context(|| format!("opening {}", path.display()))?;
         let mut takes = Vec::new();
 
         for result in reader.deserialize() {
-            let take: SessionTake = result?;
+            let take: SessionTake =
+                result.with_context(|| format!("parsing {}", path.display()))?;
             takes.push(take);
         }
 
@@ -133,18 +131,20 @@ impl SessionTake {
         self.take_end
     }
 
-    pub fn duration(&self) -> Duration {
-        self.take_end - self.take_start
-    }
-
     pub fn mark(&self) -> &str {
         &self.take_mark
     }
 
-    pub fn out_slice_file_name(&self) -> String {
-        format!(
-            "chunk-{}-take-{}-{}.mp4",
-            self.chunk_index, self.take_index, self.take_mark
-        )
+    pub fn to_take(&self, session_id: &str) -> Take {
+        Take {
+            session_id: session_id.to_owned(),
+            chunk_id: self.chunk_index.to_string(),
+            chunk_text: Some(self.chunk_text.clone()),
+            header: Some(self.header.clone()),
+            take_index: self.take_index,
+            start: self.start(),
+            end: self.end(),
+            mark: self.mark().to_owned(),
+        }
     }
 }
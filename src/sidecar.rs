@@ -0,0 +1,411 @@
+//! Metadata written next to, and embedded in, every slice.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bwf::BwfInfo,
+    data::{Take, Track},
+    loudness::LoudnessReport,
+    timecode::{FrameRate, Timecode},
+    timestamp::Timestamp,
+    trim::TrimReport,
+};
+
+pub const TOOL_NAME: &str = env!("CARGO_PKG_NAME");
+pub const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Everything known about where a slice came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SliceMetadata {
+    pub session_id: String,
+    pub chunk_index: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<String>,
+    pub take_index: usize,
+    pub mark: String,
+    pub track_index: usize,
+    pub source_file: PathBuf,
+    /// Position of the slice in the source file.
+    pub source_in: Timestamp,
+    pub source_out: Timestamp,
+    pub sync_offset: Timestamp,
+    /// Frame rate of the source, if it is video.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_rate: Option<FrameRate>,
+    /// `source_in` and `source_out` as timecode, at `frame_rate`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timecode_in: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timecode_out: Option<String>,
+    /// What the field recorder wrote into the source's BWF metadata.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recorder: Option<RecorderInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trim: Option<TrimReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loudness: Option<LoudnessReport>,
+    /// What each audio channel of the slice was made from, when its channels were mapped, eg.
+    /// `FL+FR` for a downmix.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    /// Every file made for the slice, when proxies are made too.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renditions: Vec<Rendition>,
+    pub tool: String,
+    pub tool_version: String,
+}
+
+impl SliceMetadata {
+    pub fn new(take: &Take, track_index: usize, track: &Track) -> Self {
+        Self {
+            session_id: take.session_id.clone(),
+            chunk_index: take.chunk_id.clone(),
+            chunk_text: take.chunk_text.clone(),
+            header: take.header.clone(),
+            take_index: take.take_index,
+            mark: take.mark.clone(),
+            track_index,
+            source_file: track.file.clone(),
+            source_in: take.start + track.sync_offset,
+            source_out: take.end + track.sync_offset,
+            sync_offset: track.sync_offset,
+            frame_rate: None,
+            timecode_in: None,
+            timecode_out: None,
+            recorder: None,
+            trim: None,
+            loudness: None,
+            channels: vec![],
+            renditions: vec![],
+            tool: TOOL_NAME.to_owned(),
+            tool_version: TOOL_VERSION.to_owned(),
+        }
+    }
+
+    /// Record the frame rate of the source, and where the slice is in it as timecode.
+    pub fn set_frame_rate(&mut self, rate: FrameRate) {
+        self.frame_rate = Some(rate);
+        self.timecode_in = Some(Timecode::from_timestamp(self.source_in, rate).to_string());
+        self.timecode_out = Some(Timecode::from_timestamp(self.source_out, rate).to_string());
+    }
+
+    /// Record the BWF metadata of the source, if it has any.
+    pub fn set_recorder(&mut self, info: &BwfInfo) {
+        if info.is_empty() {
+            return;
+        }
+        let ixml = info.ixml.clone().unwrap_or_default();
+        let time_of_day = info.time_reference().map(|start| start + self.source_in);
+        self.recorder = Some(RecorderInfo {
+            scene: ixml.scene,
+            take: ixml.take,
+            tape: ixml.tape,
+            note: ixml.note,
+            circled: ixml.circled,
+            track_names: ixml.tracks.into_iter().map(|track| track.name).collect(),
+            start_timecode: time_of_day.map(|time| match ixml.timecode_rate {
+                Some(rate) => Timecode::from_timestamp(time, rate).to_string(),
+                None => time.to_string(),
+            }),
+        });
+    }
+
+    pub fn title(&self) -> String {
+        format!(
+            "chunk {} take {} ({})",
+            self.chunk_index, self.take_index, self.mark
+        )
+    }
+
+    /// Key/value pairs to embed in the container with ffmpeg's `-metadata`.
+    pub fn container_tags(&self) -> Vec<(&'static str, String)> {
+        let mut tags = vec![
+            ("title", self.title()),
+            ("session_id", self.session_id.clone()),
+            ("chunk_index", self.chunk_index.clone()),
+            ("take_index", self.take_index.to_string()),
+            ("mark", self.mark.clone()),
+            ("source_file", self.source_file.display().to_string()),
+            ("source_in", self.source_in.to_string()),
+            ("source_out", self.source_out.to_string()),
+            ("encoder", format!("{} {}", self.tool, self.tool_version)),
+        ];
+        if let Some(text) = &self.chunk_text {
+            tags.push(("comment", text.clone()));
+        }
+        if let Some(header) = &self.header {
+            tags.push(("album", header.clone()));
+        }
+        if let Some(timecode) = &self.timecode_in {
+            tags.push(("timecode", timecode.clone()));
+        }
+        tags
+    }
+
+    /// `-metadata` arguments for ffmpeg. Custom keys are only kept in mp4/mov outputs with
+    /// `-movflags use_metadata_tags`.
+    pub fn ffmpeg_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        for (key, value) in self.container_tags() {
+            args.push("-metadata".to_owned());
+            args.push(format!("{key}={value}"));
+        }
+        args.push("-movflags".to_owned());
+        args.push("use_metadata_tags".to_owned());
+        args
+    }
+
+    /// RIFF INFO tags for WAV outputs, which are written natively even when ffmpeg cuts them.
+    pub fn riff_info(&self) -> Vec<([u8; 4], String)> {
+        let mut tags = vec![
+            (*b"INAM", self.title()),
+            (*b"ISRC", self.source_file.display().to_string()),
+            (*b"ISFT", format!("{} {}", self.tool, self.tool_version)),
+        ];
+        if let Some(text) = &self.chunk_text {
+            tags.push((*b"ICMT", text.clone()));
+        }
+        tags
+    }
+
+    pub fn write_json(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn write_xmp(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_xmp())?;
+        Ok(())
+    }
+
+    pub fn to_xmp(&self) -> String {
+        let mut fields = vec![
+            ("ss:sessionId", self.session_id.clone()),
+            ("ss:chunkIndex", self.chunk_index.clone()),
+            ("ss:takeIndex", self.take_index.to_string()),
+            ("ss:mark", self.mark.clone()),
+            ("ss:trackIndex", self.track_index.to_string()),
+            ("ss:sourceFile", self.source_file.display().to_string()),
+            ("ss:sourceIn", self.source_in.to_string()),
+            ("ss:sourceOut", self.source_out.to_string()),
+            ("ss:syncOffset", self.sync_offset.to_string()),
+            ("xmpDM:takeNumber", self.take_index.to_string()),
+            (
+                "xmp:CreatorTool",
+                format!("{} {}", self.tool, self.tool_version),
+            ),
+        ];
+        if let Some(header) = &self.header {
+            fields.push(("xmpDM:scene", header.clone()));
+        }
+        if let Some(text) = &self.chunk_text {
+            fields.push(("xmpDM:logComment", text.clone()));
+        }
+        if let (Some(rate), Some(timecode_in), Some(timecode_out)) =
+            (self.frame_rate, &self.timecode_in, &self.timecode_out)
+        {
+            fields.push(("ss:frameRate", rate.to_string()));
+            fields.push(("ss:timecodeIn", timecode_in.clone()));
+            fields.push(("ss:timecodeOut", timecode_out.clone()));
+        }
+        if let Some(recorder) = &self.recorder {
+            let recorder_fields = [
+                ("ss:recorderScene", &recorder.scene),
+                ("ss:recorderTake", &recorder.take),
+                ("xmpDM:tapeName", &recorder.tape),
+                ("ss:recorderTimecode", &recorder.start_timecode),
+            ];
+            for (key, value) in recorder_fields {
+                if let Some(value) = value {
+                    fields.push((key, value.clone()));
+                }
+            }
+        }
+
+        let mut xmp = String::new();
+        xmp.push_str("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+        xmp.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n");
+        xmp.push_str(" <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n");
+        xmp.push_str("  <rdf:Description rdf:about=\"\"\n");
+        xmp.push_str("    xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n");
+        xmp.push_str("    xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n");
+        xmp.push_str("    xmlns:xmpDM=\"http://ns.adobe.com/xmp/1.0/DynamicMedia/\"\n");
+        xmp.push_str("    xmlns:ss=\"https://github.com/dyc3/session-slicer/ns/1.0/\">\n");
+        xmp.push_str(&format!(
+            "   <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+            xml_escape(&self.title())
+        ));
+        for (key, value) in fields {
+            xmp.push_str(&format!("   <{key}>{}</{key}>\n", xml_escape(&value)));
+        }
+        if let (Some(format), Some(timecode)) =
+            (self.frame_rate.and_then(xmp_time_format), &self.timecode_in)
+        {
+            xmp.push_str("   <xmpDM:startTimecode rdf:parseType=\"Resource\">\n");
+            xmp.push_str(&format!(
+                "    <xmpDM:timeFormat>{format}</xmpDM:timeFormat>\n"
+            ));
+            xmp.push_str(&format!(
+                "    <xmpDM:timeValue>{timecode}</xmpDM:timeValue>\n"
+            ));
+            xmp.push_str("   </xmpDM:startTimecode>\n");
+        }
+        xmp.push_str("  </rdf:Description>\n");
+        xmp.push_str(" </rdf:RDF>\n");
+        xmp.push_str("</x:xmpmeta>\n");
+        xmp.push_str("<?xpacket end=\"w\"?>\n");
+        xmp
+    }
+}
+
+/// One of the files made for a slice.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rendition {
+    pub kind: RenditionKind,
+    /// Relative to the output directory, so the folder can be moved as a whole.
+    pub file: PathBuf,
+    /// Height the picture was scaled to, if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video_encoder: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenditionKind {
+    /// Full quality, for finishing.
+    Full,
+    /// Lightweight, for offline editing.
+    Proxy,
+}
+
+/// Names and timecode from the BWF and iXML chunks of a field recording.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecorderInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scene: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub take: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tape: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    pub circled: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub track_names: Vec<String>,
+    /// Time of day at the start of the slice, as timecode when the recorder's rate is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_timecode: Option<String>,
+}
+
+/// The XMP name of a timecode format, if XMP has one for `rate`.
+fn xmp_time_format(rate: FrameRate) -> Option<&'static str> {
+    Some(match (rate.to_string().as_str(), rate.is_drop_frame()) {
+        ("23.976", _) => "23976Timecode",
+        ("24", _) => "24Timecode",
+        ("25", _) => "25Timecode",
+        ("29.97df", _) => "2997DropTimecode",
+        ("29.97", _) => "2997NonDropTimecode",
+        ("30", _) => "30Timecode",
+        ("50", _) => "50Timecode",
+        ("59.94df", _) => "5994DropTimecode",
+        ("59.94", _) => "5994NonDropTimecode",
+        ("60", _) => "60Timecode",
+        _ => return None,
+    })
+}
+
+pub fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> SliceMetadata {
+        let take = Take {
+            session_id: "2023-08-01".to_owned(),
+            chunk_id: "3".to_owned(),
+            chunk_text: Some("Fish & chips <3".to_owned()),
+            header: Some("Intro".to_owned()),
+            take_index: 2,
+            start: Timestamp::from_secs(10),
+            end: Timestamp::from_secs(12),
+            mark: "good".to_owned(),
+        };
+        let track = Track {
+            file: PathBuf::from("video/video-session-2023-08-01.mp4"),
+            sync_offset: Timestamp::from_secs(5),
+        };
+        SliceMetadata::new(&take, 1, &track)
+    }
+
+    #[test]
+    fn source_position_includes_sync_offset() {
+        let meta = metadata();
+
+        assert_eq!(meta.source_in, Timestamp::from_secs(15));
+        assert_eq!(meta.source_out, Timestamp::from_secs(17));
+    }
+
+    #[test]
+    fn xmp_is_escaped() {
+        let xmp = metadata().to_xmp();
+
+        assert!(xmp.contains("<xmpDM:logComment>Fish &amp; chips &lt;3</xmpDM:logComment>"));
+        assert!(xmp.contains("<ss:sourceIn>00:00:15.000</ss:sourceIn>"));
+    }
+
+    #[test]
+    fn timecode_of_video_sources() {
+        let mut meta = metadata();
+        meta.set_frame_rate(FrameRate::FPS_29_97_DF);
+
+        assert_eq!(meta.timecode_in.as_deref(), Some("00:00:15;00"));
+        assert!(meta
+            .container_tags()
+            .contains(&("timecode", "00:00:15;00".to_owned())));
+        let xmp = meta.to_xmp();
+        assert!(xmp.contains("<xmpDM:timeFormat>2997DropTimecode</xmpDM:timeFormat>"));
+        assert!(xmp.contains("<xmpDM:timeValue>00:00:15;00</xmpDM:timeValue>"));
+    }
+
+    #[test]
+    fn recorder_metadata() {
+        let mut meta = metadata();
+        meta.set_recorder(&BwfInfo {
+            sample_rate: 48000,
+            bext: Some(
+                crate::bwf::Bext::parse(&crate::bwf::tests::bext_body(48000 * 3600)).unwrap(),
+            ),
+            ixml: Some(crate::bwf::Ixml::parse(crate::bwf::tests::IXML.as_bytes())),
+        });
+
+        let recorder = meta.recorder.as_ref().unwrap();
+        assert_eq!(recorder.scene.as_deref(), Some("1A"));
+        assert_eq!(recorder.track_names, ["Boom", "Lav"]);
+        // An hour and 15 seconds of frames at 29.97, counted as non-drop timecode.
+        assert_eq!(recorder.start_timecode.as_deref(), Some("01:00:11:12"));
+        assert!(meta
+            .to_xmp()
+            .contains("<ss:recorderScene>1A</ss:recorderScene>"));
+    }
+}
@@ -0,0 +1,181 @@
+//! SRT and WebVTT captions made from the script text of each take.
+
+use std::{fmt::Write, path::Path, time::Duration};
+
+use crate::export::Timeline;
+
+/// Longest caption line, in characters, before the text is wrapped.
+const MAX_LINE_LEN: usize = 42;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+/// A single cue covering a whole slice.
+pub fn slice_cues(text: &str, duration: Duration) -> Vec<Cue> {
+    vec![Cue {
+        start: Duration::ZERO,
+        end: duration,
+        text: text.to_owned(),
+    }]
+}
+
+/// One cue per clip of a timeline, at the clip's position on the timeline.
+pub fn timeline_cues(timeline: &Timeline) -> Vec<Cue> {
+    timeline
+        .clips
+        .iter()
+        .filter_map(|clip| {
+            Some(Cue {
+                start: clip.record_in,
+                end: clip.record_out(),
+                text: clip.take.chunk_text.clone()?,
+            })
+        })
+        .collect()
+}
+
+/// Wrap text into lines of at most [`MAX_LINE_LEN`] characters, breaking on whitespace.
+fn wrap(text: &str) -> String {
+    let mut lines: Vec<String> = vec![];
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if line.chars().count() + 1 + word.chars().count() <= MAX_LINE_LEN => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_owned()),
+        }
+    }
+    lines.join("\n")
+}
+
+fn format_time(time: Duration, separator: char) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+    for (i, cue) in cues.iter().enumerate() {
+        writeln!(srt, "{}", i + 1).unwrap();
+        writeln!(
+            srt,
+            "{} --> {}",
+            format_time(cue.start, ','),
+            format_time(cue.end, ',')
+        )
+        .unwrap();
+        writeln!(srt, "{}", wrap(&cue.text)).unwrap();
+        writeln!(srt).unwrap();
+    }
+    srt
+}
+
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut vtt = "WEBVTT\n\n".to_owned();
+    for cue in cues {
+        writeln!(
+            vtt,
+            "{} --> {}",
+            format_time(cue.start, '.'),
+            format_time(cue.end, '.')
+        )
+        .unwrap();
+        // "-->" isn't allowed in cue text.
+        writeln!(vtt, "{}", wrap(&cue.text).replace("-->", "->")).unwrap();
+        writeln!(vtt).unwrap();
+    }
+    vtt
+}
+
+/// Write `cues` as both `<path>.srt` and `<path>.vtt`.
+pub fn write_captions(path: &Path, cues: &[Cue]) -> anyhow::Result<()> {
+    std::fs::write(path.with_extension("srt"), to_srt(cues))?;
+    std::fs::write(path.with_extension("vtt"), to_vtt(cues))?;
+    Ok(())
+}
+
+/// Build a `subtitles` filter that burns in the given SRT file.
+pub fn burn_in_filter(srt: &Path) -> String {
+    // The path is parsed twice, once as a filter option and once by the filtergraph.
+    let mut escaped = String::new();
+    for c in srt.to_string_lossy().chars() {
+        if matches!(c, '\\' | ':' | '\'' | '[' | ']' | ',' | ';') {
+            escaped.push_str("\\\\\\");
+        }
+        escaped.push(c);
+    }
+    format!("subtitles={escaped}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_and_vtt() {
+        let cues = vec![
+            Cue {
+                start: Duration::from_millis(0),
+                end: Duration::from_millis(2500),
+                text: "Hello there.".to_owned(),
+            },
+            Cue {
+                start: Duration::from_millis(2500),
+                end: Duration::from_millis(3_723_004),
+                text: "General Kenobi!".to_owned(),
+            },
+        ];
+
+        assert_eq!(
+            to_srt(&cues),
+            "1\n00:00:00,000 --> 00:00:02,500\nHello there.\n\n2\n00:00:02,500 --> 01:02:03,004\nGeneral Kenobi!\n\n"
+        );
+        assert_eq!(
+            to_vtt(&cues),
+            "WEBVTT\n\n00:00:00.000 --> 00:00:02.500\nHello there.\n\n00:00:02.500 --> 01:02:03.004\nGeneral Kenobi!\n\n"
+        );
+    }
+
+    #[test]
+    fn long_lines_are_wrapped() {
+        let text = "The quick brown fox jumps over the lazy dog, and then keeps on running.";
+
+        assert_eq!(
+            wrap(text),
+            "The quick brown fox jumps over the lazy\ndog, and then keeps on running."
+        );
+    }
+
+    #[test]
+    fn rough_cut_cues_are_cumulative() {
+        let mut timeline = crate::export::tests::timeline();
+        timeline.clips[0].take.chunk_text = Some("one".to_owned());
+        timeline.clips[1].take.chunk_text = Some("two".to_owned());
+
+        let cues = timeline_cues(&timeline);
+
+        assert_eq!(cues[1].start, Duration::from_secs(2));
+        assert_eq!(cues[1].end, Duration::from_secs(3));
+        assert_eq!(cues[1].text, "two");
+    }
+
+    #[test]
+    fn burn_in_path_is_escaped() {
+        assert_eq!(
+            burn_in_filter(Path::new("/out/chunk-1:good.srt")),
+            "subtitles=/out/chunk-1\\\\\\:good.srt"
+        );
+    }
+}
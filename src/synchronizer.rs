@@ -2,107 +2,107 @@ use std::{
     collections::HashMap,
     fs::File,
     path::{Path, PathBuf},
-    time::Duration,
 };
 
+use log::*;
 use serde::{Deserialize, Serialize};
-use symphonia::core::{
-    codecs::{DecoderOptions, CODEC_TYPE_NULL},
-    formats::FormatOptions,
-    io::MediaSourceStream,
-    meta::MetadataOptions,
-    probe::Hint,
-};
 
-use crate::{timestamp::Timestamp, tui};
+use crate::{
+    bwf::BwfInfo,
+    data::{Session, Track},
+    timestamp::Timestamp,
+    tui,
+    wav::WavFile,
+};
 
 pub trait TrackSync {
     fn find_sync_offset(&self, path: impl AsRef<Path>) -> anyhow::Result<Timestamp>;
 }
 
+/// Finds sync offsets from the BWF time references that field recorders write, by lining up the
+/// time of day of a track with that of the session's audio.
 #[derive(Debug)]
-pub struct FileTrackSyncer;
+pub struct TimeReferenceSyncer {
+    /// Time of day at the start of the session.
+    session_start: Timestamp,
+}
 
-impl FileTrackSyncer {
-    pub fn new() -> Self {
-        Self
+impl TimeReferenceSyncer {
+    /// A syncer for the session, if one of its WAV tracks has a time reference.
+    pub fn for_session(session: &Session) -> Option<Self> {
+        session.tracks.iter().find_map(|track| {
+            let time_reference = time_reference(&track.file).ok()?;
+            Some(Self {
+                session_start: time_reference + track.sync_offset,
+            })
+        })
     }
 }
 
-impl TrackSync for FileTrackSyncer {
+impl TrackSync for TimeReferenceSyncer {
     fn find_sync_offset(&self, path: impl AsRef<Path>) -> anyhow::Result<Timestamp> {
-        let path = path.as_ref();
-        let src = File::open(&path)?;
-        let media_source = MediaSourceStream::new(Box::new(src), Default::default());
-
-        let mut hint = Hint::new();
-        if let Some(ext) = path.extension() {
-            hint.with_extension(ext.to_str().unwrap());
-        }
-
-        let meta_opts: MetadataOptions = Default::default();
-        let fmt_opts: FormatOptions = Default::default();
-
-        let probed =
-            symphonia::default::get_probe().format(&hint, media_source, &fmt_opts, &meta_opts)?;
+        Ok(self.session_start - time_reference(path.as_ref())?)
+    }
+}
 
-        loop {
-            use symphonia::core::errors::Error;
+/// Time of day at the first sample of a WAV file.
+fn time_reference(path: &Path) -> anyhow::Result<Timestamp> {
+    let wav = WavFile::open(path)?;
+    BwfInfo::read(&wav)
+        .time_reference()
+        .ok_or_else(|| anyhow::anyhow!("{} has no BWF time reference", path.display()))
+}
 
-            let mut format = probed.format;
-            let track = format
-                .tracks()
+/// Attach every WAV in `recordings` to the session it was recorded during, going by BWF time
+/// references. Returns the recordings that didn't overlap any session.
+pub fn attach_recordings(
+    sessions: &mut HashMap<String, Session>,
+    recordings: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut unmatched = vec![];
+    'recordings: for recording in recordings {
+        let span = WavFile::open(recording).ok().and_then(|wav| {
+            let start = BwfInfo::read(&wav).time_reference()?;
+            Some((start, start + wav.duration()))
+        });
+        let Some((start, end)) = span else {
+            debug!("{} has no BWF time reference", recording.display());
+            unmatched.push(recording.clone());
+            continue;
+        };
+
+        for session in sessions.values_mut() {
+            let Some(syncer) = TimeReferenceSyncer::for_session(session) else {
+                continue;
+            };
+            let length = session
+                .tracks
                 .iter()
-                .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
-                .ok_or(anyhow::anyhow!("no supported audio format found"))?;
-
-            let dec_opts: DecoderOptions = Default::default();
-            let mut decoder =
-                symphonia::default::get_codecs().make(&track.codec_params, &dec_opts)?;
-            let track_id = track.id;
-
-            loop {
-                let packet = match format.next_packet() {
-                    Ok(packet) => packet,
-                    Err(Error::ResetRequired) => {
-                        continue;
-                    }
-                    Err(err) => return Err(err.into()),
-                };
-
-                // Consume any new metadata that has been read since the last packet.
-                while !format.metadata().is_latest() {
-                    // Pop the old head of the metadata queue.
-                    format.metadata().pop();
-
-                    // Consume the new metadata at the head of the metadata queue.
-                }
-
-                if packet.track_id() != track_id {
-                    continue;
-                }
-
-                match decoder.decode(&packet) {
-                    Ok(decoded) => {}
-                    Err(Error::IoError(_)) => {
-                        // The packet failed to decode due to an IO error, skip the packet.
-                        continue;
-                    }
-                    Err(Error::DecodeError(_)) => {
-                        // The packet failed to decode due to invalid data, skip the packet.
-                        continue;
-                    }
-                    Err(err) => return Err(err.into()),
-                }
+                .filter_map(|track| WavFile::open(&track.file).ok())
+                .map(|wav| wav.duration())
+                .max()
+                .unwrap_or_default();
+            if start < syncer.session_start + length && syncer.session_start < end {
+                let sync_offset = syncer.session_start - start;
+                info!(
+                    "attaching {} to session {} at {}",
+                    recording.display(),
+                    session.session_id,
+                    sync_offset
+                );
+                session.tracks.push(Track {
+                    file: recording.clone(),
+                    sync_offset,
+                });
+                continue 'recordings;
             }
-
-            break;
         }
-
-        todo!("find sync offset");
+        unmatched.push(recording.clone());
     }
+    unmatched
 }
 
+#[derive(Debug, Default)]
 pub struct AskUserSyncer;
 
 impl AskUserSyncer {
@@ -115,13 +115,17 @@ impl TrackSync for AskUserSyncer {
     fn find_sync_offset(&self, path: impl AsRef<Path>) -> anyhow::Result<Timestamp> {
         let path = path.as_ref();
         eprintln!(
-            "Enter sync timestamp for {:?}: [ format: HH:MM:SS.mmm eg. 01:02:03.123 ]",
+            "Enter sync timestamp for {:?}: [ eg. 01:02:03.123, 1h2m3.5s or 01:02:03:12@30, negative if the video started after the audio ]",
             path
         );
         loop {
             let buf = tui::prompt();
-            let Ok(sync_offset) = Timestamp::parse(buf) else {
-                continue;
+            let sync_offset = match Timestamp::parse(buf) {
+                Ok(sync_offset) => sync_offset,
+                Err(e) => {
+                    eprintln!("{:#}", e);
+                    continue;
+                }
             };
 
             return Ok(sync_offset);
@@ -129,6 +133,30 @@ impl TrackSync for AskUserSyncer {
     }
 }
 
+/// Where a session's sync offset came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncSource {
+    /// Passed in by the caller.
+    Given,
+    /// Found in the [`SyncerCache`].
+    Cached,
+    /// Worked out from BWF time references by [`TimeReferenceSyncer`].
+    TimeReference,
+    /// Found by the fallback syncer.
+    Syncer,
+}
+
+impl SyncSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            SyncSource::Given => "given",
+            SyncSource::Cached => "cached",
+            SyncSource::TimeReference => "time reference",
+            SyncSource::Syncer => "syncer",
+        }
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SyncerCache {
     entries: HashMap<String, Timestamp>,
@@ -152,14 +180,72 @@ impl SyncerCache {
     }
 
     pub fn get(&self, file_name: impl AsRef<str>) -> Option<Timestamp> {
-        let mut file_name = file_name.as_ref();
+        let file_name = file_name.as_ref();
 
         self.entries.get(file_name).copied()
     }
 
+    /// Whether offsets were set since the cache was loaded.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
     pub fn set(&mut self, file_name: impl AsRef<str>, timestamp: Timestamp) {
         let file_name = file_name.as_ref();
         self.entries.insert(file_name.to_owned(), timestamp);
         self.dirty = true;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{bwf::tests::bext_body, wav::tests::write_test_wav};
+
+    /// A second of silence at 1 kHz in `dir`, starting `start` samples after midnight.
+    fn recording(dir: &Path, name: &str, start: u64) -> PathBuf {
+        let path = dir.join(format!("{name}.wav"));
+        write_test_wav(&path, 1000, &[0; 1000]);
+        let mut wav = WavFile::open(&path).unwrap();
+        wav.set_chunk(*b"bext", bext_body(start));
+        let tmp = path.with_extension("tmp.wav");
+        wav.write_slice(
+            &tmp,
+            crate::audio::SampleRange {
+                start: 0,
+                end: 1000,
+                sample_rate: 1000,
+            },
+            Duration::ZERO,
+        )
+        .unwrap();
+        std::fs::rename(tmp, &path).unwrap();
+        path
+    }
+
+    #[test]
+    fn recordings_are_lined_up_by_time_of_day() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let session = Session {
+            session_id: "1".to_owned(),
+            tracks: vec![Track {
+                file: recording(dir, "session", 10_000),
+                sync_offset: Timestamp::from_millis(200),
+            }],
+        };
+        let mut sessions = HashMap::from([("1".to_owned(), session)]);
+        let late = recording(dir, "late", 10_500);
+        let elsewhere = recording(dir, "elsewhere", 50_000);
+
+        let unmatched = attach_recordings(&mut sessions, &[late.clone(), elsewhere.clone()]);
+
+        assert_eq!(unmatched, [elsewhere]);
+        let track = &sessions["1"].tracks[1];
+        assert_eq!(track.file, late);
+        // Session time 0 is 10.2 s after midnight, 0.3 s before the recording started.
+        assert_eq!(track.sync_offset, -Timestamp::from_millis(300));
+    }
+}
@@ -0,0 +1,417 @@
+//! SMPTE timecode, at integer, fractional (23.976, 29.97, ...) and drop-frame rates.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::Context;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::timestamp::Timestamp;
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+/// A frame rate, as an exact fraction of frames per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameRate {
+    num: u32,
+    den: u32,
+    drop_frame: bool,
+}
+
+impl FrameRate {
+    pub const FPS_23_976: FrameRate = FrameRate::ntsc(24);
+    pub const FPS_24: FrameRate = FrameRate::integer(24);
+    pub const FPS_25: FrameRate = FrameRate::integer(25);
+    pub const FPS_29_97: FrameRate = FrameRate::ntsc(30);
+    pub const FPS_29_97_DF: FrameRate = FrameRate::FPS_29_97.with_drop_frame();
+    pub const FPS_30: FrameRate = FrameRate::integer(30);
+    pub const FPS_50: FrameRate = FrameRate::integer(50);
+    pub const FPS_59_94: FrameRate = FrameRate::ntsc(60);
+    pub const FPS_59_94_DF: FrameRate = FrameRate::FPS_59_94.with_drop_frame();
+    pub const FPS_60: FrameRate = FrameRate::integer(60);
+
+    pub fn new(num: u32, den: u32) -> anyhow::Result<Self> {
+        if num == 0 || den == 0 {
+            anyhow::bail!("frame rate {num}/{den} isn't positive");
+        }
+        let (mut a, mut b) = (num, den);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        Ok(Self {
+            num: num / a,
+            den: den / a,
+            drop_frame: false,
+        })
+    }
+
+    pub const fn integer(fps: u32) -> Self {
+        Self {
+            num: fps,
+            den: 1,
+            drop_frame: false,
+        }
+    }
+
+    /// The NTSC rate just below `fps`, eg. 29.97 for 30.
+    const fn ntsc(fps: u32) -> Self {
+        Self {
+            num: fps * 1000,
+            den: 1001,
+            drop_frame: false,
+        }
+    }
+
+    const fn with_drop_frame(self) -> Self {
+        Self {
+            drop_frame: true,
+            ..self
+        }
+    }
+
+    /// Count timecode in drop-frame. Only 29.97 and 59.94 have a drop-frame timecode.
+    pub fn drop_frame(self) -> anyhow::Result<Self> {
+        if !self.supports_drop_frame() {
+            anyhow::bail!("{} has no drop-frame timecode", self);
+        }
+        Ok(self.with_drop_frame())
+    }
+
+    pub fn supports_drop_frame(&self) -> bool {
+        self.den == 1001 && self.num.is_multiple_of(30_000)
+    }
+
+    pub fn is_drop_frame(&self) -> bool {
+        self.drop_frame
+    }
+
+    pub fn num(&self) -> u32 {
+        self.num
+    }
+
+    pub fn den(&self) -> u32 {
+        self.den
+    }
+
+    /// The whole number of frames counted per timecode second, eg. 30 for 29.97.
+    pub fn nominal(&self) -> u32 {
+        (self.num + self.den / 2) / self.den
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+
+    /// The frame nearest to `timestamp`.
+    pub fn frames(&self, timestamp: Timestamp) -> i64 {
+        let nanos = timestamp.as_nanos() as i128;
+        let scaled = nanos.abs() * self.num as i128;
+        let divisor = self.den as i128 * NANOS_PER_SEC;
+        let frames = ((scaled + divisor / 2) / divisor) as i64;
+        if nanos < 0 {
+            -frames
+        } else {
+            frames
+        }
+    }
+
+    /// The start of frame `frames`, to the nearest nanosecond.
+    pub fn timestamp(&self, frames: i64) -> Timestamp {
+        let scaled = (frames as i128).abs() * self.den as i128 * NANOS_PER_SEC;
+        let nanos = ((scaled + self.num as i128 / 2) / self.num as i128) as i64;
+        Timestamp::from_nanos(if frames < 0 { -nanos } else { nanos })
+    }
+
+    /// Frames skipped in the count at the start of every minute, except every tenth.
+    fn dropped_frames(&self) -> i64 {
+        if self.drop_frame {
+            self.nominal() as i64 / 15
+        } else {
+            0
+        }
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.den {
+            1 => write!(f, "{}", self.num)?,
+            1001 => {
+                let rate = format!("{:.3}", self.as_f64());
+                write!(f, "{}", rate.trim_end_matches('0').trim_end_matches('.'))?
+            }
+            den => write!(f, "{}/{}", self.num, den)?,
+        }
+        if self.drop_frame {
+            write!(f, "df")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FrameRate {
+    type Err = anyhow::Error;
+
+    /// A whole number (`25`), an NTSC rate (`23.976`, `29.97`), or a fraction (`30000/1001`),
+    /// followed by `df` for drop-frame timecode.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let (rate, drop_frame) = match lower.strip_suffix("df") {
+            Some(rate) => (rate.trim(), true),
+            None => (lower.as_str(), false),
+        };
+
+        let parsed = if let Some((num, den)) = rate.split_once('/') {
+            Self::new(num.parse()?, den.parse()?)?
+        } else if let Ok(fps) = rate.parse::<u32>() {
+            Self::new(fps, 1)?
+        } else {
+            let fps: f64 = rate
+                .parse()
+                .with_context(|| format!("invalid frame rate {s:?}"))?;
+            let nominal = (fps * 1.001).round();
+            if nominal < 1.0 || nominal > u32::MAX as f64 / 1000.0 {
+                anyhow::bail!("invalid frame rate {s:?}");
+            }
+            let ntsc = Self::ntsc(nominal as u32);
+            if (ntsc.as_f64() - fps).abs() > 0.005 {
+                anyhow::bail!(
+                    "frame rate {s:?} should be a whole number, an NTSC rate or a fraction"
+                );
+            }
+            ntsc
+        };
+
+        if drop_frame {
+            parsed.drop_frame()
+        } else {
+            Ok(parsed)
+        }
+    }
+}
+
+impl Serialize for FrameRate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FrameRate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let buf = String::deserialize(deserializer)?;
+        buf.parse()
+            .map_err(serde::de::Error::custom::<anyhow::Error>)
+    }
+}
+
+/// A frame count at a frame rate, written as `HH:MM:SS:FF`, or `HH:MM:SS;FF` for drop-frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Timecode {
+    pub frames: i64,
+    pub rate: FrameRate,
+}
+
+impl Timecode {
+    pub fn new(frames: i64, rate: FrameRate) -> Self {
+        Self { frames, rate }
+    }
+
+    /// The timecode of the frame nearest to `timestamp`.
+    pub fn from_timestamp(timestamp: Timestamp, rate: FrameRate) -> Self {
+        Self::new(rate.frames(timestamp), rate)
+    }
+
+    pub fn to_timestamp(self) -> Timestamp {
+        self.rate.timestamp(self.frames)
+    }
+
+    /// Parse `HH:MM:SS:FF`. A `;` before the frames, as in `HH:MM:SS;FF`, means drop-frame.
+    pub fn parse(s: &str, rate: FrameRate) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let rate = if s.contains(';') && !rate.is_drop_frame() {
+            rate.drop_frame()?
+        } else {
+            rate
+        };
+
+        let parts: Vec<&str> = s.split([':', ';']).collect();
+        let [hours, minutes, seconds, frames] = parts[..] else {
+            anyhow::bail!("timecode {s:?} should look like HH:MM:SS:FF");
+        };
+        let number = |part: &str, what: &str| -> anyhow::Result<i64> {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                anyhow::bail!("{what} of timecode {s:?} should be a whole number");
+            }
+            part.parse()
+                .with_context(|| format!("{what} of timecode {s:?} is too large"))
+        };
+        let (hours, minutes, seconds, frames) = (
+            number(hours, "hours")?,
+            number(minutes, "minutes")?,
+            number(seconds, "seconds")?,
+            number(frames, "frames")?,
+        );
+        let nominal = rate.nominal() as i64;
+        if minutes >= 60 || seconds >= 60 || frames >= nominal {
+            anyhow::bail!("timecode {s:?} is out of range at {rate} fps");
+        }
+
+        let dropped = rate.dropped_frames();
+        let total_minutes = hours * 60 + minutes;
+        if dropped > 0 && seconds == 0 && frames < dropped && minutes % 10 != 0 {
+            anyhow::bail!("timecode {s:?} is skipped in drop-frame");
+        }
+        let count = (total_minutes * 60 + seconds) * nominal + frames
+            - dropped * (total_minutes - total_minutes / 10);
+
+        Ok(Self::new(if negative { -count } else { count }, rate))
+    }
+
+    /// Hours, minutes, seconds and frames, as counted by the timecode.
+    pub fn components(&self) -> (u64, u64, u64, u64) {
+        let nominal = self.rate.nominal() as i64;
+        let dropped = self.rate.dropped_frames();
+        let mut frames = self.frames.abs();
+        if dropped > 0 {
+            let per_ten_minutes = nominal * 600 - dropped * 9;
+            let per_minute = nominal * 60 - dropped;
+            let tens = frames / per_ten_minutes;
+            let rest = frames % per_ten_minutes;
+            frames += dropped * 9 * tens;
+            if rest > dropped {
+                frames += dropped * ((rest - dropped) / per_minute);
+            }
+        }
+        let seconds = frames / nominal;
+        (
+            (seconds / 3600) as u64,
+            (seconds / 60 % 60) as u64,
+            (seconds % 60) as u64,
+            (frames % nominal) as u64,
+        )
+    }
+}
+
+impl fmt::Display for Timecode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (hours, minutes, seconds, frames) = self.components();
+        write!(
+            f,
+            "{}{:02}:{:02}:{:02}{}{:02}",
+            if self.frames < 0 { "-" } else { "" },
+            hours,
+            minutes,
+            seconds,
+            if self.rate.is_drop_frame() { ';' } else { ':' },
+            frames
+        )
+    }
+}
+
+impl FromStr for Timecode {
+    type Err = anyhow::Error;
+
+    /// Timecode with its frame rate, eg. `01:00:00;00@29.97`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (timecode, rate) = s
+            .rsplit_once('@')
+            .ok_or(anyhow::anyhow!("timecode {s:?} has no frame rate, eg. @25"))?;
+        Self::parse(timecode, rate.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn parse_frame_rates() {
+        let parse = |s: &str| s.parse::<FrameRate>().unwrap();
+
+        assert_eq!(parse("25"), FrameRate::FPS_25);
+        assert_eq!(parse("23.976"), FrameRate::FPS_23_976);
+        assert_eq!(parse("23.98"), FrameRate::FPS_23_976);
+        assert_eq!(parse("29.97df"), FrameRate::FPS_29_97_DF);
+        assert_eq!(parse("30000/1001"), FrameRate::FPS_29_97);
+        assert_eq!(parse("50/2"), FrameRate::FPS_25);
+        assert_eq!(parse("59.94 DF"), FrameRate::FPS_59_94_DF);
+        assert!("25df".parse::<FrameRate>().is_err());
+        assert!("23.5".parse::<FrameRate>().is_err());
+        assert!("0".parse::<FrameRate>().is_err());
+
+        for rate in [
+            FrameRate::FPS_23_976,
+            FrameRate::FPS_29_97_DF,
+            FrameRate::FPS_59_94,
+            FrameRate::FPS_50,
+            FrameRate::new(1000, 3).unwrap(),
+        ] {
+            assert_eq!(parse(&rate.to_string()), rate);
+        }
+    }
+
+    #[test]
+    fn drop_frame_skips_frame_numbers() {
+        let rate = FrameRate::FPS_29_97_DF;
+        let tc = |frames| Timecode::new(frames, rate).to_string();
+
+        assert_eq!(tc(1799), "00:00:59;29");
+        assert_eq!(tc(1800), "00:01:00;02");
+        assert_eq!(tc(17982), "00:10:00;00");
+        // An hour of 29.97 is 107892 frames.
+        assert_eq!(tc(107892), "01:00:00;00");
+
+        assert_eq!(Timecode::parse("00:01:00;02", rate).unwrap().frames, 1800);
+        assert!(Timecode::parse("00:01:00;00", rate).is_err());
+        assert_eq!(Timecode::parse("00:10:00;00", rate).unwrap().frames, 17982);
+    }
+
+    #[test]
+    fn drop_frame_tracks_real_time() {
+        let timecode: Timecode = "01:00:00;00@29.97".parse().unwrap();
+
+        assert!(timecode.rate.is_drop_frame());
+        // Drop-frame timecode stays within a few milliseconds of the clock.
+        let drift = timecode.to_timestamp() - Duration::from_secs(3600);
+        assert!(drift.abs() < Duration::from_millis(5), "{drift}");
+    }
+
+    #[test]
+    fn non_drop_frame_ntsc_runs_slow() {
+        let timecode: Timecode = "01:00:00:00@23.976".parse().unwrap();
+
+        assert_eq!(timecode.frames, 86400);
+        assert_eq!(timecode.to_timestamp().to_string(), "01:00:03.600");
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn timecode_round_trips(frames in -10_000_000i64..10_000_000, rate in proptest::sample::select(vec![
+            FrameRate::FPS_23_976,
+            FrameRate::FPS_24,
+            FrameRate::FPS_25,
+            FrameRate::FPS_29_97,
+            FrameRate::FPS_29_97_DF,
+            FrameRate::FPS_59_94_DF,
+            FrameRate::FPS_60,
+        ])) {
+            let timecode = Timecode::new(frames, rate);
+
+            let parsed = Timecode::parse(&timecode.to_string(), rate).unwrap();
+            proptest::prop_assert_eq!(parsed, timecode);
+            let converted = Timecode::from_timestamp(timecode.to_timestamp(), rate);
+            proptest::prop_assert_eq!(converted, timecode);
+        }
+    }
+}
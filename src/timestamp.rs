@@ -1,37 +1,319 @@
 use std::{
-    ops::{Add, Sub},
+    ops::{Add, Neg, Sub},
     time::Duration,
 };
 
 use anyhow::Context;
 use serde::{Deserialize, Deserializer, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Timestamp(Duration);
+use crate::timecode::{FrameRate, Timecode};
+
+/// A signed position in time, with nanosecond precision.
+///
+/// Negative timestamps happen when tracks are offset against each other, eg. a video that was
+/// started after the audio recording has a negative sync offset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i64);
+
+const NANOS_PER_SEC: i64 = 1_000_000_000;
 
 impl Timestamp {
+    pub const ZERO: Timestamp = Timestamp(0);
+
+    /// Parse a timestamp in any of these forms, optionally with a leading `-`:
+    ///
+    /// - `SS`, `MM:SS` or `HH:MM:SS`, with a fraction of a second of any precision,
+    ///   eg. `12.5` or `01:02:03.004`
+    /// - hours, minutes, seconds and milliseconds with units, eg. `1h2m3.5s` or `250ms`
+    /// - SMPTE timecode with its frame rate, eg. `01:02:03:12@25` or `01:00:00;00@29.97`
+    /// - a sample count with its sample rate, eg. `48000@48000Hz`
     pub fn parse(timestamp: impl AsRef<str>) -> anyhow::Result<Self> {
-        let mut parts = timestamp.as_ref().split(':');
-        let hours = parts.next().unwrap().parse::<u64>().context("hours")?;
-        let minutes = parts.next().unwrap().parse::<u64>().context("minutes")?;
-        let rest = parts.next().unwrap();
-        let mut parts = rest.split('.');
-        let seconds = parts.next().unwrap().parse::<u64>().context("seconds")?;
-        let millis = parts.next().unwrap().parse::<u64>().context("millis")?;
+        let timestamp = timestamp.as_ref();
+        parse_signed(timestamp.trim()).with_context(|| format!("invalid timestamp {timestamp:?}"))
+    }
+
+    /// Write the timestamp in the given format.
+    pub fn format(self, format: TimestampFormat) -> String {
+        let sign = if self.is_negative() { "-" } else { "" };
+        let nanos = self.0.unsigned_abs();
+        match format {
+            TimestampFormat::Clock { precision } => {
+                let nanos = round_nanos(nanos, precision);
+                let secs = nanos / NANOS_PER_SEC as u64;
+                format!(
+                    "{sign}{:02}:{:02}:{:02}{}",
+                    secs / 3600,
+                    secs / 60 % 60,
+                    secs % 60,
+                    fraction(nanos, precision)
+                )
+            }
+            TimestampFormat::Units => {
+                let secs = nanos / NANOS_PER_SEC as u64;
+                let mut out = sign.to_owned();
+                if secs >= 3600 {
+                    out.push_str(&format!("{}h", secs / 3600));
+                }
+                if secs >= 60 {
+                    out.push_str(&format!("{}m", secs / 60 % 60));
+                }
+                let fraction = fraction(nanos, 9);
+                let fraction = fraction.trim_end_matches('0').trim_end_matches('.');
+                out.push_str(&format!("{}{}s", secs % 60, fraction));
+                out
+            }
+            TimestampFormat::Seconds { precision } => {
+                let nanos = round_nanos(nanos, precision);
+                format!(
+                    "{sign}{}{}",
+                    nanos / NANOS_PER_SEC as u64,
+                    fraction(nanos, precision)
+                )
+            }
+            TimestampFormat::Smpte { rate } => {
+                format!("{}@{rate}", Timecode::from_timestamp(self, rate))
+            }
+            TimestampFormat::Samples { rate } => {
+                format!("{sign}{}@{rate}Hz", nanos_to_units(nanos, rate))
+            }
+        }
+    }
+
+    /// The timestamp of a sample, at `rate` samples per second.
+    pub fn from_samples(samples: i64, rate: u32) -> Self {
+        let nanos = units_to_nanos(samples.unsigned_abs(), rate) as i64;
+        Self(if samples < 0 { -nanos } else { nanos })
+    }
+
+    /// The nearest sample, at `rate` samples per second.
+    pub fn as_samples(self, rate: u32) -> i64 {
+        let samples = nanos_to_units(self.0.unsigned_abs(), rate) as i64;
+        if self.is_negative() {
+            -samples
+        } else {
+            samples
+        }
+    }
+
+    pub const fn from_secs(secs: i64) -> Self {
+        Self(secs * NANOS_PER_SEC)
+    }
+
+    pub const fn from_millis(millis: i64) -> Self {
+        Self(millis * (NANOS_PER_SEC / 1000))
+    }
+
+    pub const fn from_nanos(nanos: i64) -> Self {
+        Self(nanos)
+    }
+
+    pub const fn as_nanos(self) -> i64 {
+        self.0
+    }
+
+    pub fn from_secs_f64(secs: f64) -> Self {
+        Self((secs * NANOS_PER_SEC as f64).round() as i64)
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.0 as f64 / NANOS_PER_SEC as f64
+    }
+
+    pub const fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// The distance from zero, regardless of sign.
+    pub const fn abs(self) -> Duration {
+        Duration::from_nanos(self.0.unsigned_abs())
+    }
+
+    /// Convert to a duration, or `None` if the timestamp is negative.
+    pub fn to_duration(self) -> Option<Duration> {
+        (!self.is_negative()).then(|| self.abs())
+    }
+
+    /// Convert to a duration, clamping negative timestamps to zero.
+    pub fn saturating_duration(self) -> Duration {
+        self.to_duration().unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.format(TimestampFormat::default()))
+    }
+}
+
+/// How to write a timestamp. Every format can be read back by [`Timestamp::parse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// `HH:MM:SS.fff`, with `precision` digits after the decimal point.
+    Clock { precision: u8 },
+    /// `1h2m3.5s`, leaving out zero hours and minutes.
+    Units,
+    /// Seconds, with `precision` digits after the decimal point, eg. `3723.5`.
+    Seconds { precision: u8 },
+    /// SMPTE timecode, `HH:MM:SS:FF@rate`, rounded to the nearest frame.
+    Smpte { rate: FrameRate },
+    /// A sample count, `N@rateHz`, rounded to the nearest sample.
+    Samples { rate: u32 },
+}
+
+impl Default for TimestampFormat {
+    fn default() -> Self {
+        TimestampFormat::Clock { precision: 3 }
+    }
+}
+
+/// Round nanoseconds to `precision` digits of a second.
+fn round_nanos(nanos: u64, precision: u8) -> u64 {
+    let unit = 10u64.pow(9 - precision.min(9) as u32);
+    (nanos + unit / 2) / unit * unit
+}
+
+/// The fractional part of a second, with a leading `.`, or nothing if `precision` is zero.
+fn fraction(nanos: u64, precision: u8) -> String {
+    if precision == 0 {
+        return String::new();
+    }
+    let digits = format!("{:09}", nanos % NANOS_PER_SEC as u64);
+    format!(".{}", &digits[..precision.min(9) as usize])
+}
+
+fn nanos_to_units(nanos: u64, per_sec: u32) -> u64 {
+    let scaled = nanos as u128 * per_sec as u128;
+    ((scaled + NANOS_PER_SEC as u128 / 2) / NANOS_PER_SEC as u128) as u64
+}
+
+fn units_to_nanos(units: u64, per_sec: u32) -> u64 {
+    let scaled = units as u128 * NANOS_PER_SEC as u128;
+    ((scaled + per_sec as u128 / 2) / per_sec as u128) as u64
+}
+
+fn parse_signed(s: &str) -> anyhow::Result<Timestamp> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let nanos = if let Some((value, rate)) = s.rsplit_once('@') {
+        match rate.strip_suffix("Hz").or_else(|| rate.strip_suffix("hz")) {
+            Some(rate) => parse_samples(value, rate)?,
+            None => parse_smpte(value, rate)?,
+        }
+    } else if s.contains(|c: char| c.is_ascii_alphabetic()) {
+        parse_units(s)?
+    } else {
+        parse_clock(s)?
+    };
+    let nanos = i64::try_from(nanos).context("timestamp is too large")?;
+    Ok(Timestamp(if negative { -nanos } else { nanos }))
+}
 
-        Ok((Duration::from_secs(hours * 3600 + minutes * 60 + seconds)
-            + Duration::from_millis(millis))
-        .into())
+fn parse_integer(s: &str, what: &str) -> anyhow::Result<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        anyhow::bail!("{what} should be a whole number, not {s:?}");
     }
+    s.parse().with_context(|| format!("{what} is too large"))
+}
 
-    pub fn to_string(&self) -> String {
-        format!(
-            "{:02}:{:02}:{:02}.{:03}",
-            self.0.as_secs() / 3600,
-            self.0.as_secs() / 60,
-            self.0.as_secs() % 60,
-            self.0.subsec_millis()
-        )
+/// Parse a decimal number of any precision, as whole units and billionths of a unit.
+fn parse_decimal(s: &str, what: &str) -> anyhow::Result<(u64, u64)> {
+    let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+    if whole.is_empty() && frac.is_empty() {
+        anyhow::bail!("missing {what}");
+    }
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        parse_integer(whole, what)?
+    };
+    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+        anyhow::bail!("{what} should be a number, not {s:?}");
+    }
+    // Anything past nanoseconds is dropped.
+    let frac: String = frac.chars().chain(std::iter::repeat('0')).take(9).collect();
+    Ok((whole, frac.parse().unwrap()))
+}
+
+/// `SS`, `MM:SS` or `HH:MM:SS`, each with an optional fraction.
+fn parse_clock(s: &str) -> anyhow::Result<u128> {
+    let parts: Vec<&str> = s.split(':').collect();
+    if parts.len() > 3 {
+        anyhow::bail!("expected at most hours, minutes and seconds");
+    }
+    let (seconds, frac) = parse_decimal(parts[parts.len() - 1], "seconds")?;
+    let mut total = seconds as u128;
+    let names = ["minutes", "hours"];
+    for (i, part) in parts[..parts.len() - 1].iter().rev().enumerate() {
+        if total >= 60u128.pow(i as u32 + 1) {
+            anyhow::bail!("{} should be less than 60", ["seconds", "minutes"][i]);
+        }
+        total += parse_integer(part, names[i])? as u128 * 60u128.pow(i as u32 + 1);
+    }
+    Ok(total * NANOS_PER_SEC as u128 + frac as u128)
+}
+
+/// Numbers with `h`, `m`, `s` or `ms` units, largest unit first, eg. `1h2m3.5s`.
+fn parse_units(s: &str) -> anyhow::Result<u128> {
+    const UNITS: [(&str, u128); 4] = [
+        ("h", 3600 * NANOS_PER_SEC as u128),
+        ("m", 60 * NANOS_PER_SEC as u128),
+        ("s", NANOS_PER_SEC as u128),
+        ("ms", NANOS_PER_SEC as u128 / 1000),
+    ];
+    let mut rest = s;
+    let mut next_unit = 0;
+    let mut total = 0;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or(anyhow::anyhow!("{rest:?} has no unit"))?;
+        let (number, tail) = rest.split_at(number_len);
+        let unit_len = tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+
+        let Some(index) = UNITS.iter().position(|(name, _)| *name == unit) else {
+            anyhow::bail!("unknown unit {unit:?}, expected h, m, s or ms");
+        };
+        if index < next_unit {
+            anyhow::bail!("units should go from largest to smallest, and only appear once");
+        }
+        next_unit = index + 1;
+
+        let (whole, frac) = parse_decimal(number, unit)?;
+        let nanos_per_unit = UNITS[index].1;
+        total += whole as u128 * nanos_per_unit + frac as u128 * nanos_per_unit / 1_000_000_000;
+        rest = tail;
+    }
+    Ok(total)
+}
+
+/// SMPTE timecode `HH:MM:SS:FF`, or `HH:MM:SS;FF` for drop-frame, at `rate`.
+fn parse_smpte(s: &str, rate: &str) -> anyhow::Result<u128> {
+    let timecode = Timecode::parse(s, rate.parse::<FrameRate>()?)?;
+    Ok(timecode.to_timestamp().as_nanos() as u128)
+}
+
+/// A sample count at `rate` samples per second.
+fn parse_samples(s: &str, rate: &str) -> anyhow::Result<u128> {
+    let rate = parse_integer(rate, "sample rate")?;
+    let rate = u32::try_from(rate)
+        .ok()
+        .filter(|&rate| rate > 0)
+        .context("sample rate should be between 1 and 2^32")?;
+    let samples = parse_integer(s, "samples")?;
+    let nanos = samples as u128 * NANOS_PER_SEC as u128;
+    Ok((nanos + rate as u128 / 2) / rate as u128)
+}
+
+impl std::str::FromStr for Timestamp {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
     }
 }
 
@@ -40,7 +322,13 @@ impl Serialize for Timestamp {
     where
         S: serde::Serializer,
     {
-        self.to_string().serialize(serializer)
+        // Every digit down to nanoseconds, so saved timestamps load back exactly, but no more
+        // than milliseconds when the rest are zero.
+        let mut formatted = self.format(TimestampFormat::Clock { precision: 9 });
+        while formatted.ends_with('0') && formatted.len() - formatted.rfind('.').unwrap() > 4 {
+            formatted.pop();
+        }
+        formatted.serialize(serializer)
     }
 }
 
@@ -50,20 +338,35 @@ impl<'de> Deserialize<'de> for Timestamp {
         D: Deserializer<'de>,
     {
         let buf = String::deserialize(deserializer)?;
-        let result = Self::parse(&buf).map_err(serde::de::Error::custom::<anyhow::Error>);
-        result
+        Self::parse(&buf).map_err(serde::de::Error::custom::<anyhow::Error>)
     }
 }
 
-impl From<Duration> for Timestamp {
-    fn from(duration: Duration) -> Self {
-        Self(duration)
+impl TryFrom<Duration> for Timestamp {
+    type Error = anyhow::Error;
+
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        let nanos = i64::try_from(duration.as_nanos())
+            .with_context(|| format!("{duration:?} is too long for a timestamp"))?;
+        Ok(Self(nanos))
     }
 }
 
-impl From<Timestamp> for Duration {
-    fn from(timestamp: Timestamp) -> Self {
-        timestamp.0
+impl TryFrom<Timestamp> for Duration {
+    type Error = anyhow::Error;
+
+    fn try_from(timestamp: Timestamp) -> Result<Self, Self::Error> {
+        timestamp
+            .to_duration()
+            .ok_or(anyhow::anyhow!("negative timestamp: {}", timestamp))
+    }
+}
+
+impl Neg for Timestamp {
+    type Output = Timestamp;
+
+    fn neg(self) -> Self::Output {
+        Self(-self.0)
     }
 }
 
@@ -71,7 +374,7 @@ impl Add for Timestamp {
     type Output = Timestamp;
 
     fn add(self, rhs: Self) -> Self::Output {
-        (self.0 + rhs.0).into()
+        Self(self.0 + rhs.0)
     }
 }
 
@@ -79,23 +382,23 @@ impl Add<Duration> for Timestamp {
     type Output = Timestamp;
 
     fn add(self, rhs: Duration) -> Self::Output {
-        (self.0 + rhs).into()
+        self + Self::try_from(rhs).expect("overflow when adding a duration to a timestamp")
     }
 }
 
 impl Sub for Timestamp {
-    type Output = Duration;
+    type Output = Timestamp;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        self.0 - rhs.0
+        Self(self.0 - rhs.0)
     }
 }
 
 impl Sub<Duration> for Timestamp {
-    type Output = Duration;
+    type Output = Timestamp;
 
     fn sub(self, rhs: Duration) -> Self::Output {
-        (self.0 - rhs).into()
+        self - Self::try_from(rhs).expect("overflow when subtracting a duration from a timestamp")
     }
 }
 
@@ -111,7 +414,7 @@ mod tests {
 
         let duration = Timestamp::parse(timestamp).unwrap();
 
-        assert_eq!(duration, time::Duration::from_millis(0).into());
+        assert_eq!(duration, Timestamp::from_millis(0));
     }
 
     #[test]
@@ -120,6 +423,181 @@ mod tests {
 
         let duration = Timestamp::parse(timestamp).unwrap();
 
-        assert_eq!(duration, time::Duration::from_millis(3723004).into());
+        assert_eq!(duration, Timestamp::from_millis(3723004));
+    }
+
+    #[test]
+    fn timestamp_past_an_hour() {
+        let timestamp = Timestamp::from_millis(3723004);
+
+        assert_eq!(timestamp.to_string(), "01:02:03.004");
+    }
+
+    #[test]
+    fn flexible_parsing() {
+        let parse = |s| Timestamp::parse(s).unwrap();
+        let millis = |ms| Timestamp::from_millis(ms);
+
+        assert_eq!(parse("12.5"), millis(12500));
+        assert_eq!(parse("12"), millis(12000));
+        assert_eq!(parse("2:03.25"), millis(123250));
+        assert_eq!(
+            parse("01:02:03.123456789"),
+            Timestamp::from_nanos(3_723_123_456_789)
+        );
+        assert_eq!(parse("1h2m3.5s"), millis(3723500));
+        assert_eq!(parse("1.5m"), millis(90000));
+        assert_eq!(parse("250ms"), millis(250));
+        assert_eq!(parse("01:02:03:12@25"), millis(3723480));
+        assert_eq!(parse("00:00:01;00@29.97"), millis(1001));
+        assert_eq!(parse("96000@48000Hz"), millis(2000));
+        assert_eq!(parse("-1m"), -millis(60000));
+    }
+
+    #[test]
+    fn bad_timestamps() {
+        for bad in [
+            "",
+            "-",
+            "1:2:3:4",
+            "1:60",
+            "1.2.3",
+            "1m2h",
+            "1x",
+            "1:02:03:25@25",
+            "1@0Hz",
+            "abc",
+        ] {
+            assert!(Timestamp::parse(bad).is_err(), "{bad:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let timestamp = Timestamp::from_millis(3723500);
+
+        assert_eq!(
+            timestamp.format(TimestampFormat::Clock { precision: 1 }),
+            "01:02:03.5"
+        );
+        assert_eq!(timestamp.format(TimestampFormat::Units), "1h2m3.5s");
+        assert_eq!(
+            timestamp.format(TimestampFormat::Seconds { precision: 2 }),
+            "3723.50"
+        );
+        assert_eq!(
+            timestamp.format(TimestampFormat::Smpte {
+                rate: FrameRate::FPS_30
+            }),
+            "01:02:03:15@30"
+        );
+        assert_eq!(
+            (-timestamp).format(TimestampFormat::Samples { rate: 1000 }),
+            "-3723500@1000Hz"
+        );
+        assert_eq!(
+            Timestamp::from_nanos(59_999_600).format(TimestampFormat::Clock { precision: 3 }),
+            "00:00:00.060"
+        );
+    }
+
+    /// Large enough for a month of footage.
+    const MAX_NANOS: i64 = 31 * 24 * 3600 * NANOS_PER_SEC;
+
+    proptest::proptest! {
+        #[test]
+        fn exact_formats_round_trip(nanos in -MAX_NANOS..MAX_NANOS) {
+            let timestamp = Timestamp::from_nanos(nanos);
+
+            for format in [
+                TimestampFormat::Clock { precision: 9 },
+                TimestampFormat::Units,
+                TimestampFormat::Seconds { precision: 9 },
+            ] {
+                let formatted = timestamp.format(format);
+                proptest::prop_assert_eq!(Timestamp::parse(&formatted).unwrap(), timestamp, "{}", formatted);
+            }
+        }
+
+        #[test]
+        fn rounded_formats_are_stable(nanos in -MAX_NANOS..MAX_NANOS, precision in 0u8..9) {
+            let timestamp = Timestamp::from_nanos(nanos);
+
+            for format in [
+                TimestampFormat::Clock { precision },
+                TimestampFormat::Seconds { precision },
+            ] {
+                let formatted = timestamp.format(format);
+                let parsed = Timestamp::parse(&formatted).unwrap();
+                proptest::prop_assert_eq!(parsed.format(format), formatted);
+                proptest::prop_assert!((parsed - timestamp).abs() <= time::Duration::from_nanos(5 * 10u64.pow(8 - precision as u32)));
+            }
+        }
+
+        #[test]
+        fn frames_round_trip(frames in -10_000_000i64..10_000_000, fps in 1u32..=120) {
+            let rate = FrameRate::integer(fps);
+            let formatted = rate.timestamp(frames).format(TimestampFormat::Smpte { rate });
+
+            proptest::prop_assert_eq!(rate.frames(Timestamp::parse(&formatted).unwrap()), frames, "{}", formatted);
+        }
+
+        #[test]
+        fn samples_round_trip(samples in -2_000_000_000i64..2_000_000_000, rate in 1u32..=384_000) {
+            let formatted = Timestamp::from_samples(samples, rate).format(TimestampFormat::Samples { rate });
+
+            proptest::prop_assert_eq!(Timestamp::parse(&formatted).unwrap().as_samples(rate), samples, "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn negative_timestamps() {
+        let timestamp = Timestamp::parse("-00:00:01.500").unwrap();
+
+        assert!(timestamp.is_negative());
+        assert_eq!(timestamp.abs(), time::Duration::from_millis(1500));
+        assert_eq!(timestamp.to_string(), "-00:00:01.500");
+        assert_eq!(timestamp.to_duration(), None);
+        assert_eq!(
+            serde_json::to_string(&timestamp).unwrap(),
+            "\"-00:00:01.500\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Timestamp>("\"-00:00:01.500\"").unwrap(),
+            timestamp
+        );
+    }
+
+    #[test]
+    fn serde_keeps_nanoseconds() {
+        let timestamp = Timestamp::from_nanos(-1_234_567_890);
+
+        let json = serde_json::to_string(&timestamp).unwrap();
+
+        assert_eq!(json, "\"-00:00:01.23456789\"");
+        assert_eq!(serde_json::from_str::<Timestamp>(&json).unwrap(), timestamp);
+    }
+
+    #[test]
+    fn long_durations_are_errors() {
+        assert_eq!(
+            Timestamp::try_from(time::Duration::from_millis(1500)).unwrap(),
+            Timestamp::from_millis(1500)
+        );
+        assert!(Timestamp::try_from(time::Duration::MAX).is_err());
+    }
+
+    #[test]
+    fn arithmetic_crosses_zero() {
+        let start = Timestamp::from_secs(1);
+        let offset = Timestamp::parse("-00:00:02.000").unwrap();
+
+        assert_eq!(start + offset, -Timestamp::from_secs(1));
+        assert_eq!(
+            (start + offset) - start,
+            offset,
+            "subtracting never underflows"
+        );
+        assert_eq!((start + offset).saturating_duration(), time::Duration::ZERO);
     }
 }
@@ -0,0 +1,232 @@
+//! Tighten take in and out points to the speech they contain.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use signalo::{
+    filters::{
+        classify::schmitt::{self, Schmitt},
+        mean::exp::mean::{self, Mean},
+    },
+    traits::{Filter, WithConfig},
+};
+
+use crate::{audio, timestamp::Timestamp};
+
+/// Length of the windows the audio level is measured over.
+const WINDOW: Duration = Duration::from_millis(10);
+
+/// How far below the threshold the level has to fall before speech is considered over.
+const HYSTERESIS_DB: f32 = 6.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrimOptions {
+    /// Level above which audio counts as speech, in dBFS.
+    pub threshold_db: f32,
+    /// Silence kept before the first and after the last detected speech.
+    pub padding: Duration,
+}
+
+impl Default for TrimOptions {
+    fn default() -> Self {
+        Self {
+            threshold_db: -45.0,
+            padding: Duration::from_millis(200),
+        }
+    }
+}
+
+/// How a take's in and out points were moved by trimming.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrimReport {
+    pub original_start: Timestamp,
+    pub original_end: Timestamp,
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+/// Measure the level of each window of `samples`, in dBFS.
+fn window_levels(samples: &[f32], sample_rate: u32) -> Vec<f32> {
+    let window = (audio::duration_to_samples(WINDOW, sample_rate) as usize).max(1);
+    samples
+        .chunks(window)
+        .map(|chunk| {
+            let rms = (chunk.iter().map(|s| s * s).sum::<f32>() / chunk.len() as f32).sqrt();
+            20.0 * rms.max(1e-10).log10()
+        })
+        .collect()
+}
+
+/// Gate a sequence of levels. The smoothing makes the gate release late, so the direction the
+/// levels are fed in decides which edge of the speech gets the extra margin.
+fn gate(levels: impl Iterator<Item = f32>, threshold_db: f32) -> Vec<bool> {
+    let mut smooth = Mean::with_config(mean::Config { inverse_width: 0.5 });
+    let mut trigger = Schmitt::with_config(schmitt::Config {
+        thresholds: [threshold_db - HYSTERESIS_DB, threshold_db],
+        outputs: [false, true],
+    });
+    levels
+        .map(|level| trigger.filter(smooth.filter(level).max(level)))
+        .collect()
+}
+
+/// Find the span of `samples` that contains speech, as offsets from the first sample.
+///
+/// Detection runs backwards to find the start and forwards to find the end, so that the slow
+/// release of the gate always errs on the side of keeping audio.
+pub fn find_speech(
+    samples: &[f32],
+    sample_rate: u32,
+    threshold_db: f32,
+) -> Option<(Duration, Duration)> {
+    let levels = window_levels(samples, sample_rate);
+
+    let forward = gate(levels.iter().copied(), threshold_db);
+    let last = forward.iter().rposition(|&on| on)?;
+
+    let mut backward = gate(levels.iter().rev().copied(), threshold_db);
+    backward.reverse();
+    let first = backward.iter().position(|&on| on)?;
+
+    let total = audio::samples_to_duration(samples.len() as u64, sample_rate);
+    let start = WINDOW * first as u32;
+    let end = (WINDOW * (last as u32 + 1)).min(total);
+    Some((start, end))
+}
+
+/// Work out new in and out points for a take from its audio.
+///
+/// `start` and `end` are the take's position in the session, `sync_offset` where the session
+/// starts in `audio_file`. The in and out points only ever move inwards.
+pub fn trim(
+    audio_file: &std::path::Path,
+    start: Timestamp,
+    end: Timestamp,
+    sync_offset: Timestamp,
+    options: &TrimOptions,
+) -> anyhow::Result<Option<TrimReport>> {
+    let start_in_file = start + sync_offset;
+    let (samples, sample_rate) = audio::decode_mono(
+        audio_file,
+        start_in_file.saturating_duration(),
+        (end + sync_offset).saturating_duration(),
+    )?;
+    let Some((speech_start, speech_end)) = find_speech(&samples, sample_rate, options.threshold_db)
+    else {
+        return Ok(None);
+    };
+
+    // A take starting before the file is decoded from the file's start, so the speech is found
+    // that much later into the take.
+    let before_file = (-start_in_file).saturating_duration();
+    let take_len = (end - start).saturating_duration();
+    let head = (before_file + speech_start).saturating_sub(options.padding);
+    let tail = (before_file + speech_end + options.padding).min(take_len);
+
+    Ok(Some(TrimReport {
+        original_start: start,
+        original_end: end,
+        start: start + head,
+        end: start + tail,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(len: usize, amplitude: f32) -> Vec<f32> {
+        (0..len)
+            .map(|i| amplitude * (i as f32 * 0.1).sin())
+            .collect()
+    }
+
+    #[test]
+    fn finds_speech_between_silence() {
+        let mut samples = vec![0.0; 1000];
+        samples.extend(tone(2000, 0.5));
+        samples.extend(vec![0.0; 1000]);
+
+        let (start, end) = find_speech(&samples, 1000, -45.0).unwrap();
+
+        assert!(start <= Duration::from_secs(1), "start {:?}", start);
+        assert!(start >= Duration::from_millis(950), "start {:?}", start);
+        assert!(end >= Duration::from_secs(3), "end {:?}", end);
+        assert!(end <= Duration::from_millis(3100), "end {:?}", end);
+    }
+
+    #[test]
+    fn silence_has_no_speech() {
+        let samples = vec![0.0; 4000];
+
+        assert_eq!(find_speech(&samples, 1000, -45.0), None);
+    }
+
+    #[test]
+    fn quiet_noise_is_not_speech() {
+        let mut samples = tone(1000, 0.001);
+        samples.extend(tone(1000, 0.5));
+
+        let (start, _) = find_speech(&samples, 1000, -45.0).unwrap();
+
+        assert!(start >= Duration::from_millis(950), "start {:?}", start);
+    }
+
+    #[test]
+    fn trim_wav_take() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let path = dir.join("audio.wav");
+        let mut samples = vec![0i16; 2000];
+        samples.extend(tone(1000, 10000.0).iter().map(|s| *s as i16));
+        samples.extend(vec![0i16; 2000]);
+        crate::wav::tests::write_test_wav(&path, 1000, &samples);
+
+        let report = trim(
+            &path,
+            Timestamp::from_millis(1000),
+            Timestamp::from_millis(3500),
+            Timestamp::from_millis(500),
+            &TrimOptions {
+                threshold_db: -45.0,
+                padding: Duration::from_millis(100),
+            },
+        )
+        .unwrap()
+        .unwrap();
+
+        // Speech is at 2s..3s in the file, which is 1.5s..2.5s in the session.
+        assert_eq!(report.start, Timestamp::from_millis(1400));
+        assert!(report.end >= Timestamp::from_millis(2600));
+        assert!(report.end <= Timestamp::from_millis(2700));
+    }
+
+    #[test]
+    fn trim_take_starting_before_the_file() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let path = dir.join("audio.wav");
+        let mut samples = vec![0i16; 2000];
+        samples.extend(tone(1000, 10000.0).iter().map(|s| *s as i16));
+        samples.extend(vec![0i16; 2000]);
+        crate::wav::tests::write_test_wav(&path, 1000, &samples);
+
+        let report = trim(
+            &path,
+            Timestamp::from_millis(500),
+            Timestamp::from_millis(4500),
+            Timestamp::from_millis(-1000),
+            &TrimOptions {
+                threshold_db: -45.0,
+                padding: Duration::from_millis(100),
+            },
+        )
+        .unwrap()
+        .unwrap();
+
+        // Speech is at 2s..3s in the file, which is 3s..4s in the session.
+        assert_eq!(report.start, Timestamp::from_millis(2900));
+        assert!(report.end >= Timestamp::from_millis(4100));
+        assert!(report.end <= Timestamp::from_millis(4200));
+    }
+}
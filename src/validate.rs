@@ -0,0 +1,321 @@
+//! Check that takes actually lie inside the media they will be cut from.
+
+use std::{fmt, path::PathBuf, time::Duration};
+
+use log::*;
+
+use crate::{
+    data::{Session, Slicer, Take},
+    probe::MediaProbe,
+    timestamp::Timestamp,
+};
+
+/// What to do with takes that don't fit inside their media.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum RangePolicy {
+    /// Drop the take.
+    Skip,
+    /// Shorten the take to the part every track covers, and drop it if nothing is left.
+    #[default]
+    Clamp,
+    /// Stop before slicing anything.
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RangeProblem {
+    /// The take ends before, or when, it starts.
+    Empty,
+    /// None of the take is inside the media.
+    Outside { media_duration: Option<Duration> },
+    /// The take starts before the start of the media, or runs past its end.
+    PartiallyOutside { media_duration: Option<Duration> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeIssue {
+    pub session_id: String,
+    pub chunk_id: String,
+    pub take_index: usize,
+    /// The track the take doesn't fit in, if the problem is with a track.
+    pub track_file: Option<PathBuf>,
+    /// Position of the take in the media.
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub problem: RangeProblem,
+}
+
+impl fmt::Display for RangeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "session {} chunk {} take {} ({}..{})",
+            self.session_id, self.chunk_id, self.take_index, self.start, self.end
+        )?;
+        let (how, media_duration) = match &self.problem {
+            RangeProblem::Empty => return write!(f, " has zero or negative length"),
+            RangeProblem::Outside { media_duration } => ("outside", media_duration),
+            RangeProblem::PartiallyOutside { media_duration } => ("partly outside", media_duration),
+        };
+        write!(
+            f,
+            " is {} of {}",
+            how,
+            self.track_file.as_ref().unwrap().display()
+        )?;
+        if let Some(duration) = media_duration {
+            write!(f, " (00:00:00.000..{})", Timestamp::ZERO + *duration)?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the problems with a take, and the part of it that every track of its session covers.
+///
+/// Tracks that can't be probed, or whose duration is unknown, are only checked for takes that
+/// start before the media does.
+pub fn check_take(
+    take: &Take,
+    session: &Session,
+    probe: &MediaProbe,
+) -> (Vec<RangeIssue>, Option<(Timestamp, Timestamp)>) {
+    let issue = |track_file: Option<PathBuf>, start, end, problem| RangeIssue {
+        session_id: take.session_id.clone(),
+        chunk_id: take.chunk_id.clone(),
+        take_index: take.take_index,
+        track_file,
+        start,
+        end,
+        problem,
+    };
+    if take.end <= take.start {
+        return (
+            vec![issue(None, take.start, take.end, RangeProblem::Empty)],
+            None,
+        );
+    }
+
+    let mut issues = vec![];
+    let (mut start, mut end) = (take.start, take.end);
+    for track in &session.tracks {
+        let media_duration = match probe.probe(&track.file) {
+            Ok(info) => info.duration,
+            Err(e) => {
+                warn!("failed to probe {}: {}", track.file.display(), e);
+                None
+            }
+        };
+        let media_end = media_duration.and_then(|duration| Timestamp::try_from(duration).ok());
+        let start_in_media = take.start + track.sync_offset;
+        let end_in_media = take.end + track.sync_offset;
+        let problem = if end_in_media <= Timestamp::ZERO
+            || media_end.is_some_and(|media_end| start_in_media >= media_end)
+        {
+            RangeProblem::Outside { media_duration }
+        } else if start_in_media < Timestamp::ZERO
+            || media_end.is_some_and(|media_end| end_in_media > media_end)
+        {
+            RangeProblem::PartiallyOutside { media_duration }
+        } else {
+            continue;
+        };
+        issues.push(issue(
+            Some(track.file.clone()),
+            start_in_media,
+            end_in_media,
+            problem,
+        ));
+        // The part of the session this track covers.
+        start = start.max(-track.sync_offset);
+        if let Some(media_end) = media_end {
+            end = end.min(media_end - track.sync_offset);
+        }
+    }
+
+    let covered = (end > start).then_some((start, end));
+    (issues, covered)
+}
+
+/// Check every take of the slicer against the duration of its media, and apply `policy` to the
+/// ones that don't fit. Returns every problem found.
+pub fn validate_takes(slicer: &mut Slicer, policy: RangePolicy) -> anyhow::Result<Vec<RangeIssue>> {
+    let sessions = slicer.sessions.read().unwrap();
+    let mut all_issues = vec![];
+    let mut validated = std::collections::HashMap::new();
+    for (session_id, takes) in &slicer.takes {
+        let Some(session) = sessions.get(session_id) else {
+            validated.insert(session_id.clone(), takes.clone());
+            continue;
+        };
+
+        let mut kept = vec![];
+        for take in takes {
+            let (issues, covered) = check_take(take, session, &slicer.probe);
+            if issues.is_empty() {
+                kept.push(take.clone());
+                continue;
+            }
+            for issue in &issues {
+                warn!("{}", issue);
+            }
+            all_issues.extend(issues);
+            match (policy, covered) {
+                (RangePolicy::Clamp, Some((start, end))) => {
+                    info!(
+                        "clamping chunk {} take {} to {}..{}",
+                        take.chunk_id, take.take_index, start, end
+                    );
+                    kept.push(Take {
+                        start,
+                        end,
+                        ..take.clone()
+                    });
+                }
+                _ => info!("skipping chunk {} take {}", take.chunk_id, take.take_index),
+            }
+        }
+        validated.insert(session_id.clone(), kept);
+    }
+    drop(sessions);
+
+    if policy == RangePolicy::Fail && !all_issues.is_empty() {
+        anyhow::bail!(
+            "{} takes don't fit inside their media:\n{}",
+            all_issues.len(),
+            all_issues
+                .iter()
+                .map(|issue| issue.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+    slicer.takes = validated;
+    Ok(all_issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{data::Track, export::tests::take, wav::tests::write_test_wav};
+
+    /// A slicer with one session, with a 2 second track and a 3 second track starting 1.5
+    /// seconds late, both written to `dir`.
+    fn slicer(dir: &std::path::Path, takes: Vec<Take>) -> Slicer {
+        let short = dir.join("short.wav");
+        let long = dir.join("long.wav");
+        write_test_wav(&short, 1000, &[0; 2000]);
+        write_test_wav(&long, 1000, &[0; 3000]);
+
+        let slicer = Slicer::new();
+        slicer.sessions.write().unwrap().insert(
+            "session".to_owned(),
+            Session {
+                session_id: "session".to_owned(),
+                tracks: vec![
+                    Track {
+                        file: short,
+                        sync_offset: Timestamp::ZERO,
+                    },
+                    Track {
+                        file: long,
+                        sync_offset: Timestamp::from_millis(1500),
+                    },
+                ],
+            },
+        );
+        Slicer {
+            takes: [("session".to_owned(), takes)].into(),
+            ..slicer
+        }
+    }
+
+    #[test]
+    fn problems_are_reported() {
+        let tmp = crate::tests::temp_dir();
+        let slicer = slicer(tmp.path(), vec![]);
+        let sessions = slicer.sessions.read().unwrap();
+        let session = &sessions["session"];
+
+        let (issues, covered) = check_take(&take("1", 0, 0, 1000), session, &slicer.probe);
+        assert!(issues.is_empty());
+        assert_eq!(covered, Some((Timestamp::ZERO, Timestamp::from_secs(1))));
+
+        let (issues, covered) = check_take(&take("2", 0, 1000, 1000), session, &slicer.probe);
+        assert_eq!(issues[0].problem, RangeProblem::Empty);
+        assert_eq!(covered, None);
+
+        let (issues, covered) = check_take(&take("3", 0, 1000, 1800), session, &slicer.probe);
+        assert_eq!(
+            issues[0].problem,
+            RangeProblem::PartiallyOutside {
+                media_duration: Some(Duration::from_secs(3))
+            }
+        );
+        assert_eq!(issues[0].start, Timestamp::from_millis(2500));
+        assert_eq!(
+            covered,
+            Some((Timestamp::from_secs(1), Timestamp::from_millis(1500)))
+        );
+
+        let (issues, covered) = check_take(&take("4", 0, 2500, 3000), session, &slicer.probe);
+        assert_eq!(issues.len(), 2);
+        assert!(matches!(issues[0].problem, RangeProblem::Outside { .. }));
+        assert_eq!(covered, None);
+    }
+
+    #[test]
+    fn negative_sync_offset() {
+        let tmp = crate::tests::temp_dir();
+        let slicer = slicer(tmp.path(), vec![]);
+        let mut sessions = slicer.sessions.write().unwrap();
+        let session = sessions.get_mut("session").unwrap();
+        // The second track started recording 1.5 seconds after the session.
+        session.tracks[1].sync_offset = -Timestamp::from_millis(1500);
+
+        let (issues, covered) = check_take(&take("1", 0, 1000, 2000), session, &slicer.probe);
+
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].start, -Timestamp::from_millis(500));
+        assert!(matches!(
+            issues[0].problem,
+            RangeProblem::PartiallyOutside { .. }
+        ));
+        assert_eq!(
+            covered,
+            Some((Timestamp::from_millis(1500), Timestamp::from_secs(2)))
+        );
+    }
+
+    #[test]
+    fn policies() {
+        let takes = vec![
+            take("1", 0, 0, 1000),
+            take("2", 0, 1000, 1800),
+            take("3", 0, 2500, 3000),
+        ];
+        let tmp = crate::tests::temp_dir();
+        let kept = |policy| {
+            let mut slicer = slicer(tmp.path(), takes.clone());
+            validate_takes(&mut slicer, policy).map(|_| {
+                slicer.takes["session"]
+                    .iter()
+                    .map(|t| (t.chunk_id.clone(), t.end.saturating_duration()))
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            kept(RangePolicy::Skip).unwrap(),
+            [("1".to_owned(), Duration::from_secs(1))]
+        );
+        assert_eq!(
+            kept(RangePolicy::Clamp).unwrap(),
+            [
+                ("1".to_owned(), Duration::from_secs(1)),
+                ("2".to_owned(), Duration::from_millis(1500))
+            ]
+        );
+        assert!(kept(RangePolicy::Fail).is_err());
+    }
+}
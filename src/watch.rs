@@ -0,0 +1,543 @@
+//! Slice sessions as they come in during a shoot, once their files have stopped changing.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{slice_file_prefix, SlicePlan, Slicer, Take, Track},
+    import::ImporterRegistry,
+    synchronizer::{SyncerCache, TimeReferenceSyncer, TrackSync},
+    timestamp::Timestamp,
+    validate::{self, RangePolicy},
+};
+
+pub const STATE_JSON: &str = "watch_state.json";
+
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    pub sessions_dir: PathBuf,
+    pub video_dir: PathBuf,
+    pub output_dir: PathBuf,
+    /// Only read the sessions directory with this importer.
+    pub session_format: Option<String>,
+    /// How often to look for changes.
+    pub interval: Duration,
+    /// How long a file's size and modification time have to stay the same before it counts as
+    /// finished.
+    pub settle: Duration,
+    /// Don't slice a session until its video shows up.
+    pub wait_for_video: bool,
+    pub sync_offsets: Vec<(String, Timestamp)>,
+    pub out_of_range: RangePolicy,
+}
+
+/// Which takes have been sliced, by session id. Saved in the output directory after every
+/// session, so a restarted watch picks up where it left off.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WatchState {
+    sliced: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl WatchState {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    fn key(take: &Take) -> String {
+        format!("{}#{}", take.chunk_id, take.take_index)
+    }
+
+    pub fn is_sliced(&self, take: &Take) -> bool {
+        self.sliced
+            .get(&take.session_id)
+            .is_some_and(|keys| keys.contains(&Self::key(take)))
+    }
+
+    pub fn mark_sliced(&mut self, take: &Take) {
+        self.sliced
+            .entry(take.session_id.clone())
+            .or_default()
+            .insert(Self::key(take));
+    }
+}
+
+/// Tells when files have stopped changing, by comparing their size and modification time
+/// between polls.
+#[derive(Debug, Default)]
+pub struct StableFiles {
+    seen: HashMap<PathBuf, (u64, Option<SystemTime>, Instant)>,
+}
+
+impl StableFiles {
+    /// Whether `path` exists and hasn't changed for at least `settle`, as of `now`. A file has to
+    /// be seen unchanged by two calls to count as stable.
+    pub fn is_stable(&mut self, path: &Path, settle: Duration, now: Instant) -> bool {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            self.seen.remove(path);
+            return false;
+        };
+        let stamp = (metadata.len(), metadata.modified().ok());
+        match self.seen.get(path) {
+            Some(&(len, modified, since)) if (len, modified) == stamp => {
+                now.duration_since(since) >= settle
+            }
+            _ => {
+                self.seen.insert(path.to_owned(), (stamp.0, stamp.1, now));
+                false
+            }
+        }
+    }
+}
+
+pub struct Watcher {
+    pub config: WatchConfig,
+    pub state: WatchState,
+    stable: StableFiles,
+    /// Entries that failed to import, so the failure is only reported once.
+    failed: HashSet<PathBuf>,
+    /// Sessions that are only waiting for their video, so that is only reported once.
+    waiting: HashSet<String>,
+    /// Entries that failed to slice, so the failure is only reported once.
+    failed_slicing: HashSet<PathBuf>,
+    /// Sessions without a sync offset for their video, so that is only reported once.
+    unsynced: HashSet<String>,
+}
+
+impl Watcher {
+    /// A watcher that continues from the state saved in the output directory, if there is one.
+    pub fn new(config: WatchConfig) -> Self {
+        let state_path = config.output_dir.join(STATE_JSON);
+        let state = match WatchState::load(&state_path) {
+            Ok(state) => state,
+            Err(e) => {
+                if state_path.exists() {
+                    warn!("failed to load {}: {}", state_path.display(), e);
+                }
+                WatchState::default()
+            }
+        };
+        Self {
+            config,
+            state,
+            stable: StableFiles::default(),
+            failed: HashSet::new(),
+            waiting: HashSet::new(),
+            failed_slicing: HashSet::new(),
+            unsynced: HashSet::new(),
+        }
+    }
+
+    /// Poll until the process is stopped.
+    pub fn run(&mut self, slicer: &mut Slicer, importers: &ImporterRegistry) -> anyhow::Result<()> {
+        info!(
+            "watching {} and {}",
+            self.config.sessions_dir.display(),
+            self.config.video_dir.display()
+        );
+        loop {
+            let sliced = self.poll(slicer, importers, Instant::now())?;
+            if sliced > 0 {
+                info!("sliced {} new takes, waiting for more", sliced);
+            }
+            std::thread::sleep(self.config.interval);
+        }
+    }
+
+    /// Slice the new takes of every session whose files have settled. Returns the number of takes
+    /// sliced.
+    pub fn poll(
+        &mut self,
+        slicer: &mut Slicer,
+        importers: &ImporterRegistry,
+        now: Instant,
+    ) -> anyhow::Result<usize> {
+        let mut entries = std::fs::read_dir(&self.config.sessions_dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+
+        let mut sliced = 0;
+        for entry in entries {
+            let hidden = entry
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden || !self.is_settled(&entry, now) {
+                continue;
+            }
+            // One session failing mustn't stop the others from being sliced.
+            match self.ingest(&entry, slicer, importers, now) {
+                Ok(count) => {
+                    self.failed_slicing.remove(&entry);
+                    sliced += count;
+                }
+                Err(e) => {
+                    if self.failed_slicing.insert(entry.clone()) {
+                        error!("failed to slice {}: {:#}", entry.display(), e);
+                    }
+                }
+            }
+        }
+        Ok(sliced)
+    }
+
+    /// Whether a sessions directory entry, and everything directly in it, has stopped changing.
+    fn is_settled(&mut self, entry: &Path, now: Instant) -> bool {
+        let files = match std::fs::read_dir(entry) {
+            Ok(dir) => dir
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|path| path.is_file())
+                .collect(),
+            Err(_) => vec![entry.to_owned()],
+        };
+        // Check every file, so they all start settling at the same time.
+        files
+            .iter()
+            .map(|file| self.stable.is_stable(file, self.config.settle, now))
+            .fold(!files.is_empty(), |all, stable| all && stable)
+    }
+
+    fn ingest(
+        &mut self,
+        entry: &Path,
+        slicer: &mut Slicer,
+        importers: &ImporterRegistry,
+        now: Instant,
+    ) -> anyhow::Result<usize> {
+        let mut session = match importers.import(entry, self.config.session_format.as_deref()) {
+            Ok(session) => {
+                self.failed.remove(entry);
+                session
+            }
+            Err(e) => {
+                if self.failed.insert(entry.to_owned()) {
+                    warn!("skipping {}: {:#}", entry.display(), e);
+                }
+                return Ok(0);
+            }
+        };
+        let session_id = session.session.session_id.clone();
+        session.takes.retain(|take| !self.state.is_sliced(take));
+        if session.takes.is_empty() {
+            return Ok(0);
+        }
+
+        let video = self
+            .config
+            .video_dir
+            .join(format!("video-session-{}.mp4", session_id));
+        if video.exists() {
+            if !self.stable.is_stable(&video, self.config.settle, now) {
+                debug!("waiting for {} to finish copying", video.display());
+                return Ok(0);
+            }
+            let Some(sync_offset) = self.sync_offset(&session.session, &video) else {
+                return Ok(0);
+            };
+            session.session.tracks.push(Track {
+                file: video,
+                sync_offset,
+            });
+        } else if self.config.wait_for_video {
+            if self.waiting.insert(session_id.clone()) {
+                info!("session {} is waiting for its video", session_id);
+            }
+            return Ok(0);
+        }
+        self.waiting.remove(&session_id);
+
+        info!(
+            "slicing {} new takes of session {}",
+            session.takes.len(),
+            session_id
+        );
+        let takes = session.takes.clone();
+        slicer.sessions.write().unwrap().clear();
+        slicer.takes.clear();
+        slicer.register_session(session);
+        validate::validate_takes(slicer, self.config.out_of_range)?;
+        let plan = slicer.plan_in(&self.config.output_dir)?;
+        let outputs = slicer.slice(&plan)?;
+        debug!("wrote {} slices", outputs.len());
+
+        // Takes whose slices failed have been reported while slicing, and are tried again on the
+        // next poll.
+        let mut sliced = 0;
+        for take in &takes {
+            if self.is_take_done(take, &plan) {
+                self.state.mark_sliced(take);
+                sliced += 1;
+            }
+        }
+        self.state.save(&self.config.output_dir.join(STATE_JSON))?;
+        Ok(sliced)
+    }
+
+    /// Whether every file planned for `take` is there. Takes with nothing planned either had
+    /// all their slices already or failed to plan, so their slices are looked for by name.
+    fn is_take_done(&self, take: &Take, plan: &SlicePlan) -> bool {
+        let key = WatchState::key(take);
+        let mut planned = plan
+            .slices
+            .iter()
+            .filter(|slice| WatchState::key(&slice.take) == key)
+            .peekable();
+        if planned.peek().is_some() {
+            return planned.all(|slice| {
+                slice.file.exists() && slice.proxy.as_ref().is_none_or(|proxy| proxy.exists())
+            });
+        }
+
+        let session_dir = self.config.output_dir.join(&take.session_id);
+        let prefix = slice_file_prefix(&take.chunk_id, take.take_index);
+        [session_dir.join("full"), session_dir]
+            .iter()
+            .filter_map(|dir| std::fs::read_dir(dir).ok())
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+    }
+
+    /// The sync offset of a session's video from `--sync-offset`, the syncer cache, or BWF time
+    /// references. Watching is unattended, so nobody is asked.
+    fn sync_offset(&mut self, session: &crate::data::Session, video: &Path) -> Option<Timestamp> {
+        let given = self
+            .config
+            .sync_offsets
+            .iter()
+            .find(|(id, _)| *id == session.session_id)
+            .map(|(_, offset)| *offset);
+        if given.is_some() {
+            self.unsynced.remove(&session.session_id);
+            return given;
+        }
+
+        let cache_path = self.config.video_dir.join("syncer_cache.json");
+        let file_name = video.file_name().unwrap().to_string_lossy();
+        let mut cache = SyncerCache::load(&cache_path).unwrap_or_default();
+        if let Some(offset) = cache.get(&file_name) {
+            self.unsynced.remove(&session.session_id);
+            return Some(offset);
+        }
+        match TimeReferenceSyncer::for_session(session).map(|syncer| syncer.find_sync_offset(video))
+        {
+            Some(Ok(offset)) => {
+                info!("synced {} by its BWF time reference", file_name);
+                cache.set(&file_name, offset);
+                if let Err(e) = cache.save(&cache_path) {
+                    warn!("failed to save syncer cache: {}", e);
+                }
+                self.unsynced.remove(&session.session_id);
+                Some(offset)
+            }
+            _ => {
+                if self.unsynced.insert(session.session_id.clone()) {
+                    warn!(
+                        "no sync offset for {}, add it to {} or pass --sync-offset",
+                        file_name,
+                        cache_path.display()
+                    );
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::tests::write_test_wav;
+
+    #[test]
+    fn files_settle_once_unchanged() {
+        let tmp = crate::tests::temp_dir();
+        let path = tmp.path().join("file.txt");
+        std::fs::write(&path, "a").unwrap();
+        let mut stable = StableFiles::default();
+        let start = Instant::now();
+        let settle = Duration::from_secs(10);
+
+        assert!(!stable.is_stable(&path, settle, start));
+        assert!(!stable.is_stable(&path, settle, start + Duration::from_secs(5)));
+        assert!(stable.is_stable(&path, settle, start + Duration::from_secs(10)));
+
+        std::fs::write(&path, "ab").unwrap();
+        assert!(!stable.is_stable(&path, settle, start + Duration::from_secs(11)));
+        assert!(!stable.is_stable(&path, settle, start + Duration::from_secs(20)));
+        assert!(stable.is_stable(&path, settle, start + Duration::from_secs(21)));
+    }
+
+    const HEADER: &str = "header,chunk_index,chunk_text,take_index,take_mark,take_start,take_end\n";
+
+    /// Writes session `id` to `dir/sessions`, with five seconds of audio and the given takes.
+    fn write_session(dir: &Path, id: &str, sync_offset: &str, takes: &str) {
+        let session = dir.join("sessions").join(id);
+        std::fs::create_dir_all(&session).unwrap();
+        write_test_wav(&session.join("audio.wav"), 1000, &[0; 5000]);
+        std::fs::write(
+            session.join("metadata.json"),
+            format!(r#"{{"SyncOffset": "{sync_offset}"}}"#),
+        )
+        .unwrap();
+        std::fs::write(session.join("takes.csv"), format!("{HEADER}{takes}")).unwrap();
+    }
+
+    /// Watches `dir/sessions` and `dir/video`, slicing into `dir/out` without settling.
+    fn config(dir: &Path, out_of_range: RangePolicy) -> WatchConfig {
+        let config = WatchConfig {
+            sessions_dir: dir.join("sessions"),
+            video_dir: dir.join("video"),
+            output_dir: dir.join("out"),
+            session_format: None,
+            interval: Duration::ZERO,
+            settle: Duration::ZERO,
+            wait_for_video: false,
+            sync_offsets: vec![],
+            out_of_range,
+        };
+        std::fs::create_dir_all(&config.video_dir).unwrap();
+        std::fs::create_dir_all(&config.output_dir).unwrap();
+        config
+    }
+
+    fn slicer() -> Slicer {
+        let mut slicer = Slicer::new();
+        slicer.options.per_session_dirs = true;
+        slicer
+    }
+
+    /// The first take of a session, as far as [`WatchState`] is concerned.
+    fn take(session_id: &str) -> Take {
+        Take {
+            session_id: session_id.to_owned(),
+            chunk_id: "0".to_owned(),
+            chunk_text: None,
+            header: None,
+            take_index: 0,
+            start: Timestamp::ZERO,
+            end: Timestamp::ZERO,
+            mark: "good".to_owned(),
+        }
+    }
+
+    #[test]
+    fn only_new_takes_are_sliced() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let first = "Intro,0,Hi,0,good,00:00:00.500,00:00:01.500\n";
+        write_session(dir, "1", "00:00:00.000", first);
+        let config = config(dir, RangePolicy::Clamp);
+        let importers = ImporterRegistry::default();
+        let mut slicer = slicer();
+        let mut watcher = Watcher::new(config.clone());
+        let now = Instant::now();
+
+        // Files have to be seen twice before they count as finished.
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 0);
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 1);
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 0);
+
+        let second = "Intro,0,Hi,1,good,00:00:02.000,00:00:03.000\n";
+        write_session(dir, "1", "00:00:00.000", &format!("{first}{second}"));
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 0);
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 1);
+
+        // A restart doesn't redo anything.
+        let mut watcher = Watcher::new(config);
+        watcher.poll(&mut slicer, &importers, now).unwrap();
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 0);
+        assert!(dir.join("out/1/chunk-0-take-0-track-0-good.wav").exists());
+        assert!(dir.join("out/1/chunk-0-take-1-track-0-good.wav").exists());
+    }
+
+    #[test]
+    fn failed_sessions_and_takes_are_retried() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        // Ends after the audio, which fails validation.
+        write_session(
+            dir,
+            "1",
+            "00:00:00.000",
+            "Intro,0,Hi,0,good,00:00:04.000,00:00:06.000\n",
+        );
+        // Starts before the audio, so nothing is written.
+        write_session(
+            dir,
+            "2",
+            "-00:00:01.000",
+            "Intro,0,Hi,0,good,00:00:00.500,00:00:01.500\n",
+        );
+        write_session(
+            dir,
+            "3",
+            "00:00:00.000",
+            "Intro,0,Hi,0,good,00:00:00.500,00:00:01.500\n",
+        );
+        let importers = ImporterRegistry::default();
+        let mut slicer = slicer();
+        let mut watcher = Watcher::new(config(dir, RangePolicy::Fail));
+        let now = Instant::now();
+
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 0);
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 1);
+        assert!(!watcher.state.is_sliced(&take("1")));
+        assert!(!watcher.state.is_sliced(&take("2")));
+        assert!(watcher.state.is_sliced(&take("3")));
+
+        write_session(
+            dir,
+            "1",
+            "00:00:00.000",
+            "Intro,0,Hi,0,good,00:00:03.000,00:00:04.000\n",
+        );
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 0);
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 1);
+        assert!(watcher.state.is_sliced(&take("1")));
+    }
+
+    #[test]
+    fn sessions_without_a_sync_offset_are_reported_after_waiting() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        write_session(
+            dir,
+            "1",
+            "00:00:00.000",
+            "Intro,0,Hi,0,good,00:00:00.500,00:00:01.500\n",
+        );
+        let mut config = config(dir, RangePolicy::Clamp);
+        config.wait_for_video = true;
+        let importers = ImporterRegistry::default();
+        let mut slicer = slicer();
+        let mut watcher = Watcher::new(config);
+        let now = Instant::now();
+
+        watcher.poll(&mut slicer, &importers, now).unwrap();
+        watcher.poll(&mut slicer, &importers, now).unwrap();
+        assert!(watcher.waiting.contains("1"));
+        assert!(watcher.unsynced.is_empty());
+
+        std::fs::write(dir.join("video/video-session-1.mp4"), "video").unwrap();
+        watcher.poll(&mut slicer, &importers, now).unwrap();
+        assert_eq!(watcher.poll(&mut slicer, &importers, now).unwrap(), 0);
+        assert!(watcher.unsynced.contains("1"));
+
+        watcher.config.sync_offsets = vec![("1".to_owned(), Timestamp::ZERO)];
+        watcher.poll(&mut slicer, &importers, now).unwrap();
+        assert!(watcher.unsynced.is_empty());
+    }
+}
@@ -0,0 +1,762 @@
+//! Minimal RIFF/WAVE reader and writer, used to cut WAV tracks natively without re-encoding.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::audio::SampleRange;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// The contents of a `fmt ` chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WavFormat {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    /// For `WAVE_FORMAT_EXTENSIBLE`, the format tag embedded in the sub format GUID.
+    pub sub_format_tag: Option<u16>,
+}
+
+impl WavFormat {
+    fn parse(body: &[u8]) -> anyhow::Result<Self> {
+        if body.len() < 16 {
+            anyhow::bail!("fmt chunk too short");
+        }
+        let format_tag = u16::from_le_bytes([body[0], body[1]]);
+        let sub_format_tag = if format_tag == WAVE_FORMAT_EXTENSIBLE && body.len() >= 26 {
+            Some(u16::from_le_bytes([body[24], body[25]]))
+        } else {
+            None
+        };
+        Ok(Self {
+            format_tag,
+            channels: u16::from_le_bytes([body[2], body[3]]),
+            sample_rate: u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+            block_align: u16::from_le_bytes([body[12], body[13]]),
+            bits_per_sample: u16::from_le_bytes([body[14], body[15]]),
+            sub_format_tag,
+        })
+    }
+
+    /// The ffmpeg encoder that writes samples in this format.
+    pub fn ffmpeg_codec(&self) -> Option<&'static str> {
+        Some(match self.sample_kind()? {
+            SampleKind::U8 => "pcm_u8",
+            SampleKind::I16 => "pcm_s16le",
+            SampleKind::I24 => "pcm_s24le",
+            SampleKind::I32 => "pcm_s32le",
+            SampleKind::F32 => "pcm_f32le",
+            SampleKind::F64 => "pcm_f64le",
+        })
+    }
+
+    fn sample_kind(&self) -> Option<SampleKind> {
+        let tag = self.sub_format_tag.unwrap_or(self.format_tag);
+        match (tag, self.bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => Some(SampleKind::U8),
+            (WAVE_FORMAT_PCM, 16) => Some(SampleKind::I16),
+            (WAVE_FORMAT_PCM, 24) => Some(SampleKind::I24),
+            (WAVE_FORMAT_PCM, 32) => Some(SampleKind::I32),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Some(SampleKind::F32),
+            (WAVE_FORMAT_IEEE_FLOAT, 64) => Some(SampleKind::F64),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleKind {
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+    F64,
+}
+
+impl SampleKind {
+    fn scale(self, sample: &mut [u8], gain: f64) {
+        match self {
+            SampleKind::U8 => {
+                let value = (sample[0] as f64 - 128.0) * gain + 128.0;
+                sample[0] = value.round() as u8;
+            }
+            SampleKind::I16 => {
+                let value = i16::from_le_bytes([sample[0], sample[1]]) as f64 * gain;
+                sample.copy_from_slice(&(value.round() as i16).to_le_bytes());
+            }
+            SampleKind::I24 => {
+                let value = i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8;
+                let value = ((value as f64 * gain).round() as i32).to_le_bytes();
+                sample.copy_from_slice(&value[..3]);
+            }
+            SampleKind::I32 => {
+                let value = i32::from_le_bytes(sample.try_into().unwrap()) as f64 * gain;
+                sample.copy_from_slice(&(value.round() as i32).to_le_bytes());
+            }
+            SampleKind::F32 => {
+                let value = f32::from_le_bytes(sample.try_into().unwrap()) as f64 * gain;
+                sample.copy_from_slice(&(value as f32).to_le_bytes());
+            }
+            SampleKind::F64 => {
+                let value = f64::from_le_bytes(sample.try_into().unwrap()) * gain;
+                sample.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Chunk {
+    id: [u8; 4],
+    /// The body of the chunk. Empty for the `data` chunk, which is never loaded into memory.
+    body: Vec<u8>,
+}
+
+/// A parsed WAV file. Only the chunk layout and the small chunks are kept in memory.
+#[derive(Debug, Clone)]
+pub struct WavFile {
+    path: PathBuf,
+    pub format: WavFormat,
+    chunks: Vec<Chunk>,
+    data_offset: u64,
+    data_len: u64,
+}
+
+impl WavFile {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+            anyhow::bail!("{} is not a RIFF/WAVE file", path.display());
+        }
+
+        let mut chunks = vec![];
+        let mut format = None;
+        let mut data = None;
+        let mut offset = 12u64;
+        while offset + 8 <= file_len {
+            let mut chunk_header = [0u8; 8];
+            reader.read_exact(&mut chunk_header)?;
+            let id: [u8; 4] = chunk_header[0..4].try_into().unwrap();
+            let len = u32::from_le_bytes(chunk_header[4..8].try_into().unwrap()) as u64;
+            offset += 8;
+
+            if &id == b"data" {
+                // Recorders that were interrupted can leave a bogus length behind.
+                let len = len.min(file_len - offset);
+                data = Some((offset, len));
+                chunks.push(Chunk { id, body: vec![] });
+                offset += len + (len & 1);
+                reader.seek(SeekFrom::Start(offset))?;
+                continue;
+            }
+
+            let mut body = vec![0u8; len.min(file_len - offset) as usize];
+            reader.read_exact(&mut body)?;
+            if &id == b"fmt " {
+                format = Some(WavFormat::parse(&body)?);
+            }
+            chunks.push(Chunk { id, body });
+            offset += len + (len & 1);
+            reader.seek(SeekFrom::Start(offset))?;
+        }
+
+        let format = format.ok_or(anyhow::anyhow!("{}: missing fmt chunk", path.display()))?;
+        let (data_offset, data_len) =
+            data.ok_or(anyhow::anyhow!("{}: missing data chunk", path.display()))?;
+        if format.channels == 0 {
+            anyhow::bail!("{}: no channels", path.display());
+        }
+        // Frames are cut into one sample per channel.
+        let expected_align = format
+            .sample_kind()
+            .map(|_| format.channels * format.bits_per_sample.div_ceil(8));
+        if format.block_align == 0
+            || format.block_align % format.channels != 0
+            || expected_align.is_some_and(|align| align != format.block_align)
+        {
+            anyhow::bail!(
+                "{}: invalid block align {} for {} channels of {} bits",
+                path.display(),
+                format.block_align,
+                format.channels,
+                format.bits_per_sample
+            );
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            format,
+            chunks,
+            data_offset,
+            data_len,
+        })
+    }
+
+    /// Replace the RIFF `LIST`/`INFO` chunk with the given tags. It is only written out by
+    /// [`WavFile::write_slice`], the source file is never modified.
+    pub fn set_info(&mut self, tags: &[([u8; 4], String)]) {
+        let mut body = b"INFO".to_vec();
+        for (id, value) in tags {
+            let len = value.len() + 1;
+            body.extend_from_slice(id);
+            body.extend_from_slice(&(len as u32).to_le_bytes());
+            body.extend_from_slice(value.as_bytes());
+            body.push(0);
+            if len & 1 == 1 {
+                body.push(0);
+            }
+        }
+        self.set_info_chunk(Chunk { id: *b"LIST", body });
+    }
+
+    fn info_chunk(&self) -> Option<&Chunk> {
+        self.chunks
+            .iter()
+            .find(|c| &c.id == b"LIST" && c.body.starts_with(b"INFO"))
+    }
+
+    fn set_info_chunk(&mut self, chunk: Chunk) {
+        let existing = self
+            .chunks
+            .iter()
+            .position(|c| &c.id == b"LIST" && c.body.starts_with(b"INFO"));
+        match existing {
+            Some(idx) => self.chunks[idx] = chunk,
+            None => {
+                let data = self.chunks.iter().position(|c| &c.id == b"data").unwrap();
+                self.chunks.insert(data, chunk);
+            }
+        }
+    }
+
+    /// Take over the BWF chunks and RIFF `INFO` tags of `source`, for a slice of it starting
+    /// `frames` in. For files written by tools that drop them, like ffmpeg. Like
+    /// [`WavFile::set_info`], this only affects slices.
+    pub fn copy_metadata(&mut self, source: &WavFile, frames: u64) {
+        for id in [*b"bext", *b"iXML"] {
+            if let Some(body) = source.chunk(&id) {
+                let body = crate::bwf::shift_time_reference(&id, body, frames).into_owned();
+                self.set_chunk(id, body);
+            }
+        }
+        if let Some(info) = source.info_chunk() {
+            self.set_info_chunk(info.clone());
+        }
+    }
+
+    /// The body of the first chunk with the given id. Always empty for `data`.
+    pub fn chunk(&self, id: &[u8; 4]) -> Option<&[u8]> {
+        self.chunks
+            .iter()
+            .find(|c| &c.id == id)
+            .map(|c| c.body.as_slice())
+    }
+
+    /// Replace the first chunk with the given id, or add one before `data`. Like
+    /// [`WavFile::set_info`], this only affects slices.
+    pub fn set_chunk(&mut self, id: [u8; 4], body: Vec<u8>) {
+        let chunk = Chunk { id, body };
+        match self.chunks.iter().position(|c| c.id == id) {
+            Some(idx) => self.chunks[idx] = chunk,
+            None => {
+                let data = self.chunks.iter().position(|c| &c.id == b"data").unwrap();
+                self.chunks.insert(data, chunk);
+            }
+        }
+    }
+
+    /// The number of sample frames in the `data` chunk.
+    pub fn frames(&self) -> u64 {
+        self.data_len / self.format.block_align as u64
+    }
+
+    pub fn duration(&self) -> Duration {
+        crate::audio::samples_to_duration(self.frames(), self.format.sample_rate)
+    }
+
+    /// Copy the frames in `range` to a new WAV file, applying a linear fade in and out of `fade`.
+    ///
+    /// All chunks other than `data` are copied, with the BWF time reference moved to the start of
+    /// the slice. The range is clamped to the length of the file. Returns the number of frames
+    /// written.
+    pub fn write_slice(
+        &self,
+        out: impl AsRef<Path>,
+        range: SampleRange,
+        fade: Duration,
+    ) -> anyhow::Result<u64> {
+        self.write(out.as_ref(), range, fade, None)
+    }
+
+    /// Like [`WavFile::write_slice`], but only keeping `channels`, in that order. Channels can be
+    /// repeated.
+    pub fn write_channels(
+        &self,
+        out: impl AsRef<Path>,
+        range: SampleRange,
+        fade: Duration,
+        channels: &[usize],
+    ) -> anyhow::Result<u64> {
+        if channels.is_empty() {
+            anyhow::bail!("no channels to write");
+        }
+        if let Some(channel) = channels
+            .iter()
+            .find(|&&c| c >= self.format.channels as usize)
+        {
+            anyhow::bail!(
+                "{} has no channel {}, only {}",
+                self.path.display(),
+                channel,
+                self.format.channels
+            );
+        }
+        self.write(out.as_ref(), range, fade, Some(channels))
+    }
+
+    fn write(
+        &self,
+        out: &Path,
+        range: SampleRange,
+        fade: Duration,
+        channels: Option<&[usize]>,
+    ) -> anyhow::Result<u64> {
+        if range.sample_rate != self.format.sample_rate {
+            anyhow::bail!(
+                "sample rate mismatch: range is {} Hz, file is {} Hz",
+                range.sample_rate,
+                self.format.sample_rate
+            );
+        }
+        let range = SampleRange {
+            start: range.start.min(self.frames()),
+            end: range.end.min(self.frames()),
+            ..range
+        };
+        let fade_frames = range.fade_samples(fade);
+        let kind = self.format.sample_kind();
+        if fade_frames > 0 && kind.is_none() {
+            anyhow::bail!(
+                "can't fade format tag {:#06x} with {} bits per sample",
+                self.format.format_tag,
+                self.format.bits_per_sample
+            );
+        }
+
+        let block_align = self.format.block_align as u64;
+        let bytes_per_sample = self.format.block_align as usize / self.format.channels as usize;
+        let out_block_align = match channels {
+            Some(channels) => (channels.len() * bytes_per_sample) as u64,
+            None => block_align,
+        };
+        let data_len = range.len() * out_block_align;
+
+        let bodies: Vec<_> = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let body = crate::bwf::shift_time_reference(&chunk.id, &chunk.body, range.start);
+                match channels {
+                    Some(channels) if &chunk.id == b"fmt " => {
+                        fmt_with_channels(&body, channels.len() as u16, out_block_align as u16)
+                            .into()
+                    }
+                    _ => body,
+                }
+            })
+            .collect();
+        let riff_len: u64 = 4 + self
+            .chunks
+            .iter()
+            .zip(&bodies)
+            .map(|(chunk, body)| {
+                let len = if &chunk.id == b"data" {
+                    data_len
+                } else {
+                    body.len() as u64
+                };
+                8 + len + (len & 1)
+            })
+            .sum::<u64>();
+        let riff_len = u32::try_from(riff_len).context("sliced WAV is too large")?;
+
+        let mut src = BufReader::new(File::open(&self.path)?);
+        let mut dst = BufWriter::new(File::create(out)?);
+
+        dst.write_all(b"RIFF")?;
+        dst.write_all(&riff_len.to_le_bytes())?;
+        dst.write_all(b"WAVE")?;
+        for (chunk, body) in self.chunks.iter().zip(&bodies) {
+            dst.write_all(&chunk.id)?;
+            if &chunk.id != b"data" {
+                dst.write_all(&(body.len() as u32).to_le_bytes())?;
+                dst.write_all(body)?;
+                if body.len() & 1 == 1 {
+                    dst.write_all(&[0])?;
+                }
+                continue;
+            }
+
+            dst.write_all(&(data_len as u32).to_le_bytes())?;
+            src.seek(SeekFrom::Start(
+                self.data_offset + range.start * block_align,
+            ))?;
+
+            if let Some(channels) = channels {
+                self.copy_channels(&mut src, &mut dst, range.len(), fade_frames, kind, channels)?;
+                if data_len & 1 == 1 {
+                    dst.write_all(&[0])?;
+                }
+                continue;
+            }
+
+            let body_frames = range.len() - fade_frames * 2;
+            let mut buf = vec![0u8; (fade_frames * block_align) as usize];
+
+            src.read_exact(&mut buf)?;
+            self.apply_gain(&mut buf, kind, |i| i as f64 / fade_frames as f64);
+            dst.write_all(&buf)?;
+
+            let copied = std::io::copy(&mut (&mut src).take(body_frames * block_align), &mut dst)?;
+            if copied != body_frames * block_align {
+                anyhow::bail!("unexpected end of data in {}", self.path.display());
+            }
+
+            src.read_exact(&mut buf)?;
+            self.apply_gain(&mut buf, kind, |i| {
+                (fade_frames - 1 - i) as f64 / fade_frames as f64
+            });
+            dst.write_all(&buf)?;
+
+            if data_len & 1 == 1 {
+                dst.write_all(&[0])?;
+            }
+        }
+        dst.flush()?;
+
+        Ok(range.len())
+    }
+
+    /// Copy `frames` frames from `src`, keeping only `channels`, fading in and out over
+    /// `fade_frames`. Slower than copying whole frames, so only used when picking channels.
+    fn copy_channels(
+        &self,
+        src: &mut impl Read,
+        dst: &mut impl Write,
+        frames: u64,
+        fade_frames: u64,
+        kind: Option<SampleKind>,
+        channels: &[usize],
+    ) -> anyhow::Result<()> {
+        const BLOCK_FRAMES: u64 = 4096;
+        let block_align = self.format.block_align as usize;
+        let bytes_per_sample = block_align / self.format.channels as usize;
+        let out_block_align = channels.len() * bytes_per_sample;
+
+        let mut buf = vec![0u8; BLOCK_FRAMES as usize * block_align];
+        let mut out = vec![];
+        let mut done = 0;
+        while done < frames {
+            let block = (frames - done).min(BLOCK_FRAMES);
+            let buf = &mut buf[..block as usize * block_align];
+            src.read_exact(buf)
+                .with_context(|| format!("unexpected end of data in {}", self.path.display()))?;
+            out.clear();
+            for frame in buf.chunks_exact(block_align) {
+                for &channel in channels {
+                    let start = channel * bytes_per_sample;
+                    out.extend_from_slice(&frame[start..start + bytes_per_sample]);
+                }
+            }
+            if fade_frames > 0 {
+                Self::scale_frames(&mut out, kind, out_block_align, bytes_per_sample, |i| {
+                    let i = done + i;
+                    if i < fade_frames {
+                        i as f64 / fade_frames as f64
+                    } else if i >= frames - fade_frames {
+                        (frames - 1 - i) as f64 / fade_frames as f64
+                    } else {
+                        1.0
+                    }
+                });
+            }
+            dst.write_all(&out)?;
+            done += block;
+        }
+        Ok(())
+    }
+
+    fn apply_gain(&self, buf: &mut [u8], kind: Option<SampleKind>, gain: impl Fn(u64) -> f64) {
+        let bytes_per_sample = self.format.block_align as usize / self.format.channels as usize;
+        Self::scale_frames(
+            buf,
+            kind,
+            self.format.block_align as usize,
+            bytes_per_sample,
+            gain,
+        );
+    }
+
+    fn scale_frames(
+        buf: &mut [u8],
+        kind: Option<SampleKind>,
+        block_align: usize,
+        bytes_per_sample: usize,
+        gain: impl Fn(u64) -> f64,
+    ) {
+        let Some(kind) = kind else {
+            return;
+        };
+        for (i, frame) in buf.chunks_exact_mut(block_align).enumerate() {
+            let gain = gain(i as u64);
+            for sample in frame.chunks_exact_mut(bytes_per_sample) {
+                kind.scale(sample, gain);
+            }
+        }
+    }
+}
+
+/// A `fmt ` chunk body for the same samples with a different number of channels. The speaker
+/// positions of extensible formats are dropped, since they no longer apply.
+fn fmt_with_channels(body: &[u8], channels: u16, block_align: u16) -> Vec<u8> {
+    let mut body = body.to_vec();
+    if body.len() < 16 {
+        return body;
+    }
+    let sample_rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+    body[2..4].copy_from_slice(&channels.to_le_bytes());
+    body[8..12].copy_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    body[12..14].copy_from_slice(&block_align.to_le_bytes());
+    let format_tag = u16::from_le_bytes([body[0], body[1]]);
+    if format_tag == WAVE_FORMAT_EXTENSIBLE && body.len() >= 24 {
+        body[20..24].copy_from_slice(&0u32.to_le_bytes());
+    }
+    body
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn write_test_wav(path: &Path, sample_rate: u32, samples: &[i16]) {
+        write_test_wav_channels(path, sample_rate, 1, samples);
+    }
+
+    /// A 16 bit WAV file with `channels` channels, interleaved in `samples`.
+    pub(crate) fn write_test_wav_channels(
+        path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        samples: &[i16],
+    ) {
+        let mut buf = vec![];
+        buf.extend_from_slice(b"RIFF");
+        buf.extend_from_slice(&(36 + samples.len() as u32 * 2).to_le_bytes());
+        buf.extend_from_slice(b"WAVEfmt ");
+        buf.extend_from_slice(&16u32.to_le_bytes());
+        buf.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        buf.extend_from_slice(&channels.to_le_bytes());
+        buf.extend_from_slice(&sample_rate.to_le_bytes());
+        buf.extend_from_slice(&(sample_rate * 2 * channels as u32).to_le_bytes());
+        buf.extend_from_slice(&(2 * channels).to_le_bytes());
+        buf.extend_from_slice(&16u16.to_le_bytes());
+        buf.extend_from_slice(b"data");
+        buf.extend_from_slice(&(samples.len() as u32 * 2).to_le_bytes());
+        for sample in samples {
+            buf.extend_from_slice(&sample.to_le_bytes());
+        }
+        std::fs::write(path, buf).unwrap();
+    }
+
+    /// A test WAV from a recorder, with a `bext` chunk starting at `time_reference` samples and
+    /// the test iXML.
+    pub(crate) fn write_test_bwf(
+        path: &Path,
+        sample_rate: u32,
+        samples: &[i16],
+        time_reference: u64,
+    ) {
+        let tmp = path.with_extension("tmp.wav");
+        write_test_wav(&tmp, sample_rate, samples);
+        let mut wav = WavFile::open(&tmp).unwrap();
+        wav.set_chunk(*b"bext", crate::bwf::tests::bext_body(time_reference));
+        wav.set_chunk(*b"iXML", crate::bwf::tests::IXML.as_bytes().to_vec());
+        let range = SampleRange {
+            start: 0,
+            end: wav.frames(),
+            sample_rate,
+        };
+        wav.write_slice(path, range, Duration::ZERO).unwrap();
+        std::fs::remove_file(tmp).unwrap();
+    }
+
+    fn read_samples(path: &Path) -> Vec<i16> {
+        let wav = WavFile::open(path).unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        bytes[wav.data_offset as usize..(wav.data_offset + wav.data_len) as usize]
+            .chunks_exact(2)
+            .map(|s| i16::from_le_bytes([s[0], s[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn slice_is_sample_accurate() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let src = dir.join("src.wav");
+        let dst = dir.join("dst.wav");
+        let samples: Vec<i16> = (0..100).collect();
+        write_test_wav(&src, 1000, &samples);
+
+        let wav = WavFile::open(&src).unwrap();
+        assert_eq!(wav.frames(), 100);
+        let range = SampleRange {
+            start: 10,
+            end: 60,
+            sample_rate: 1000,
+        };
+        let written = wav.write_slice(&dst, range, Duration::ZERO).unwrap();
+
+        assert_eq!(written, 50);
+        assert_eq!(read_samples(&dst), (10..60).collect::<Vec<i16>>());
+    }
+
+    #[test]
+    fn slice_keeps_bwf_metadata() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let src = dir.join("src.wav");
+        let dst = dir.join("dst.wav");
+        write_test_wav(&src, 1000, &[0; 100]);
+        let mut wav = WavFile::open(&src).unwrap();
+        wav.set_chunk(*b"bext", crate::bwf::tests::bext_body(5000));
+        wav.set_chunk(*b"iXML", crate::bwf::tests::IXML.as_bytes().to_vec());
+
+        let range = SampleRange {
+            start: 40,
+            end: 60,
+            sample_rate: 1000,
+        };
+        wav.write_slice(&dst, range, Duration::ZERO).unwrap();
+
+        let info = crate::bwf::BwfInfo::read(&WavFile::open(&dst).unwrap());
+        assert_eq!(info.bext.unwrap().time_reference, 5040);
+        assert_eq!(info.ixml.unwrap().scene.as_deref(), Some("1A"));
+        assert_eq!(read_samples(&dst), [0; 20]);
+    }
+
+    #[test]
+    fn metadata_is_copied_from_the_source() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let src = dir.join("src.wav");
+        let dst = dir.join("dst.wav");
+        write_test_bwf(&src, 1000, &[0; 100], 5000);
+        let mut source = WavFile::open(&src).unwrap();
+        source.set_info(&[(*b"INAM", "take".to_owned())]);
+        write_test_wav(&dst, 1000, &[0; 20]);
+
+        let mut wav = WavFile::open(&dst).unwrap();
+        wav.copy_metadata(&source, 40);
+        let info = crate::bwf::BwfInfo::read(&wav);
+        assert_eq!(info.bext.unwrap().time_reference, 5040);
+        assert_eq!(info.ixml.unwrap().scene.as_deref(), Some("1A"));
+        assert_eq!(wav.chunk(b"LIST"), source.chunk(b"LIST"));
+    }
+
+    #[test]
+    fn slice_with_fade() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let src = dir.join("src.wav");
+        let dst = dir.join("dst.wav");
+        write_test_wav(&src, 1000, &[1000; 100]);
+
+        let wav = WavFile::open(&src).unwrap();
+        let range = SampleRange {
+            start: 0,
+            end: 20,
+            sample_rate: 1000,
+        };
+        wav.write_slice(&dst, range, Duration::from_millis(5))
+            .unwrap();
+
+        let out = read_samples(&dst);
+        assert_eq!(
+            out,
+            [
+                0, 200, 400, 600, 800, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000, 1000,
+                800, 600, 400, 200, 0
+            ]
+        );
+    }
+
+    #[test]
+    fn slice_picked_channels() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let src = dir.join("src.wav");
+        let dst = dir.join("dst.wav");
+        // Three channels, each sample is its channel times 1000 plus its frame.
+        let samples: Vec<i16> = (0..20)
+            .flat_map(|frame| (0..3).map(move |channel| channel * 1000 + frame))
+            .collect();
+        write_test_wav_channels(&src, 1000, 3, &samples);
+
+        let wav = WavFile::open(&src).unwrap();
+        let range = SampleRange {
+            start: 5,
+            end: 8,
+            sample_rate: 1000,
+        };
+        wav.write_channels(&dst, range, Duration::ZERO, &[2, 0])
+            .unwrap();
+
+        let out = WavFile::open(&dst).unwrap();
+        assert_eq!(out.format.channels, 2);
+        assert_eq!(out.format.block_align, 4);
+        assert_eq!(out.frames(), 3);
+        assert_eq!(read_samples(&dst), [2005, 5, 2006, 6, 2007, 7]);
+        assert!(wav
+            .write_channels(&dst, range, Duration::ZERO, &[3])
+            .is_err());
+    }
+
+    #[test]
+    fn bad_formats_are_rejected() {
+        let tmp = crate::tests::temp_dir();
+        let dir = tmp.path();
+        let path = dir.join("bad.wav");
+        let write = |channels: u16, block_align: u16| {
+            write_test_wav(&path, 1000, &[0; 10]);
+            let mut bytes = std::fs::read(&path).unwrap();
+            bytes[22..24].copy_from_slice(&channels.to_le_bytes());
+            bytes[32..34].copy_from_slice(&block_align.to_le_bytes());
+            std::fs::write(&path, bytes).unwrap();
+        };
+
+        write(0, 2);
+        assert!(WavFile::open(&path).is_err());
+        write(2, 2);
+        assert!(WavFile::open(&path).is_err());
+        write(2, 4);
+        assert!(WavFile::open(&path).is_ok());
+    }
+}